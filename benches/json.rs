@@ -0,0 +1,88 @@
+//! serde_json against the serializer the build selected, on the bodies of
+//! the hot endpoints. Run with `cargo bench --features simd-json` to compare
+//! simd-json; without the feature both sides are serde_json.
+
+use chrono::Utc;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use rinha_servico_rust::json;
+use rinha_servico_rust::schema::{
+    Balance, CreateCustomerTransactionRequest, CreateCustomerTransactionResponse,
+    GetCustomerStatementResponse, StatementTransaction,
+};
+
+const REQUEST: &[u8] = br#"{"valor": 1000, "tipo": "d", "descricao": "descricao"}"#;
+
+fn statement() -> GetCustomerStatementResponse {
+    let now = Utc::now();
+    GetCustomerStatementResponse {
+        balance: Balance {
+            total: -9098,
+            limit: 100000,
+            date: now.naive_utc(),
+        },
+        last_transactions: (0..10)
+            .map(|i| StatementTransaction {
+                value: Some(1000 + i),
+                tx_type: Some(if i % 2 == 0 { "c" } else { "d" }.to_string()),
+                description: Some(format!("descr{}", i)),
+                date: Some(now),
+            })
+            .collect(),
+    }
+}
+
+fn created() -> CreateCustomerTransactionResponse {
+    CreateCustomerTransactionResponse {
+        id: Some(1_234_567_890_123),
+        uuid: None,
+        date: Some(Utc::now()),
+        limit: 100000,
+        total: -9098,
+    }
+}
+
+fn parse_request(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_request");
+    group.bench_function("serde_json", |b| {
+        b.iter(|| {
+            serde_json::from_slice::<CreateCustomerTransactionRequest>(black_box(REQUEST)).unwrap()
+        })
+    });
+    // simd-json parses in place, so each iteration gets its own copy, made
+    // outside the measurement.
+    group.bench_function("selected", |b| {
+        b.iter_batched(
+            || REQUEST.to_vec(),
+            |mut body| json::from_slice::<CreateCustomerTransactionRequest>(black_box(&mut body)).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn serialize_created(c: &mut Criterion) {
+    let response = created();
+    let mut group = c.benchmark_group("serialize_created");
+    group.bench_function("serde_json", |b| {
+        b.iter(|| serde_json::to_string(black_box(&response)).unwrap())
+    });
+    group.bench_function("selected", |b| {
+        b.iter(|| json::to_string(black_box(&response)).unwrap())
+    });
+    group.finish();
+}
+
+fn serialize_statement(c: &mut Criterion) {
+    let statement = statement();
+    let mut group = c.benchmark_group("serialize_statement");
+    group.bench_function("serde_json", |b| {
+        b.iter(|| serde_json::to_string(black_box(&statement)).unwrap())
+    });
+    group.bench_function("selected", |b| {
+        b.iter(|| json::to_string(black_box(&statement)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse_request, serialize_created, serialize_statement);
+criterion_main!(benches);
@@ -0,0 +1,24 @@
+CREATE TABLE IF NOT EXISTS customers (
+    id SERIAL PRIMARY KEY,
+    "limit" INTEGER NOT NULL,
+    balance INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS transactions (
+    id SERIAL PRIMARY KEY,
+    value INTEGER NOT NULL,
+    "type" VARCHAR(1) NOT NULL,
+    description VARCHAR(10) NOT NULL,
+    customer_id INTEGER NOT NULL REFERENCES customers (id),
+    created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS transactions_customer_id_created_at_idx
+    ON transactions (customer_id, created_at DESC);
+
+INSERT INTO customers (id, "limit")
+VALUES (1, 100000), (2, 80000), (3, 1000000), (4, 10000000), (5, 500000)
+ON CONFLICT (id) DO NOTHING;
+
+SELECT setval(pg_get_serial_sequence('customers', 'id'), (SELECT MAX(id) FROM customers));
@@ -0,0 +1,9 @@
+ALTER TABLE webhooks ADD COLUMN IF NOT EXISTS secret TEXT;
+
+-- Two UUIDs give 64 hex digits from the server's strong random source, the
+-- format of the secrets the service generates.
+UPDATE webhooks
+SET secret = replace(gen_random_uuid()::text, '-', '') || replace(gen_random_uuid()::text, '-', '')
+WHERE secret IS NULL;
+
+ALTER TABLE webhooks ALTER COLUMN secret SET NOT NULL;
@@ -0,0 +1 @@
+ALTER TABLE transactions ALTER COLUMN description TYPE TEXT;
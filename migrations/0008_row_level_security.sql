@@ -0,0 +1,27 @@
+-- Per-customer isolation for DB_ROW_LEVEL_SECURITY mode. The app sets
+-- `app.current_customer_id` on every checkout; when it is unset or empty, as
+-- for background work and admin routes, every row stays visible. FORCE makes
+-- the policies apply to the table owner the app connects as.
+ALTER TABLE customers ENABLE ROW LEVEL SECURITY;
+ALTER TABLE customers FORCE ROW LEVEL SECURITY;
+CREATE POLICY customer_isolation ON customers
+    USING (
+        (SELECT NULLIF(current_setting('app.current_customer_id', true), '')) IS NULL
+        OR id = (SELECT NULLIF(current_setting('app.current_customer_id', true), '')::INTEGER)
+    );
+
+ALTER TABLE transactions ENABLE ROW LEVEL SECURITY;
+ALTER TABLE transactions FORCE ROW LEVEL SECURITY;
+CREATE POLICY customer_isolation ON transactions
+    USING (
+        (SELECT NULLIF(current_setting('app.current_customer_id', true), '')) IS NULL
+        OR customer_id = (SELECT NULLIF(current_setting('app.current_customer_id', true), '')::INTEGER)
+    );
+
+ALTER TABLE webhooks ENABLE ROW LEVEL SECURITY;
+ALTER TABLE webhooks FORCE ROW LEVEL SECURITY;
+CREATE POLICY customer_isolation ON webhooks
+    USING (
+        (SELECT NULLIF(current_setting('app.current_customer_id', true), '')) IS NULL
+        OR customer_id = (SELECT NULLIF(current_setting('app.current_customer_id', true), '')::INTEGER)
+    );
@@ -0,0 +1,5 @@
+-- External identifier for transactions, generated by the app for new rows.
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS uuid UUID;
+UPDATE transactions SET uuid = gen_random_uuid() WHERE uuid IS NULL;
+ALTER TABLE transactions ALTER COLUMN uuid SET NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS transactions_uuid_idx ON transactions (uuid);
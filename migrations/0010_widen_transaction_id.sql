@@ -0,0 +1,3 @@
+-- Transaction ids may be generated by the app, as 64-bit time-ordered ids.
+ALTER TABLE transactions ALTER COLUMN id TYPE BIGINT;
+ALTER SEQUENCE transactions_id_seq AS BIGINT;
@@ -0,0 +1,3 @@
+-- Creation times are set by the app, in UTC with microsecond precision.
+ALTER TABLE transactions
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ(6) USING created_at AT TIME ZONE 'UTC';
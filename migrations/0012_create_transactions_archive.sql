@@ -0,0 +1,23 @@
+-- Transactions past the retention period, moved out of the hot table by the
+-- archival job. Same columns as `transactions`, plus when they were moved.
+CREATE TABLE IF NOT EXISTS transactions_archive (
+    id BIGINT PRIMARY KEY,
+    value INTEGER NOT NULL,
+    "type" VARCHAR(1) NOT NULL,
+    description TEXT NOT NULL,
+    customer_id INTEGER NOT NULL REFERENCES customers (id),
+    created_at TIMESTAMPTZ(6) NOT NULL,
+    uuid UUID NOT NULL UNIQUE,
+    archived_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS transactions_archive_customer_id_created_at_idx
+    ON transactions_archive (customer_id, created_at DESC);
+
+ALTER TABLE transactions_archive ENABLE ROW LEVEL SECURITY;
+ALTER TABLE transactions_archive FORCE ROW LEVEL SECURITY;
+CREATE POLICY customer_isolation ON transactions_archive
+    USING (
+        (SELECT NULLIF(current_setting('app.current_customer_id', true), '')) IS NULL
+        OR customer_id = (SELECT NULLIF(current_setting('app.current_customer_id', true), '')::INTEGER)
+    );
@@ -0,0 +1,7 @@
+-- Trigram index for substring searches on descriptions. The extension can
+-- only exist once per database, so it goes to `public` whatever schema this
+-- runs in, and its operator class is named with the schema.
+CREATE EXTENSION IF NOT EXISTS pg_trgm WITH SCHEMA public;
+
+CREATE INDEX IF NOT EXISTS transactions_description_trgm_idx
+    ON transactions USING gin (description public.gin_trgm_ops);
@@ -0,0 +1,19 @@
+-- Every customer's balance at the end of each UTC day, written by the daily
+-- close once the day is over.
+CREATE TABLE IF NOT EXISTS daily_balances (
+    customer_id INTEGER NOT NULL REFERENCES customers (id),
+    day DATE NOT NULL,
+    balance INTEGER NOT NULL,
+    closed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (customer_id, day)
+);
+
+CREATE INDEX IF NOT EXISTS daily_balances_day_idx ON daily_balances (day);
+
+ALTER TABLE daily_balances ENABLE ROW LEVEL SECURITY;
+ALTER TABLE daily_balances FORCE ROW LEVEL SECURITY;
+CREATE POLICY customer_isolation ON daily_balances
+    USING (
+        (SELECT NULLIF(current_setting('app.current_customer_id', true), '')) IS NULL
+        OR customer_id = (SELECT NULLIF(current_setting('app.current_customer_id', true), '')::INTEGER)
+    );
@@ -0,0 +1,17 @@
+-- Creation time of each customer's latest transaction. New transactions are
+-- stamped from it while the customer row is locked, so their order always
+-- matches the order their balance changes were applied in, whichever
+-- instance wrote them.
+ALTER TABLE customers ADD COLUMN IF NOT EXISTS last_transaction_at TIMESTAMPTZ(6);
+
+UPDATE customers c SET last_transaction_at = t.created_at
+FROM (
+    SELECT customer_id, MAX(created_at) AS created_at
+    FROM (
+        SELECT customer_id, created_at FROM transactions
+        UNION ALL
+        SELECT customer_id, created_at FROM transactions_archive
+    ) all_transactions
+    GROUP BY customer_id
+) t
+WHERE c.id = t.customer_id;
@@ -0,0 +1,2 @@
+-- Recurring jobs are scheduled from when the last one of their kind finished.
+CREATE INDEX IF NOT EXISTS jobs_kind_status_updated_at_idx ON jobs (kind, status, updated_at);
@@ -0,0 +1,152 @@
+use std::str::FromStr;
+use std::time::Instant;
+
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage};
+use chrono::Local;
+use serde_json::json;
+
+use crate::request_id::RequestId;
+use crate::{context, server};
+
+/// Layout used for each access log line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessLogFormat {
+    /// Human-readable line, close to actix's default logger.
+    Default,
+    /// One JSON object per request.
+    Json,
+    /// Common Log Format, followed by the extra timing fields.
+    Common,
+}
+
+impl FromStr for AccessLogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "default" => Ok(AccessLogFormat::Default),
+            "json" => Ok(AccessLogFormat::Json),
+            "common" | "clf" => Ok(AccessLogFormat::Common),
+            other => Err(format!("unknown access log format: {}", other)),
+        }
+    }
+}
+
+struct AccessLogEntry {
+    remote_addr: String,
+    method: String,
+    path: String,
+    version: String,
+    status: u16,
+    size: u64,
+    latency_us: u128,
+    pool_wait_us: u128,
+    request_id: String,
+}
+
+pub async fn log_access(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let format = req
+        .app_data::<web::Data<server::MyData>>()
+        .map(|data| data.access_log_format)
+        .unwrap_or(AccessLogFormat::Default);
+
+    let started = Instant::now();
+    let remote_addr = req
+        .connection_info()
+        .realip_remote_addr()
+        .unwrap_or("-")
+        .to_string();
+    let method = req.method().to_string();
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.to_string())
+        .unwrap_or_else(|| req.path().to_string());
+    let version = format!("{:?}", req.version());
+
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .map(RequestId::to_string)
+        .unwrap_or_else(|| "-".to_string());
+
+    let (res, pool_wait) = context::scope(next.call(req)).await;
+
+    // Errors, the 500s of caught panics among them, only become responses
+    // further out, so their status comes from the error itself.
+    let (status, size) = match &res {
+        Ok(res) => {
+            let size = match res.response().body().size() {
+                BodySize::Sized(size) => size,
+                _ => 0,
+            };
+            (res.status(), size)
+        }
+        Err(err) => (err.as_response_error().status_code(), 0),
+    };
+
+    let entry = AccessLogEntry {
+        remote_addr,
+        method,
+        path,
+        version,
+        status: status.as_u16(),
+        size,
+        latency_us: started.elapsed().as_micros(),
+        pool_wait_us: pool_wait.as_micros(),
+        request_id,
+    };
+    log::info!(target: "access", "{}", entry.render(format));
+
+    res
+}
+
+impl AccessLogEntry {
+    fn render(&self, format: AccessLogFormat) -> String {
+        match format {
+            AccessLogFormat::Default => format!(
+                "{} \"{} {} {}\" {} {} {}us pool_wait={}us request_id={}",
+                self.remote_addr,
+                self.method,
+                self.path,
+                self.version,
+                self.status,
+                self.size,
+                self.latency_us,
+                self.pool_wait_us,
+                self.request_id,
+            ),
+            AccessLogFormat::Json => json!({
+                "remote_addr": self.remote_addr,
+                "method": self.method,
+                "path": self.path,
+                "version": self.version,
+                "status": self.status,
+                "size": self.size,
+                "latency_us": self.latency_us as u64,
+                "pool_wait_us": self.pool_wait_us as u64,
+                "request_id": self.request_id,
+            })
+            .to_string(),
+            AccessLogFormat::Common => format!(
+                "{} - - [{}] \"{} {} {}\" {} {} {} {} {}",
+                self.remote_addr,
+                Local::now().format("%d/%b/%Y:%H:%M:%S %z"),
+                self.method,
+                self.path,
+                self.version,
+                self.status,
+                self.size,
+                self.latency_us,
+                self.pool_wait_us,
+                self.request_id,
+            ),
+        }
+    }
+}
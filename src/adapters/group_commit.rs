@@ -0,0 +1,202 @@
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+
+use crate::domain::{CreatedTransaction, NewTransaction};
+use crate::ports::TransactionPort;
+use crate::{config, db, deadline, errors, ids, metrics, rls, tenant};
+
+const QUEUE_SIZE: usize = 4096;
+
+struct Job {
+    new_tx: NewTransaction,
+    balance_delta: i64,
+    tenant: Option<tenant::Schema>,
+    customer: Option<i32>,
+    deadline: Option<Instant>,
+    reply: oneshot::Sender<Result<CreatedTransaction, errors::AppError>>,
+}
+
+/// Collects the transactions arriving within `WriteConfig::group_commit_window_us`
+/// of the first one, up to `group_commit_max_size`, and commits them together
+/// with `db::create_transactions_group_db`. Every caller waits for its group's
+/// commit, so writes take at least the window in exchange for one commit per
+/// group instead of one per transaction. Groups run at `WriteConfig::isolation` and are retried on
+/// conflicts as a whole, so a retry waits for every transaction of the group.
+pub struct GroupCommitWriter {
+    jobs: mpsc::Sender<Job>,
+}
+
+/// What every group is committed with.
+struct Committer {
+    pool: sqlx::Pool<sqlx::Postgres>,
+    side_effects: db::SideEffects,
+    keys: Arc<ids::TransactionKeys>,
+    policy: config::WriteConfig,
+    metrics: Arc<metrics::Metrics>,
+}
+
+impl GroupCommitWriter {
+    pub fn new(
+        pool: sqlx::Pool<sqlx::Postgres>,
+        side_effects: db::SideEffects,
+        keys: Arc<ids::TransactionKeys>,
+        policy: config::WriteConfig,
+        metrics: Arc<metrics::Metrics>,
+    ) -> GroupCommitWriter {
+        let window = Duration::from_micros(policy.group_commit_window_us);
+        let max_size = policy.group_commit_max_size.max(1);
+        let committer = Arc::new(Committer {
+            pool,
+            side_effects,
+            keys,
+            policy,
+            metrics,
+        });
+        let (jobs, receiver) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(collect(committer, window, max_size, receiver));
+        GroupCommitWriter { jobs }
+    }
+}
+
+async fn collect(
+    committer: Arc<Committer>,
+    window: Duration,
+    max_size: usize,
+    mut receiver: mpsc::Receiver<Job>,
+) {
+    while let Some(first) = receiver.recv().await {
+        let deadline = Instant::now() + window;
+        let mut jobs = vec![first];
+        while jobs.len() < max_size {
+            match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(job)) => jobs.push(job),
+                _ => break,
+            }
+        }
+
+        // Connections are set up per tenant and principal on checkout, so
+        // each combination commits separately.
+        let mut groups: Vec<Vec<Job>> = Vec::new();
+        for job in jobs {
+            match groups
+                .iter_mut()
+                .find(|group| group[0].tenant == job.tenant && group[0].customer == job.customer)
+            {
+                Some(group) => group.push(job),
+                None => groups.push(vec![job]),
+            }
+        }
+
+        // Committing in the background lets the next group fill up meanwhile.
+        for group in groups {
+            tokio::spawn(commit(committer.clone(), group));
+        }
+    }
+}
+
+async fn commit(committer: Arc<Committer>, jobs: Vec<Job>) {
+    let (tenant, customer) = (jobs[0].tenant.clone(), jobs[0].customer);
+    // The group is given up only once none of its callers waits anymore.
+    let deadline = jobs
+        .iter()
+        .map(|job| job.deadline)
+        .reduce(|a, b| a.zip(b).map(|(a, b)| a.max(b)))
+        .flatten();
+    let (txs, replies): (Vec<_>, Vec<_>) = jobs
+        .into_iter()
+        .map(|job| ((job.new_tx, job.balance_delta), job.reply))
+        .unzip();
+
+    let write = db::create_transactions_group_db(
+        &committer.pool,
+        &txs,
+        committer.side_effects,
+        &committer.keys,
+        &committer.policy,
+        &committer.metrics,
+    );
+    let write = async {
+        match deadline {
+            Some(deadline) => deadline::scope(deadline, write).await,
+            None => write.await,
+        }
+    };
+    let write = async {
+        match customer {
+            Some(customer) => rls::scope(customer, write).await,
+            None => write.await,
+        }
+    };
+    let result = match tenant {
+        Some(schema) => tenant::scope(schema, write).await,
+        None => write.await,
+    };
+
+    match result {
+        Ok(outcomes) => {
+            for (outcome, reply) in outcomes.into_iter().zip(replies) {
+                let created = outcome.map(|(limit, balance, transaction)| CreatedTransaction {
+                    limit,
+                    balance,
+                    transaction,
+                });
+                let _ = reply.send(created);
+            }
+        }
+        Err(err) => {
+            log::error!("group commit of {} transactions failed: {}", replies.len(), err);
+            for reply in replies {
+                let _ = reply.send(Err(shared_error(&err)));
+            }
+        }
+    }
+}
+
+/// A copy of a group's failure for each of its callers. Keeps what the
+/// breaker and the pool exhaustion and deadline responses look at; the rest is reduced to
+/// the message.
+fn shared_error(err: &errors::AppError) -> errors::AppError {
+    match err {
+        errors::AppError::ErrPoolExhausted => errors::AppError::ErrPoolExhausted,
+        errors::AppError::ErrDeadlineExceeded => errors::AppError::ErrDeadlineExceeded,
+        errors::AppError::SQLError(sqlx::Error::Io(io_err)) => errors::AppError::SQLError(
+            sqlx::Error::Io(io::Error::new(io_err.kind(), io_err.to_string())),
+        ),
+        errors::AppError::SQLError(sqlx::Error::PoolClosed) => {
+            errors::AppError::SQLError(sqlx::Error::PoolClosed)
+        }
+        errors::AppError::SQLError(err) => {
+            errors::AppError::SQLError(sqlx::Error::Protocol(err.to_string()))
+        }
+        err => errors::AppError::SQLError(sqlx::Error::Protocol(err.to_string())),
+    }
+}
+
+#[async_trait]
+impl TransactionPort for GroupCommitWriter {
+    async fn create(
+        &self,
+        new_tx: NewTransaction,
+        balance_delta: i64,
+    ) -> Result<CreatedTransaction, errors::AppError> {
+        let (reply, result) = oneshot::channel();
+        let job = Job {
+            new_tx,
+            balance_delta,
+            tenant: tenant::current(),
+            customer: rls::current(),
+            deadline: deadline::current(),
+            reply,
+        };
+
+        // Only fails if the collector panicked.
+        let crashed = || errors::AppError::SQLError(sqlx::Error::WorkerCrashed);
+        self.jobs.send(job).await.map_err(|_| crashed())?;
+        result.await.map_err(|_| crashed())?
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::domain::{self, CreatedTransaction, Customer, NewTransaction, Statement, Transaction};
+use crate::errors;
+use crate::ports::{StatementPort, TransactionPort};
+
+/// Same customers the initial migration seeds: (id, limit).
+const SEED_CUSTOMERS: [(i32, i32); 5] = [
+    (1, 100000),
+    (2, 80000),
+    (3, 1000000),
+    (4, 10000000),
+    (5, 500000),
+];
+
+const STATEMENT_TRANSACTIONS: usize = 10;
+
+struct Account {
+    customer: Customer,
+    /// Oldest first.
+    transactions: Vec<Transaction>,
+}
+
+struct State {
+    accounts: HashMap<i32, Account>,
+    next_transaction_id: i64,
+}
+
+/// Keeps everything in process memory; nothing survives a restart and
+/// instances don't share state.
+pub struct MemoryAdapter {
+    state: Mutex<State>,
+}
+
+impl MemoryAdapter {
+    pub fn new() -> MemoryAdapter {
+        let now = Utc::now().naive_utc();
+        let accounts = SEED_CUSTOMERS
+            .iter()
+            .map(|&(id, limit)| {
+                let customer = Customer {
+                    id,
+                    limit,
+                    balance: 0,
+                    created_at: now,
+                };
+                let account = Account {
+                    customer,
+                    transactions: vec![],
+                };
+                (id, account)
+            })
+            .collect();
+
+        MemoryAdapter {
+            state: Mutex::new(State {
+                accounts,
+                next_transaction_id: 1,
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl StatementPort for MemoryAdapter {
+    async fn statement(&self, customer_id: i64) -> Result<Statement, errors::AppError> {
+        let state = self.state.lock().unwrap();
+        let account = i32::try_from(customer_id)
+            .ok()
+            .and_then(|id| state.accounts.get(&id))
+            .ok_or(errors::AppError::ErrCustomerNotFound)?;
+
+        let transactions = account
+            .transactions
+            .iter()
+            .rev()
+            .take(STATEMENT_TRANSACTIONS)
+            .cloned()
+            .collect();
+
+        Ok(Statement {
+            customer: account.customer.clone(),
+            transactions,
+            taken_at: Utc::now().naive_utc(),
+        })
+    }
+}
+
+#[async_trait]
+impl TransactionPort for MemoryAdapter {
+    async fn create(
+        &self,
+        new_tx: NewTransaction,
+        balance_delta: i64,
+    ) -> Result<CreatedTransaction, errors::AppError> {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_transaction_id;
+        let account = state
+            .accounts
+            .get_mut(&new_tx.customer_id)
+            .ok_or(errors::AppError::ErrCustomerNotFound)?;
+
+        let limit = account.customer.limit as i64;
+        let balance = account.customer.balance as i64;
+        if !domain::within_limit(balance, limit, balance_delta) {
+            return Err(errors::AppError::ErrNegativeTransactionBalance);
+        }
+        let balance = balance + balance_delta;
+        account.customer.balance = balance as i32;
+
+        let transaction = Transaction {
+            id: Some(id),
+            value: Some(new_tx.value),
+            tx_type: Some(new_tx.tx_type),
+            description: Some(new_tx.description),
+            customer_id: Some(new_tx.customer_id),
+            created_at: Some(Utc::now()),
+            uuid: Some(Uuid::new_v4()),
+        };
+        account.transactions.push(transaction.clone());
+        state.next_transaction_id += 1;
+
+        Ok(CreatedTransaction {
+            limit,
+            balance,
+            transaction,
+        })
+    }
+}
@@ -0,0 +1,33 @@
+//! Implementations of the ports in `ports.rs`. The HTTP side of the
+//! application is `server.rs`, which only talks to the service layer.
+
+use std::str::FromStr;
+
+pub mod group_commit;
+pub mod memory;
+pub mod postgres;
+pub mod read_model;
+pub mod redis;
+pub mod sharded;
+
+/// Where customers and transactions are stored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageBackend {
+    Postgres,
+    /// Process-local storage seeded with the rinha customers. Only the
+    /// statement and transaction endpoints use it; everything else still
+    /// goes to Postgres.
+    Memory,
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "postgres" => Ok(StorageBackend::Postgres),
+            "memory" => Ok(StorageBackend::Memory),
+            other => Err(format!("unknown storage backend: {}", other)),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::domain::{CreatedTransaction, Customer, NewTransaction, Statement, Transaction};
+use crate::ports::{StatementPort, TransactionPort};
+use crate::{config, db, errors, ids, metrics};
+
+pub struct PostgresAdapter {
+    pool: sqlx::Pool<sqlx::Postgres>,
+    /// Pool statements are routed to.
+    statement_pool: sqlx::Pool<sqlx::Postgres>,
+    side_effects: db::SideEffects,
+    write: config::WriteConfig,
+    metrics: Arc<metrics::Metrics>,
+    keys: Arc<ids::TransactionKeys>,
+}
+
+impl PostgresAdapter {
+    pub fn new(
+        pool: sqlx::Pool<sqlx::Postgres>,
+        statement_pool: sqlx::Pool<sqlx::Postgres>,
+        side_effects: db::SideEffects,
+        write: config::WriteConfig,
+        metrics: Arc<metrics::Metrics>,
+        keys: Arc<ids::TransactionKeys>,
+    ) -> PostgresAdapter {
+        PostgresAdapter {
+            pool,
+            statement_pool,
+            side_effects,
+            write,
+            metrics,
+            keys,
+        }
+    }
+}
+
+#[async_trait]
+impl StatementPort for PostgresAdapter {
+    async fn statement(&self, customer_id: i64) -> Result<Statement, errors::AppError> {
+        let rows = db::get_statement_db(self.statement_pool.to_owned(), customer_id).await?;
+
+        let first = rows.first().ok_or(errors::AppError::ErrCustomerNotFound)?;
+        let customer = Customer::from(first);
+        let taken_at = first.statement_date;
+
+        // Customers without transactions still get one row from the LEFT
+        // JOIN, with every transaction column null.
+        let transactions = if first.transaction_id.is_some() {
+            rows.into_iter().map(Transaction::from).collect()
+        } else {
+            vec![]
+        };
+
+        Ok(Statement {
+            customer,
+            transactions,
+            taken_at,
+        })
+    }
+}
+
+#[async_trait]
+impl TransactionPort for PostgresAdapter {
+    async fn create(
+        &self,
+        new_tx: NewTransaction,
+        balance_delta: i64,
+    ) -> Result<CreatedTransaction, errors::AppError> {
+        let (limit, balance, transaction) = db::create_customer_transaction_db(
+            self.pool.to_owned(),
+            new_tx,
+            balance_delta,
+            self.side_effects,
+            &self.keys,
+            &self.write,
+            &self.metrics,
+        )
+        .await?;
+
+        Ok(CreatedTransaction {
+            limit,
+            balance,
+            transaction,
+        })
+    }
+}
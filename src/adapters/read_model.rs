@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use chrono::Utc;
+
+use crate::db::GetCustomerStatementResult;
+use crate::domain::{Customer, Statement, Transaction};
+use crate::ports::StatementPort;
+use crate::{errors, tenant};
+
+const STATEMENT_TRANSACTIONS: usize = 10;
+
+/// A change to one of the tables the read model mirrors, as decoded from the
+/// replication slot.
+pub enum Change {
+    UpsertCustomer(Customer),
+    InsertTransaction(Transaction),
+    /// Rows were deleted or truncated, or transactions were rewritten. The
+    /// latest transactions of a customer can't be rebuilt from what is left,
+    /// so the model is reloaded.
+    Removed,
+}
+
+#[derive(Default)]
+struct State {
+    customers: HashMap<i32, Customer>,
+    /// Latest transactions per customer, newest first.
+    transactions: HashMap<i32, Vec<Transaction>>,
+}
+
+/// In-memory copy of every customer and their latest transactions, kept
+/// current by `replication::spawn`. Reads only use it once it has caught up
+/// with the replication slot.
+#[derive(Default)]
+pub struct ReadModel {
+    state: RwLock<State>,
+    ready: AtomicBool,
+}
+
+impl ReadModel {
+    pub fn new() -> ReadModel {
+        ReadModel::default()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::Release);
+    }
+
+    /// Replaces the whole model with a snapshot made of `STATEMENT_QUERY`
+    /// style rows.
+    pub fn load(&self, rows: Vec<GetCustomerStatementResult>) {
+        let mut state = State::default();
+        for row in rows {
+            let customer = Customer::from(&row);
+            let transactions = state.transactions.entry(customer.id).or_default();
+            state.customers.insert(customer.id, customer);
+            if row.transaction_id.is_some() {
+                transactions.push(Transaction::from(row));
+            }
+        }
+        *self.state.write().unwrap() = state;
+    }
+
+    /// Applies the changes of one committed database transaction at once, so
+    /// readers never see a balance without the transaction that produced it.
+    /// Returns whether the model has to be reloaded.
+    pub fn apply(&self, changes: Vec<Change>) -> bool {
+        let mut state = self.state.write().unwrap();
+        let mut reload = false;
+        for change in changes {
+            match change {
+                Change::UpsertCustomer(customer) => {
+                    state.customers.insert(customer.id, customer);
+                }
+                Change::InsertTransaction(tx) => {
+                    let Some(customer_id) = tx.customer_id else { continue };
+                    let transactions = state.transactions.entry(customer_id).or_default();
+                    // The snapshot and the slot overlap right after a load.
+                    if transactions.iter().any(|existing| existing.id == tx.id) {
+                        continue;
+                    }
+                    let position = transactions
+                        .iter()
+                        .position(|existing| (existing.created_at, existing.id) < (tx.created_at, tx.id))
+                        .unwrap_or(transactions.len());
+                    transactions.insert(position, tx);
+                    transactions.truncate(STATEMENT_TRANSACTIONS);
+                }
+                Change::Removed => reload = true,
+            }
+        }
+        reload
+    }
+
+    fn statement(&self, customer_id: i64) -> Result<Statement, errors::AppError> {
+        let state = self.state.read().unwrap();
+        let customer = i32::try_from(customer_id)
+            .ok()
+            .and_then(|id| state.customers.get(&id))
+            .ok_or(errors::AppError::ErrCustomerNotFound)?;
+
+        Ok(Statement {
+            customer: customer.clone(),
+            transactions: state
+                .transactions
+                .get(&customer.id)
+                .cloned()
+                .unwrap_or_default(),
+            taken_at: Utc::now().naive_utc(),
+        })
+    }
+}
+
+/// Serves statements from the read model, and from `fallback` while it is
+/// catching up or for tenants other than the default one, which it doesn't
+/// mirror.
+pub struct ReadModelAdapter {
+    model: Arc<ReadModel>,
+    fallback: Arc<dyn StatementPort>,
+}
+
+impl ReadModelAdapter {
+    pub fn new(model: Arc<ReadModel>, fallback: Arc<dyn StatementPort>) -> ReadModelAdapter {
+        ReadModelAdapter { model, fallback }
+    }
+}
+
+#[async_trait]
+impl StatementPort for ReadModelAdapter {
+    async fn statement(&self, customer_id: i64) -> Result<Statement, errors::AppError> {
+        if self.model.is_ready() && tenant::current().is_none() {
+            return self.model.statement(customer_id);
+        }
+        self.fallback.statement(customer_id).await
+    }
+}
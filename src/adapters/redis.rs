@@ -0,0 +1,332 @@
+//! Latest transactions of every customer kept in Redis lists, so statements
+//! only read the customer's row from Postgres instead of joining it with
+//! their transactions. Every instance pushes the transactions it commits to
+//! the list, newest first; a missing list is rebuilt from Postgres on the
+//! next statement.
+//!
+//! A list is only created by a statement read, and only if no transaction
+//! was committed between the start of that read and the list's creation:
+//! writers that find the list missing bump the customer's generation counter
+//! instead, and the read only creates the list if the generation is still
+//! the one it saw before going to Postgres. Transactions committed before a
+//! read but pushed after it rebuilt the list show up twice, so lists keep
+//! more entries than a statement needs and reads drop the duplicates.
+//!
+//! The balance still comes from Postgres, so it can be a transaction ahead
+//! of the list while a write is between its commit and its push.
+
+use std::sync::Arc;
+
+use crate::ports::{StatementPort, TransactionPort};
+use crate::{config, errors, tenant};
+
+/// Puts Redis in front of `statements` and after `transactions`. Without the
+/// `redis` feature, this fails.
+#[cfg(feature = "redis")]
+pub async fn wrap(
+    cfg: &config::RedisConfig,
+    schema: tenant::Schema,
+    pool: sqlx::Pool<sqlx::Postgres>,
+    statements: Arc<dyn StatementPort>,
+    transactions: Arc<dyn TransactionPort>,
+) -> Result<(Arc<dyn StatementPort>, Arc<dyn TransactionPort>), errors::CustomError> {
+    let url = cfg.url.as_ref().map(|url| url.0.as_str()).unwrap_or_default();
+    let client = ::redis::Client::open(url)
+        .map_err(|err| errors::CustomError::StandardError(Box::new(err)))?;
+    let conn = ::redis::aio::ConnectionManager::new(client)
+        .await
+        .map_err(|err| errors::CustomError::StandardError(Box::new(err)))?;
+
+    let lists = Arc::new(enabled::Lists::new(conn, cfg.key_prefix.clone(), schema));
+    Ok((
+        Arc::new(enabled::RedisStatements {
+            lists: lists.clone(),
+            pool,
+            fallback: statements,
+        }),
+        Arc::new(enabled::RedisTransactions {
+            lists,
+            inner: transactions,
+        }),
+    ))
+}
+
+#[cfg(not(feature = "redis"))]
+pub async fn wrap(
+    _cfg: &config::RedisConfig,
+    _schema: tenant::Schema,
+    _pool: sqlx::Pool<sqlx::Postgres>,
+    _statements: Arc<dyn StatementPort>,
+    _transactions: Arc<dyn TransactionPort>,
+) -> Result<(Arc<dyn StatementPort>, Arc<dyn TransactionPort>), errors::CustomError> {
+    Err(errors::CustomError::StringError(
+        "cannot use Redis: built without the `redis` feature".to_string(),
+    ))
+}
+
+#[cfg(feature = "redis")]
+mod enabled {
+    use std::collections::HashSet;
+    use std::cmp::Reverse;
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use chrono::{DateTime, Utc};
+    use ::redis::aio::ConnectionManager;
+    use ::redis::{AsyncCommands, RedisResult, Script};
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::domain::{CreatedTransaction, NewTransaction, Statement, Transaction};
+    use crate::db;
+
+    const STATEMENT_TRANSACTIONS: usize = 10;
+    /// Entries kept per list, with room for transactions pushed twice.
+    const LIST_LEN: usize = 2 * STATEMENT_TRANSACTIONS;
+    /// Last entry of every list built from Postgres, so customers without
+    /// transactions still have a list.
+    const END: &str = "-";
+
+    /// Pushes to an existing list, or bumps the generation when there is
+    /// none. KEYS: list, generation. ARGV: entry, list length.
+    const PUSH: &str = "
+        if redis.call('EXISTS', KEYS[1]) == 1 then
+            redis.call('LPUSH', KEYS[1], ARGV[1])
+            redis.call('LTRIM', KEYS[1], 0, tonumber(ARGV[2]) - 1)
+        else
+            redis.call('INCR', KEYS[2])
+        end
+        return 0
+    ";
+
+    /// Creates the list unless it exists or the generation moved on. KEYS:
+    /// list, generation. ARGV: expected generation, then the entries.
+    const BUILD: &str = "
+        if redis.call('EXISTS', KEYS[1]) == 0 and (redis.call('GET', KEYS[2]) or '') == ARGV[1] then
+            redis.call('RPUSH', KEYS[1], unpack(ARGV, 2))
+        end
+        return 0
+    ";
+
+    /// A transaction as stored in a list.
+    #[derive(Serialize, Deserialize)]
+    struct Entry {
+        id: Option<i64>,
+        #[serde(rename = "valor")]
+        value: Option<i32>,
+        #[serde(rename = "tipo")]
+        tx_type: Option<String>,
+        #[serde(rename = "descricao")]
+        description: Option<String>,
+        #[serde(rename = "realizada_em")]
+        created_at: Option<DateTime<Utc>>,
+    }
+
+    impl From<&Transaction> for Entry {
+        fn from(tx: &Transaction) -> Entry {
+            Entry {
+                id: tx.id,
+                value: tx.value,
+                tx_type: tx.tx_type.clone(),
+                description: tx.description.clone(),
+                created_at: tx.created_at,
+            }
+        }
+    }
+
+    pub struct Lists {
+        conn: ConnectionManager,
+        prefix: String,
+        /// Schema of requests without a tenant.
+        schema: tenant::Schema,
+        push: Script,
+        build: Script,
+    }
+
+    impl Lists {
+        pub fn new(conn: ConnectionManager, prefix: String, schema: tenant::Schema) -> Lists {
+            Lists {
+                conn,
+                prefix,
+                schema,
+                push: Script::new(PUSH),
+                build: Script::new(BUILD),
+            }
+        }
+
+        /// Start of the list keys of the current tenant.
+        fn list_prefix(&self) -> String {
+            let schema = tenant::current().unwrap_or_else(|| self.schema.clone());
+            format!("{}:{}:ultimas:", self.prefix, schema.0)
+        }
+
+        /// List and generation keys of a customer of the current tenant.
+        fn keys(&self, customer_id: i64) -> (String, String) {
+            let list = format!("{}{}", self.list_prefix(), customer_id);
+            let generation = format!("{}:geracao", list);
+            (list, generation)
+        }
+
+        /// The customer's latest transactions, newest first, or the
+        /// generation to build the list with when there is none.
+        async fn latest(&self, customer_id: i64) -> RedisResult<Result<Vec<Transaction>, String>> {
+            let (list, generation) = self.keys(customer_id);
+            let mut conn = self.conn.clone();
+            let entries: Vec<String> = conn.lrange(&list, 0, -1).await?;
+            if entries.is_empty() {
+                let current: Option<String> = conn.get(&generation).await?;
+                return Ok(Err(current.unwrap_or_default()));
+            }
+
+            let mut seen = HashSet::new();
+            let mut txs: Vec<Transaction> = entries
+                .iter()
+                .filter(|entry| *entry != END)
+                .filter_map(|entry| serde_json::from_str::<Entry>(entry).ok())
+                .filter(|entry| seen.insert(entry.id))
+                .map(|entry| Transaction {
+                    id: entry.id,
+                    value: entry.value,
+                    tx_type: entry.tx_type,
+                    description: entry.description,
+                    customer_id: i32::try_from(customer_id).ok(),
+                    created_at: entry.created_at,
+                    uuid: None,
+                })
+                .collect();
+            txs.sort_by_key(|tx| Reverse((tx.created_at, tx.id)));
+            txs.truncate(STATEMENT_TRANSACTIONS);
+            Ok(Ok(txs))
+        }
+
+        async fn build(&self, customer_id: i64, generation: &str, txs: &[Transaction]) -> RedisResult<()> {
+            let (list, generation_key) = self.keys(customer_id);
+            let mut invocation = self.build.key(&list);
+            invocation.key(&generation_key).arg(generation);
+            for tx in txs {
+                invocation.arg(serde_json::to_string(&Entry::from(tx)).unwrap_or_default());
+            }
+            invocation.arg(END);
+            invocation.invoke_async(&mut self.conn.clone()).await
+        }
+
+        async fn push(&self, tx: &Transaction) -> RedisResult<()> {
+            let Some(customer_id) = tx.customer_id else {
+                return Ok(());
+            };
+            let (list, generation) = self.keys(customer_id as i64);
+            self.push
+                .key(&list)
+                .key(&generation)
+                .arg(serde_json::to_string(&Entry::from(tx)).unwrap_or_default())
+                .arg(LIST_LEN)
+                .invoke_async(&mut self.conn.clone())
+                .await
+        }
+
+        /// Drops a list that may have missed a transaction, so it gets
+        /// rebuilt.
+        async fn discard(&self, customer_id: i64) -> RedisResult<()> {
+            let (list, generation) = self.keys(customer_id);
+            let mut conn = self.conn.clone();
+            let _: () = conn.del(&list).await?;
+            conn.incr(&generation, 1).await
+        }
+
+        /// Drops the list of every customer of the current tenant.
+        async fn discard_all(&self) -> RedisResult<()> {
+            let pattern = format!("{}*", self.list_prefix());
+            let mut conn = self.conn.clone();
+            let lists: Vec<String> = {
+                let mut keys = conn.scan_match::<_, String>(&pattern).await?;
+                let mut lists = Vec::new();
+                while let Some(key) = keys.next_item().await {
+                    if !key.ends_with(":geracao") {
+                        lists.push(key);
+                    }
+                }
+                lists
+            };
+            for list in lists {
+                let _: () = conn.del(&list).await?;
+                let _: i64 = conn.incr(format!("{}:geracao", list), 1).await?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Statements with the balance from Postgres and the transactions from
+    /// Redis, or entirely from `fallback` when the list is missing or Redis
+    /// fails.
+    pub struct RedisStatements {
+        pub lists: Arc<Lists>,
+        pub pool: sqlx::Pool<sqlx::Postgres>,
+        pub fallback: Arc<dyn StatementPort>,
+    }
+
+    #[async_trait]
+    impl StatementPort for RedisStatements {
+        async fn statement(&self, customer_id: i64) -> Result<Statement, errors::AppError> {
+            let generation = match self.lists.latest(customer_id).await {
+                Ok(Ok(transactions)) => {
+                    let (customer, taken_at) = db::get_customer_db(self.pool.to_owned(), customer_id)
+                        .await?
+                        .ok_or(errors::AppError::ErrCustomerNotFound)?;
+                    return Ok(Statement {
+                        customer,
+                        transactions,
+                        taken_at,
+                    });
+                }
+                Ok(Err(generation)) => Some(generation),
+                Err(err) => {
+                    log::warn!("reading latest transactions of customer {} from Redis: {}", customer_id, err);
+                    None
+                }
+            };
+
+            let statement = self.fallback.statement(customer_id).await?;
+            if let Some(generation) = generation {
+                if let Err(err) = self.lists.build(customer_id, &generation, &statement.transactions).await {
+                    log::warn!("building latest transactions of customer {} in Redis: {}", customer_id, err);
+                }
+            }
+            Ok(statement)
+        }
+
+        async fn invalidate(&self, customer_id: Option<i64>) {
+            let result = match customer_id {
+                Some(customer_id) => self.lists.discard(customer_id).await,
+                None => self.lists.discard_all().await,
+            };
+            if let Err(err) = result {
+                log::error!("latest transactions in Redis may be stale: {}", err);
+            }
+        }
+    }
+
+    /// Pushes every transaction `inner` commits to its customer's list.
+    pub struct RedisTransactions {
+        pub lists: Arc<Lists>,
+        pub inner: Arc<dyn TransactionPort>,
+    }
+
+    #[async_trait]
+    impl TransactionPort for RedisTransactions {
+        async fn create(
+            &self,
+            new_tx: NewTransaction,
+            balance_delta: i64,
+        ) -> Result<CreatedTransaction, errors::AppError> {
+            let customer_id = new_tx.customer_id as i64;
+            let created = self.inner.create(new_tx, balance_delta).await?;
+            if let Err(err) = self.lists.push(&created.transaction).await {
+                log::warn!("pushing transaction of customer {} to Redis: {}", customer_id, err);
+                if let Err(err) = self.lists.discard(customer_id).await {
+                    log::error!("latest transactions of customer {} in Redis may be stale: {}", customer_id, err);
+                }
+            }
+            Ok(created)
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures_util::FutureExt;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+
+use crate::domain::{CreatedTransaction, NewTransaction};
+use crate::ports::TransactionPort;
+use crate::{deadline, errors, rls, tenant};
+
+struct Job {
+    new_tx: NewTransaction,
+    balance_delta: i64,
+    tenant: Option<tenant::Schema>,
+    customer: Option<i32>,
+    deadline: Option<Instant>,
+    reply: oneshot::Sender<Result<CreatedTransaction, errors::AppError>>,
+}
+
+/// Routes every write for a customer through the same one of `shards` worker
+/// tasks, so writes to a hot customer are applied one after the other here
+/// instead of queueing on the customer's row lock in Postgres. Writes to
+/// different customers on the same shard also wait for each other, so there
+/// should be about as many shards as pool connections.
+pub struct ShardedWriter {
+    shards: Vec<mpsc::Sender<Job>>,
+}
+
+impl ShardedWriter {
+    pub fn new(inner: Arc<dyn TransactionPort>, shards: usize, queue_size: usize) -> ShardedWriter {
+        let shards = (0..shards.max(1))
+            .map(|_| {
+                let (sender, receiver) = mpsc::channel(queue_size.max(1));
+                tokio::spawn(work(inner.clone(), receiver));
+                sender
+            })
+            .collect();
+        ShardedWriter { shards }
+    }
+
+    fn shard(&self, tenant: &Option<tenant::Schema>, customer_id: i32) -> &mpsc::Sender<Job> {
+        let mut hasher = DefaultHasher::new();
+        (tenant, customer_id).hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+}
+
+/// Applies one shard's writes in order. Jobs carry the request's tenant and
+/// principal, which the pool hooks read from task-locals, and its deadline. A write that panics
+/// fails alone; the worker carries on with the next one.
+async fn work(inner: Arc<dyn TransactionPort>, mut jobs: mpsc::Receiver<Job>) {
+    while let Some(job) = jobs.recv().await {
+        let customer_id = job.new_tx.customer_id;
+        let write = inner.create(job.new_tx, job.balance_delta);
+        let write = async {
+            match job.deadline {
+                Some(deadline) => deadline::scope(deadline, write).await,
+                None => write.await,
+            }
+        };
+        let write = async {
+            match job.customer {
+                Some(customer) => rls::scope(customer, write).await,
+                None => write.await,
+            }
+        };
+        let write = async {
+            match job.tenant {
+                Some(schema) => tenant::scope(schema, write).await,
+                None => write.await,
+            }
+        };
+        let result = match AssertUnwindSafe(write).catch_unwind().await {
+            Ok(result) => result,
+            Err(_) => {
+                log::error!("shard worker panicked applying a write of customer {}", customer_id);
+                Err(errors::AppError::SQLError(sqlx::Error::WorkerCrashed))
+            }
+        };
+        // The request may have gone away; the write stands regardless.
+        let _ = job.reply.send(result);
+    }
+}
+
+#[async_trait]
+impl TransactionPort for ShardedWriter {
+    async fn create(
+        &self,
+        new_tx: NewTransaction,
+        balance_delta: i64,
+    ) -> Result<CreatedTransaction, errors::AppError> {
+        let tenant = tenant::current();
+        let (reply, result) = oneshot::channel();
+        let shard = self.shard(&tenant, new_tx.customer_id);
+        let job = Job {
+            new_tx,
+            balance_delta,
+            tenant,
+            customer: rls::current(),
+            deadline: deadline::current(),
+            reply,
+        };
+
+        // Only fails if the shard's worker is gone.
+        let crashed = || errors::AppError::SQLError(sqlx::Error::WorkerCrashed);
+        shard.send(job).await.map_err(|_| crashed())?;
+        result.await.map_err(|_| crashed())?
+    }
+}
@@ -0,0 +1,506 @@
+use std::collections::BTreeMap;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::{self, ContentType};
+use actix_web::middleware::{self, Next};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::{DateTime, NaiveDateTime, Utc};
+
+use crate::server::MyData;
+use crate::pagination::Pagination;
+use crate::{consistency, db, dialect, domain, error_catalog, errors, import, latency, request_id, warmup};
+
+const MAX_LISTED_JOBS: i64 = 100;
+const MAX_LISTED_DEAD_LETTERS: i64 = 100;
+/// Largest CSV accepted by the customer import.
+const MAX_IMPORT_BYTES: usize = 16 * 1024 * 1024;
+
+/// Registers the operational endpoints under `/admin`. Every one of them
+/// requires `ADMIN_TOKEN` as a bearer token, and refuses to run when it isn't
+/// configured.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin")
+            .wrap(middleware::from_fn(require_token))
+            .service(web::resource("/warmup").route(web::post().to(warmup)))
+            .service(web::resource("/latency").route(web::get().to(latency)))
+            .service(web::resource("/maintenance").route(web::post().to(maintenance)))
+            .service(
+                web::resource("/clientes/{id}/anonimizar").route(web::post().to(anonymize_customer)),
+            )
+            .service(web::resource("/clientes").route(web::get().to(list_customers)))
+            .service(
+                web::resource("/clientes/import")
+                    .app_data(web::PayloadConfig::new(MAX_IMPORT_BYTES))
+                    .route(web::post().to(import_customers)),
+            )
+            .service(web::resource("/auditoria").route(web::get().to(list_audit_log)))
+            .service(web::resource("/consistency").route(web::get().to(consistency)))
+            .service(web::resource("/consistency/repair").route(web::post().to(repair_consistency)))
+            .service(web::resource("/jobs").route(web::get().to(list_jobs)))
+            .service(web::resource("/jobs/{id}/retry").route(web::post().to(retry_job)))
+            .service(
+                web::resource("/webhooks/dead-letters").route(web::get().to(list_dead_letters)),
+            )
+            .service(
+                web::resource("/webhooks/dead-letters/{id}/requeue")
+                    .route(web::post().to(requeue_dead_letter)),
+            ),
+    );
+}
+
+/// Refuses requests without the `ADMIN_TOKEN` bearer token, and every
+/// request when no token is configured.
+pub(crate) async fn require_token(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let expected = req
+        .app_data::<web::Data<MyData>>()
+        .and_then(|data| data.admin_token.clone());
+    let Some(expected) = expected else {
+        return Ok(req.error_response(errors::AppError::ErrAdminTokenRequired));
+    };
+
+    let provided = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if provided != Some(expected.0.as_str()) {
+        return Ok(req.error_response(errors::AppError::ErrInvalidAdminToken));
+    }
+
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
+
+/// Resets the ledger and warms every layer up, answering only once the
+/// instance is ready for a benchmark run. Concurrent calls are serialized and
+/// repeated calls leave the same state behind.
+async fn warmup(d: web::Data<MyData>, _: HttpRequest) -> Result<HttpResponse, actix_web::Error> {
+    let _guard = d.warmup_lock.lock().await;
+    db::reset_state_db(&d.pool).await?;
+    d.transactions.invalidate(None).await;
+    let report = warmup::warm_up(&d.pool, d.db_max_connections).await?;
+
+    let res = serde_json::to_string(&WarmupResponse {
+        customers: report.customers,
+        connections: report.connections,
+        elapsed_ms: report.elapsed.as_millis() as u64,
+    })
+    .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
+}
+
+#[derive(Debug, Serialize)]
+struct WarmupResponse {
+    #[serde(rename = "clientes")]
+    customers: usize,
+    #[serde(rename = "conexoes")]
+    connections: usize,
+    #[serde(rename = "duracao_ms")]
+    elapsed_ms: u64,
+}
+
+/// Latency percentiles per route over the configured window. Empty unless
+/// latency tracking is enabled.
+async fn latency(d: web::Data<MyData>, _: HttpRequest) -> Result<HttpResponse, actix_web::Error> {
+    let res = serde_json::to_string(&LatencyResponse {
+        enabled: d.latency_enabled,
+        window_secs: d.latency.window().as_secs(),
+        routes: d.latency.summary(),
+    })
+    .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
+}
+
+#[derive(Debug, Serialize)]
+struct LatencyResponse {
+    #[serde(rename = "habilitado")]
+    enabled: bool,
+    #[serde(rename = "janela_segundos")]
+    window_secs: u64,
+    #[serde(rename = "rotas")]
+    routes: BTreeMap<String, latency::RouteLatency>,
+}
+
+const DEFAULT_MAINTENANCE_RETRY_AFTER_SECS: u32 = 60;
+
+/// Turns maintenance mode on or off. While on, public endpoints answer `503`
+/// with `Retry-After` set to `tentar_apos_segundos`; admin routes keep
+/// working.
+async fn maintenance(
+    body: web::Json<MaintenanceRequest>,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    d.maintenance.set(
+        body.enabled,
+        body.retry_after_secs.unwrap_or(DEFAULT_MAINTENANCE_RETRY_AFTER_SECS),
+    );
+    log::warn!(
+        "maintenance mode {}",
+        if body.enabled { "enabled" } else { "disabled" }
+    );
+
+    let res = serde_json::to_string(&MaintenanceResponse {
+        enabled: d.maintenance.enabled(),
+        retry_after_secs: d.maintenance.retry_after_secs(),
+    })
+    .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
+}
+
+#[derive(Debug, Deserialize)]
+struct MaintenanceRequest {
+    #[serde(rename = "ativo")]
+    enabled: bool,
+    #[serde(rename = "tentar_apos_segundos")]
+    retry_after_secs: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct MaintenanceResponse {
+    #[serde(rename = "ativo")]
+    enabled: bool,
+    #[serde(rename = "tentar_apos_segundos")]
+    retry_after_secs: u32,
+}
+
+/// Checks every customer's balance against its transactions and lists the
+/// ones that drifted.
+async fn consistency(d: web::Data<MyData>, _: HttpRequest) -> Result<HttpResponse, actix_web::Error> {
+    let report = consistency::check(&d.pools.pool(db::RoutedQuery::Consistency)).await?;
+    if !report.drifts.is_empty() {
+        log::error!("{} customers have drifted balances", report.drifts.len());
+    }
+
+    let res = serde_json::to_string(&report).map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
+}
+
+/// Sets every drifted balance to the one computed from the customer's
+/// transactions, auditing each correction.
+async fn repair_consistency(
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let report = consistency::repair(&d.pool).await?;
+
+    let res = serde_json::to_string(&report).map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
+}
+
+/// Scrubs a customer's transaction descriptions for a data erasure request,
+/// keeping amounts and balances so the ledger still adds up. Can be repeated,
+/// e.g. to cover transactions created afterwards; every call is audited.
+async fn anonymize_customer(
+    id: web::Path<i32>,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let request_id = request_id::current();
+    let (anonymized_at, transactions) =
+        db::anonymize_customer_db(&d.pool, *id, request_id.as_ref().map(|id| id.0.as_str()))
+            .await?
+            .ok_or(errors::AppError::ErrCustomerNotFound)?;
+    d.transactions.invalidate(Some(*id as i64)).await;
+    log::warn!("customer {} anonymized, {} transactions scrubbed", *id, transactions);
+
+    let res = serde_json::to_string(&AnonymizeResponse {
+        anonymized_at,
+        transactions,
+    })
+    .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
+}
+
+#[derive(Debug, Serialize)]
+struct AnonymizeResponse {
+    #[serde(rename = "anonimizado_em")]
+    anonymized_at: DateTime<Utc>,
+    #[serde(rename = "transacoes_anonimizadas")]
+    transactions: u64,
+}
+
+async fn list_customers(
+    pagination: Pagination,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (customers, total) = db::page_customers_db(
+        d.pool.to_owned(),
+        pagination.fetch_limit(),
+        pagination.offset(),
+        pagination.after(),
+    )
+    .await?;
+
+    let page = pagination
+        .page(customers, total, |customer| customer.id as i64)
+        .map(|customer| CustomerResponse::from(&customer));
+    let res = dialect::to_string(&page).map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
+}
+
+#[derive(Debug, Serialize)]
+struct CustomerResponse {
+    id: i32,
+    #[serde(rename = "limite")]
+    limit: i32,
+    #[serde(rename = "saldo")]
+    balance: i32,
+    #[serde(rename = "criado_em")]
+    created_at: NaiveDateTime,
+}
+
+impl From<&domain::Customer> for CustomerResponse {
+    fn from(customer: &domain::Customer) -> Self {
+        CustomerResponse {
+            id: customer.id,
+            limit: customer.limit,
+            balance: customer.balance,
+            created_at: customer.created_at,
+        }
+    }
+}
+
+/// Creates customers from a CSV upload with the columns `id` (optional),
+/// `limite` and `saldo_inicial`. Valid rows are inserted together and the
+/// rest reported by line, rows whose id is already taken included; nothing
+/// is inserted when the header is unusable.
+async fn import_customers(
+    body: web::Bytes,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let import::Parsed {
+        customers,
+        mut rejected,
+    } = import::parse(&body)?;
+    let request_id = request_id::current();
+    let (imported, taken) = db::import_customers_db(
+        &d.pool,
+        &customers,
+        request_id.as_ref().map(|id| id.0.as_str()),
+    )
+    .await?;
+    rejected.extend(
+        taken
+            .into_iter()
+            .map(|line| import::RowError::new(line, &error_catalog::IMPORT_EXISTING_ID)),
+    );
+    rejected.sort_by_key(|error| error.line);
+    log::info!("{} customers imported, {} rows rejected", imported, rejected.len());
+
+    let res = serde_json::to_string(&ImportResponse {
+        imported,
+        errors: rejected,
+    })
+    .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
+}
+
+#[derive(Debug, Serialize)]
+struct ImportResponse {
+    #[serde(rename = "importados")]
+    imported: u64,
+    #[serde(rename = "erros")]
+    errors: Vec<import::RowError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListAuditLogQuery {
+    cliente_id: Option<i32>,
+}
+
+/// The audit log, newest first, of every customer or only `?cliente_id=`.
+async fn list_audit_log(
+    query: web::Query<ListAuditLogQuery>,
+    pagination: Pagination,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (entries, total) = db::page_audit_log_db(
+        d.pool.to_owned(),
+        query.cliente_id,
+        pagination.fetch_limit(),
+        pagination.offset(),
+        pagination.after(),
+    )
+    .await?;
+
+    let page = pagination
+        .page(entries, total, |entry| entry.id)
+        .map(|entry| AuditEntryResponse::from(&entry));
+    let res = dialect::to_string(&page).map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
+}
+
+#[derive(Debug, Serialize)]
+struct AuditEntryResponse {
+    id: i64,
+    #[serde(rename = "acao")]
+    action: String,
+    #[serde(rename = "cliente_id")]
+    customer_id: Option<i32>,
+    #[serde(rename = "detalhes")]
+    details: serde_json::Value,
+    #[serde(rename = "criado_em")]
+    created_at: DateTime<Utc>,
+}
+
+impl From<&db::AuditEntry> for AuditEntryResponse {
+    fn from(entry: &db::AuditEntry) -> Self {
+        AuditEntryResponse {
+            id: entry.id,
+            action: entry.action.clone(),
+            customer_id: entry.customer_id,
+            details: entry.details.0.clone(),
+            created_at: entry.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ListJobsQuery {
+    status: Option<String>,
+}
+
+async fn list_jobs(
+    query: web::Query<ListJobsQuery>,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let jobs = db::list_jobs_db(d.pool.to_owned(), query.into_inner().status, MAX_LISTED_JOBS).await?;
+
+    let jobs: Vec<JobResponse> = jobs.iter().map(JobResponse::from).collect();
+    let res = serde_json::to_string(&jobs).map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
+}
+
+async fn retry_job(
+    id: web::Path<i64>,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !db::retry_failed_job_db(d.pool.to_owned(), *id).await? {
+        return Err(errors::AppError::ErrJobNotFound.into());
+    }
+    Ok(HttpResponse::Accepted().finish())
+}
+
+#[derive(Debug, Deserialize)]
+struct ListDeadLettersQuery {
+    #[serde(default)]
+    incluir_reenfileirados: bool,
+}
+
+async fn list_dead_letters(
+    query: web::Query<ListDeadLettersQuery>,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let dead_letters = db::list_webhook_dead_letters_db(
+        d.pool.to_owned(),
+        query.incluir_reenfileirados,
+        MAX_LISTED_DEAD_LETTERS,
+    )
+    .await?;
+
+    let dead_letters: Vec<DeadLetterResponse> =
+        dead_letters.iter().map(DeadLetterResponse::from).collect();
+    let res = serde_json::to_string(&dead_letters).map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
+}
+
+async fn requeue_dead_letter(
+    id: web::Path<i64>,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let job_id =
+        db::requeue_webhook_dead_letter_db(d.pool.to_owned(), *id, d.webhooks.max_attempts)
+            .await?
+            .ok_or(errors::AppError::ErrDeadLetterNotFound)?;
+
+    let res = serde_json::to_string(&RequeueResponse { job_id }).map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Accepted().content_type(ContentType::json()).body(res))
+}
+
+#[derive(Debug, Serialize)]
+struct RequeueResponse {
+    job_id: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct DeadLetterResponse {
+    id: i64,
+    webhook_id: i64,
+    job_id: i64,
+    payload: serde_json::Value,
+    #[serde(rename = "tentativas")]
+    attempts: i32,
+    #[serde(rename = "ultimo_erro")]
+    last_error: String,
+    #[serde(rename = "falhou_em")]
+    failed_at: NaiveDateTime,
+    #[serde(rename = "reenfileirado_em")]
+    requeued_at: Option<NaiveDateTime>,
+}
+
+impl From<&db::WebhookDeadLetter> for DeadLetterResponse {
+    fn from(dead_letter: &db::WebhookDeadLetter) -> Self {
+        DeadLetterResponse {
+            id: dead_letter.id,
+            webhook_id: dead_letter.webhook_id,
+            job_id: dead_letter.job_id,
+            payload: dead_letter.payload.0.clone(),
+            attempts: dead_letter.attempts,
+            last_error: dead_letter.last_error.clone(),
+            failed_at: dead_letter.failed_at,
+            requeued_at: dead_letter.requeued_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JobResponse {
+    id: i64,
+    #[serde(rename = "tipo")]
+    kind: String,
+    payload: serde_json::Value,
+    status: String,
+    #[serde(rename = "tentativas")]
+    attempts: i32,
+    #[serde(rename = "max_tentativas")]
+    max_attempts: i32,
+    #[serde(rename = "executar_em")]
+    run_at: NaiveDateTime,
+    #[serde(rename = "bloqueado_por")]
+    locked_by: Option<String>,
+    #[serde(rename = "ultimo_erro")]
+    last_error: Option<String>,
+    #[serde(rename = "criado_em")]
+    created_at: NaiveDateTime,
+}
+
+impl From<&db::Job> for JobResponse {
+    fn from(job: &db::Job) -> Self {
+        JobResponse {
+            id: job.id,
+            kind: job.kind.clone(),
+            payload: job.payload.0.clone(),
+            status: job.status.clone(),
+            attempts: job.attempts,
+            max_attempts: job.max_attempts,
+            run_at: job.run_at,
+            locked_by: job.locked_by.clone(),
+            last_error: job.last_error.clone(),
+            created_at: job.created_at,
+        }
+    }
+}
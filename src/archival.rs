@@ -0,0 +1,94 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::jobs::{self, JobError, JobHandler};
+use crate::ports::StatementPort;
+use crate::{config, db, errors, tenant};
+
+pub const JOB_KIND: &str = "archival";
+
+/// Registers the recurring job that moves transactions past the retention
+/// period to `transactions_archive`, in the default schema and then in each
+/// tenant's. Statements only show what is left in the hot table, so cached
+/// statements of the customers involved are invalidated through `statements`.
+pub fn register(
+    runner: &mut jobs::JobRunner,
+    pool: sqlx::Pool<sqlx::Postgres>,
+    statements: Arc<dyn StatementPort>,
+    cfg: config::ArchivalConfig,
+    tenant_schemas: Vec<tenant::Schema>,
+) {
+    if cfg.retention_days == 0 {
+        return;
+    }
+
+    let interval = Duration::from_secs(cfg.interval_secs.max(1));
+    let handler = Archival {
+        pool,
+        statements,
+        cfg,
+        tenant_schemas,
+    };
+    runner.schedule(JOB_KIND, Arc::new(handler), interval);
+}
+
+struct Archival {
+    pool: sqlx::Pool<sqlx::Postgres>,
+    statements: Arc<dyn StatementPort>,
+    cfg: config::ArchivalConfig,
+    tenant_schemas: Vec<tenant::Schema>,
+}
+
+#[async_trait]
+impl JobHandler for Archival {
+    /// Fails if any schema failed, after trying all of them.
+    async fn run(&self, _payload: &serde_json::Value) -> Result<(), JobError> {
+        let mut failed = Vec::new();
+        match archive(&self.pool, self.statements.as_ref(), &self.cfg).await {
+            Ok(0) => {}
+            Ok(n) => log::info!("archived {} transactions", n),
+            Err(err) => {
+                log::error!("archiving transactions failed: {}", err);
+                failed.push("default");
+            }
+        }
+        for schema in &self.tenant_schemas {
+            match tenant::scope(schema.clone(), archive(&self.pool, self.statements.as_ref(), &self.cfg)).await {
+                Ok(0) => {}
+                Ok(n) => log::info!("archived {} transactions of schema {}", n, schema.0),
+                Err(err) => {
+                    log::error!("archiving transactions of schema {} failed: {}", schema.0, err);
+                    failed.push(&*schema.0);
+                }
+            }
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("archiving failed in schemas {}", failed.join(", ")).into())
+        }
+    }
+}
+
+async fn archive(
+    pool: &sqlx::Pool<sqlx::Postgres>,
+    statements: &dyn StatementPort,
+    cfg: &config::ArchivalConfig,
+) -> Result<u64, errors::AppError> {
+    let mut total = 0;
+    loop {
+        let mut moved = 0;
+        for (customer_id, count) in db::archive_transactions_db(pool, cfg.retention_days, cfg.batch_size).await? {
+            statements.invalidate(Some(customer_id as i64)).await;
+            moved += count as u64;
+        }
+        total += moved;
+        // A full batch means there is probably more waiting.
+        if moved < cfg.batch_size as u64 {
+            return Ok(total);
+        }
+    }
+}
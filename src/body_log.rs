@@ -0,0 +1,78 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::web;
+use rand::Rng;
+use serde_json::Value;
+
+use crate::{buffered, server};
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Routes whose bodies are logged; everything else passes through untouched.
+const LOGGED_ROUTES: [&str; 2] = ["/clientes/{id}/extrato", "/clientes/{id}/transacoes"];
+
+/// Logs the full request and response bodies of a sample of requests to the
+/// public endpoints, masking the configured JSON fields.
+pub async fn log_bodies(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let data = match req.app_data::<web::Data<server::MyData>>() {
+        Some(data) => data.clone(),
+        None => return Ok(next.call(req).await?.map_into_boxed_body()),
+    };
+    let cfg = &data.body_log;
+
+    let logged_route = req
+        .match_pattern()
+        .is_some_and(|pattern| LOGGED_ROUTES.contains(&pattern.as_str()));
+    if !logged_route || !rand::thread_rng().gen_bool(cfg.sample_rate.clamp(0.0, 1.0)) {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+
+    let req_body = buffered::read_request_body(&mut req).await?;
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+
+    let res = next.call(req).await?;
+    let status = res.status();
+    let (res, res_body) = buffered::read_response_body(res).await?;
+
+    log::debug!(
+        "{} {} request={} response[{}]={}",
+        method,
+        path,
+        redact(&req_body, &cfg.redact_fields),
+        status.as_u16(),
+        res_body.map_or_else(|| "<stream>".to_string(), |body| redact(&body, &cfg.redact_fields)),
+    );
+
+    Ok(res)
+}
+
+fn redact(body: &[u8], fields: &[String]) -> String {
+    match serde_json::from_slice::<Value>(body) {
+        Ok(mut value) => {
+            redact_value(&mut value, fields);
+            value.to_string()
+        }
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    }
+}
+
+fn redact_value(value: &mut Value, fields: &[String]) {
+    match value {
+        Value::Object(map) => {
+            for (key, field_value) in map.iter_mut() {
+                if fields.contains(key) {
+                    *field_value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_value(field_value, fields);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| redact_value(item, fields)),
+        _ => {}
+    }
+}
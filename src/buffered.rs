@@ -0,0 +1,33 @@
+use actix_web::body::{self, BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::web;
+
+/// Reads the whole request body and puts it back as the request payload, so
+/// middlewares can inspect it without starving the handler.
+pub async fn read_request_body(req: &mut ServiceRequest) -> Result<web::Bytes, actix_web::Error> {
+    let body = req.extract::<web::Bytes>().await?;
+    req.set_payload(body.clone().into());
+    Ok(body)
+}
+
+/// Collects the response body into memory, returning it together with an
+/// equivalent response that can still be sent to the client. Streamed bodies,
+/// which may be large or never end, are left alone and come back as `None`.
+pub async fn read_response_body(
+    res: ServiceResponse<impl MessageBody + 'static>,
+) -> Result<(ServiceResponse<BoxBody>, Option<web::Bytes>), actix_web::Error> {
+    if let BodySize::Stream = res.response().body().size() {
+        return Ok((res.map_into_boxed_body(), None));
+    }
+
+    let (http_req, http_res) = res.into_parts();
+    let (http_res, res_body) = http_res.into_parts();
+    let bytes = body::to_bytes(res_body).await.map_err(|err| {
+        let err: Box<dyn std::error::Error> = err.into();
+        ErrorInternalServerError(err.to_string())
+    })?;
+
+    let http_res = http_res.set_body(bytes.clone()).map_into_boxed_body();
+    Ok((ServiceResponse::new(http_req, http_res), Some(bytes)))
+}
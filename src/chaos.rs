@@ -0,0 +1,53 @@
+use std::{io, time::Duration};
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::middleware::Next;
+use actix_web::web;
+use rand::Rng;
+
+use crate::{errors, server};
+
+/// Fault injection middleware. Every request rolls the dice independently for
+/// each fault kind configured in `ChaosConfig`:
+///
+/// - extra latency before the handler runs;
+/// - a dropped database connection, which closes one pooled connection and
+///   fails the request the same way a reset socket would;
+/// - a plain 500 response.
+pub async fn inject_faults(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let data = req
+        .app_data::<web::Data<server::MyData>>()
+        .cloned()
+        .ok_or_else(|| ErrorInternalServerError("missing app data"))?;
+    let chaos = &data.chaos;
+
+    if roll(chaos.latency_probability) {
+        tokio::time::sleep(Duration::from_millis(chaos.latency_ms)).await;
+    }
+
+    if roll(chaos.db_drop_probability) {
+        if let Ok(conn) = data.pool.acquire().await {
+            conn.close().await.ok();
+        }
+        let err = sqlx::Error::Io(io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "chaos: injected connection drop",
+        ));
+        return Ok(req.error_response(errors::AppError::from(err)));
+    }
+
+    if roll(chaos.error_probability) {
+        return Ok(req.error_response(ErrorInternalServerError("chaos: injected failure")));
+    }
+
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
+
+fn roll(probability: f64) -> bool {
+    probability > 0.0 && rand::thread_rng().gen_bool(probability.min(1.0))
+}
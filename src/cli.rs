@@ -0,0 +1,147 @@
+use std::{env, process};
+
+use rinha_servico_rust::client::RinhaClient;
+use rinha_servico_rust::schema::CreateCustomerTransactionRequest;
+use serde::Serialize;
+
+use crate::{errors, smoke};
+
+const DEFAULT_BASE_URL: &str = "http://localhost:9999";
+
+/// Operations subcommands, run against an instance at `--url` (or
+/// `RINHA_URL`) instead of starting the server. Admin routes are called with
+/// `--token` (or `ADMIN_TOKEN`).
+pub enum Command {
+    Extrato {
+        id: i32,
+    },
+    Transacao {
+        id: i32,
+        request: CreateCustomerTransactionRequest,
+    },
+    Smoke {
+        id: i32,
+    },
+    /// Compares every stored balance with its transactions; fails when any
+    /// drifted. With `repair`, drifted balances are corrected instead.
+    Check {
+        repair: bool,
+    },
+}
+
+pub struct Invocation {
+    base_url: String,
+    admin_token: Option<String>,
+    command: Command,
+}
+
+/// Parses the command line, without the program name. Returns `None` when
+/// no subcommand was given and the server should start.
+pub fn parse(args: &[String]) -> Result<Option<Invocation>, errors::CustomError> {
+    let mut base_url = env::var("RINHA_URL").unwrap_or(DEFAULT_BASE_URL.to_string());
+    let mut admin_token = env::var("ADMIN_TOKEN").ok();
+    let mut positional = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--url" {
+            base_url = args.next().cloned().ok_or_else(|| usage("--url needs a value"))?;
+        } else if let Some(url) = arg.strip_prefix("--url=") {
+            base_url = url.to_string();
+        } else if arg == "--token" {
+            admin_token = Some(args.next().cloned().ok_or_else(|| usage("--token needs a value"))?);
+        } else if let Some(token) = arg.strip_prefix("--token=") {
+            admin_token = Some(token.to_string());
+        } else {
+            positional.push(arg.as_str());
+        }
+    }
+
+    let command = match positional.as_slice() {
+        ["extrato", id] => Command::Extrato { id: parse_id(id)? },
+        ["transacao", id, value, tx_type, description] => Command::Transacao {
+            id: parse_id(id)?,
+            request: CreateCustomerTransactionRequest {
+                value: value
+                    .parse()
+                    .map_err(|_| usage(&format!("invalid valor: {}", value)))?,
+                tx_type: tx_type.to_string(),
+                description: description.to_string(),
+            },
+        },
+        ["smoke"] => Command::Smoke { id: 1 },
+        ["smoke", id] => Command::Smoke { id: parse_id(id)? },
+        ["check"] => Command::Check { repair: false },
+        ["check", "--repair"] => Command::Check { repair: true },
+        ["extrato", ..] | ["transacao", ..] | ["smoke", ..] | ["check", ..] => {
+            return Err(usage("wrong number of arguments"))
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(Invocation {
+        base_url,
+        admin_token,
+        command,
+    }))
+}
+
+pub async fn run(invocation: Invocation) -> Result<(), errors::CustomError> {
+    let client = RinhaClient::new(&invocation.base_url).with_admin_token(invocation.admin_token);
+
+    match invocation.command {
+        Command::Extrato { id } => print(&client.extrato(id).await.map_err(boxed)?),
+        Command::Transacao { id, request } => {
+            print(&client.criar_transacao(id, &request).await.map_err(boxed)?)
+        }
+        Command::Smoke { id } => smoke::run(&client, id).await,
+        Command::Check { repair: true } => print(&client.reparar_consistencia().await.map_err(boxed)?),
+        Command::Check { repair: false } => {
+            let report = client.consistencia().await.map_err(boxed)?;
+            print(&report)?;
+            if report.drifts.is_empty() {
+                Ok(())
+            } else {
+                Err(errors::CustomError::StringError(format!(
+                    "{} of {} customers have drifted balances",
+                    report.drifts.len(),
+                    report.checked
+                )))
+            }
+        }
+    }
+}
+
+/// Ends the process once a subcommand is done, with a non-zero status and the
+/// error on stderr if it failed.
+pub fn exit_on_error(result: Result<(), errors::CustomError>) -> ! {
+    match result {
+        Ok(()) => process::exit(0),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1)
+        }
+    }
+}
+
+fn print<T: Serialize>(value: &T) -> Result<(), errors::CustomError> {
+    let pretty = serde_json::to_string_pretty(value).map_err(boxed)?;
+    println!("{}", pretty);
+    Ok(())
+}
+
+fn parse_id(id: &str) -> Result<i32, errors::CustomError> {
+    id.parse()
+        .map_err(|_| usage(&format!("invalid customer id: {}", id)))
+}
+
+fn usage(problem: &str) -> errors::CustomError {
+    errors::CustomError::StringError(format!(
+        "{}\nusage: rinha-servico-rust [--url URL] extrato <id>\n       rinha-servico-rust [--url URL] transacao <id> <valor> <tipo> <descricao>\n       rinha-servico-rust [--url URL] smoke [<id>]\n       rinha-servico-rust [--url URL] [--token TOKEN] check [--repair]",
+        problem
+    ))
+}
+
+fn boxed<E: std::error::Error + 'static>(err: E) -> errors::CustomError {
+    errors::CustomError::StandardError(Box::new(err))
+}
@@ -0,0 +1,137 @@
+//! Typed HTTP client for a running instance, enabled by the `client`
+//! feature.
+
+use std::fmt;
+
+use crate::schema::{
+    ConsistencyReport, CreateCustomerTransactionRequest, CreateCustomerTransactionResponse,
+    ErrorEnvelope, GetCustomerStatementResponse, RepairReport,
+};
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The request couldn't be sent or the response couldn't be read.
+    Http(reqwest::Error),
+    /// The server answered with a non-2xx status. `error` is the body when
+    /// it was in the error envelope.
+    Api {
+        status: u16,
+        error: Option<ErrorEnvelope>,
+    },
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Http(err) => write!(f, "http error: {}", err),
+            ClientError::Api {
+                status,
+                error: Some(error),
+            } => write!(f, "status {}: {} ({})", status, error.message, error.code),
+            ClientError::Api { status, error: None } => write!(f, "status {}", status),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Http(err) => Some(err),
+            ClientError::Api { .. } => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(err: reqwest::Error) -> ClientError {
+        ClientError::Http(err)
+    }
+}
+
+impl ClientError {
+    /// HTTP status of the response, for API errors.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            ClientError::Http(err) => err.status().map(|status| status.as_u16()),
+            ClientError::Api { status, .. } => Some(*status),
+        }
+    }
+}
+
+pub struct RinhaClient {
+    http: reqwest::Client,
+    base_url: String,
+    admin_token: Option<String>,
+}
+
+impl RinhaClient {
+    pub fn new(base_url: &str) -> RinhaClient {
+        RinhaClient::with_client(reqwest::Client::new(), base_url)
+    }
+
+    /// Uses an existing reqwest client, e.g. one with custom timeouts.
+    pub fn with_client(http: reqwest::Client, base_url: &str) -> RinhaClient {
+        RinhaClient {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            admin_token: None,
+        }
+    }
+
+    /// Sends `token` as the bearer token of `/admin` requests.
+    pub fn with_admin_token(mut self, token: Option<String>) -> RinhaClient {
+        self.admin_token = token;
+        self
+    }
+
+    /// `GET /clientes/{id}/extrato`.
+    pub async fn extrato(&self, id: i32) -> Result<GetCustomerStatementResponse, ClientError> {
+        let url = format!("{}/clientes/{}/extrato", self.base_url, id);
+        let res = self.http.get(url).send().await?;
+        parse(res).await
+    }
+
+    /// `POST /clientes/{id}/transacoes`.
+    pub async fn criar_transacao(
+        &self,
+        id: i32,
+        req: &CreateCustomerTransactionRequest,
+    ) -> Result<CreateCustomerTransactionResponse, ClientError> {
+        let url = format!("{}/clientes/{}/transacoes", self.base_url, id);
+        let res = self.http.post(url).json(req).send().await?;
+        parse(res).await
+    }
+
+    /// `GET /admin/consistency`.
+    pub async fn consistencia(&self) -> Result<ConsistencyReport, ClientError> {
+        let url = format!("{}/admin/consistency", self.base_url);
+        let res = self.admin(self.http.get(url)).send().await?;
+        parse(res).await
+    }
+
+    /// `POST /admin/consistency/repair`.
+    pub async fn reparar_consistencia(&self) -> Result<RepairReport, ClientError> {
+        let url = format!("{}/admin/consistency/repair", self.base_url);
+        let res = self.admin(self.http.post(url)).send().await?;
+        parse(res).await
+    }
+
+    fn admin(&self, req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.admin_token {
+            Some(token) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+}
+
+async fn parse<T: serde::de::DeserializeOwned>(res: reqwest::Response) -> Result<T, ClientError> {
+    let status = res.status();
+    if !status.is_success() {
+        let error = res.json::<ErrorEnvelope>().await.ok();
+        return Err(ClientError::Api {
+            status: status.as_u16(),
+            error,
+        });
+    }
+    Ok(res.json::<T>().await?)
+}
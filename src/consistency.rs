@@ -0,0 +1,60 @@
+use rinha_servico_rust::schema::{BalanceDrift, ConsistencyReport, RepairReport};
+
+use crate::{db, errors, request_id};
+
+/// Recomputes every customer's balance from its transactions and reports the
+/// customers whose stored balance differs. Caching, batching and write-behind
+/// modes all keep the balance apart from the transactions, so this is how a
+/// bug in any of them shows up.
+pub async fn check(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<ConsistencyReport, errors::AppError> {
+    let balances = db::balances_db(pool).await?;
+
+    let checked = balances.len();
+    let drifts = balances
+        .into_iter()
+        .filter(|(_, balance, computed_balance)| balance != computed_balance)
+        .map(|(customer_id, balance, computed_balance)| BalanceDrift {
+            customer_id,
+            balance,
+            computed_balance,
+        })
+        .collect();
+
+    Ok(ConsistencyReport { checked, drifts })
+}
+
+/// Runs `check`, then corrects each drifted customer in its own transaction,
+/// checking it again under lock. Every correction is written to the audit
+/// log.
+pub async fn repair(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<RepairReport, errors::AppError> {
+    let report = check(pool).await?;
+    let request_id = request_id::current();
+
+    let mut repaired = Vec::new();
+    for drift in report.drifts {
+        let corrected = db::repair_balance_db(
+            pool,
+            drift.customer_id,
+            request_id.as_ref().map(|id| id.0.as_str()),
+        )
+        .await?;
+        if let Some((balance, computed_balance)) = corrected {
+            log::warn!(
+                "balance of customer {} repaired from {} to {}",
+                drift.customer_id,
+                balance,
+                computed_balance
+            );
+            repaired.push(BalanceDrift {
+                customer_id: drift.customer_id,
+                balance,
+                computed_balance,
+            });
+        }
+    }
+
+    Ok(RepairReport {
+        checked: report.checked,
+        repaired,
+    })
+}
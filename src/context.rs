@@ -0,0 +1,62 @@
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_util::Stream;
+use tokio::task::LocalKey;
+
+tokio::task_local! {
+    static POOL_WAIT: Cell<Duration>;
+}
+
+/// Runs `f` with fresh request-scoped bookkeeping, returning its output along
+/// with the total time spent waiting for pooled database connections.
+pub async fn scope<F: Future>(f: F) -> (F::Output, Duration) {
+    POOL_WAIT
+        .scope(Cell::new(Duration::ZERO), async move {
+            let output = f.await;
+            (output, POOL_WAIT.with(Cell::get))
+        })
+        .await
+}
+
+/// Adds `elapsed` to the current request's pool wait time. Calls made outside
+/// a request scope (background tasks, startup) are ignored.
+pub fn record_pool_wait(elapsed: Duration) {
+    let _ = POOL_WAIT.try_with(|wait| wait.set(wait.get() + elapsed));
+}
+
+/// Re-enters `key`'s task-local scope with `value` every time `stream` is
+/// polled. Response body streams are polled after the handler returns, outside
+/// the scopes set up by middleware.
+pub fn scoped_stream<T: Clone + Unpin + 'static, S: Stream>(
+    key: &'static LocalKey<T>,
+    value: Option<T>,
+    stream: S,
+) -> impl Stream<Item = S::Item> {
+    ScopedStream {
+        key,
+        value,
+        inner: Box::pin(stream),
+    }
+}
+
+struct ScopedStream<T: 'static, S> {
+    key: &'static LocalKey<T>,
+    value: Option<T>,
+    inner: Pin<Box<S>>,
+}
+
+impl<T: Clone + Unpin + 'static, S: Stream> Stream for ScopedStream<T, S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let this = &mut *self;
+        match this.value.clone() {
+            Some(value) => this.key.sync_scope(value, || this.inner.as_mut().poll_next(cx)),
+            None => this.inner.as_mut().poll_next(cx),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{Days, NaiveDate, Utc};
+
+use crate::jobs::{self, JobError, JobHandler};
+use crate::{config, db, errors, tenant};
+
+pub const JOB_KIND: &str = "daily_balances";
+
+/// Registers the recurring job that closes every finished UTC day, writing
+/// each customer's end-of-day balance to `daily_balances`, in the default
+/// schema and then in each tenant's. Days are closed in order from the day
+/// after the last closed one, or `backfill_days` back on the first run, so
+/// days missed while no instance was up are caught up.
+pub fn register(
+    runner: &mut jobs::JobRunner,
+    pool: sqlx::Pool<sqlx::Postgres>,
+    cfg: config::DailyBalancesConfig,
+    tenant_schemas: Vec<tenant::Schema>,
+) {
+    if !cfg.enabled {
+        return;
+    }
+
+    let interval = Duration::from_secs(cfg.interval_secs.max(1));
+    let handler = CloseDays {
+        pool,
+        cfg,
+        tenant_schemas,
+    };
+    runner.schedule(JOB_KIND, Arc::new(handler), interval);
+}
+
+struct CloseDays {
+    pool: sqlx::Pool<sqlx::Postgres>,
+    cfg: config::DailyBalancesConfig,
+    tenant_schemas: Vec<tenant::Schema>,
+}
+
+#[async_trait]
+impl JobHandler for CloseDays {
+    /// Fails if any schema failed, after trying all of them.
+    async fn run(&self, _payload: &serde_json::Value) -> Result<(), JobError> {
+        let mut failed = Vec::new();
+        match close_days(&self.pool, &self.cfg).await {
+            Ok(0) => {}
+            Ok(n) => log::info!("closed {} days of balances", n),
+            Err(err) => {
+                log::error!("closing daily balances failed: {}", err);
+                failed.push("default");
+            }
+        }
+        for schema in &self.tenant_schemas {
+            match tenant::scope(schema.clone(), close_days(&self.pool, &self.cfg)).await {
+                Ok(0) => {}
+                Ok(n) => log::info!("closed {} days of balances of schema {}", n, schema.0),
+                Err(err) => {
+                    log::error!("closing daily balances of schema {} failed: {}", schema.0, err);
+                    failed.push(&*schema.0);
+                }
+            }
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("closing daily balances failed in schemas {}", failed.join(", ")).into())
+        }
+    }
+}
+
+/// Closes every finished day not closed yet, returning how many.
+async fn close_days(
+    pool: &sqlx::Pool<sqlx::Postgres>,
+    cfg: &config::DailyBalancesConfig,
+) -> Result<u32, errors::AppError> {
+    let yesterday = Utc::now().date_naive() - Days::new(1);
+    let mut day = match db::last_closed_day_db(pool).await? {
+        Some(last) => last + Days::new(1),
+        None => yesterday - Days::new(cfg.backfill_days.into()),
+    };
+
+    let mut closed = 0;
+    while day <= yesterday {
+        db::close_day_db(pool, day).await?;
+        closed += 1;
+        day = day.succ_opt().unwrap_or(NaiveDate::MAX);
+    }
+    Ok(closed)
+}
@@ -1,39 +1,223 @@
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_stream::try_stream;
+use futures_util::{Stream, TryStreamExt};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use sqlx::postgres::PgPoolOptions;
-use sqlx::types::chrono::NaiveDateTime;
+use sqlx::pool::PoolConnection;
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions, PgSslMode};
+use sqlx::types::Json;
+use sqlx::{Connection, PgConnection, Postgres};
+use sqlx::types::chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use uuid::Uuid;
 
-use crate::errors;
+use crate::domain::{self, Customer, NewTransaction, Transaction};
+use crate::{config, context, deadline, errors, events, failover, ids, metrics, rls, tenant, webhooks};
 
-pub struct Customer {
-    pub id: i32,
-    pub limit: i32,
-    pub balance: i32,
-    pub created_at: NaiveDateTime,
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// SQLSTATE raised when a SERIALIZABLE transaction can't be committed.
+const SERIALIZATION_FAILURE: &str = "40001";
+/// SQLSTATE raised on the transaction Postgres picks as the deadlock victim.
+const DEADLOCK_DETECTED: &str = "40P01";
+
+/// Why a write transaction was aborted by Postgres and run again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetryReason {
+    SerializationFailure,
+    Deadlock,
 }
 
-pub struct Transaction {
-    pub id: Option<i32>,
-    pub value: Option<i32>,
-    pub tx_type: Option<String>,
-    pub description: Option<String>,
-    pub customer_id: Option<i32>,
-    pub created_at: Option<NaiveDateTime>,
+/// Isolation level of the write path. READ COMMITTED relies on the balance
+/// update CTE taking the row lock; SERIALIZABLE lets Postgres detect every
+/// anomaly at the cost of retrying the transactions it aborts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WriteIsolation {
+    ReadCommitted,
+    Serializable,
+}
+
+impl FromStr for WriteIsolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace(' ', "_").as_str() {
+            "read_committed" => Ok(WriteIsolation::ReadCommitted),
+            "serializable" => Ok(WriteIsolation::Serializable),
+            other => Err(format!("unknown write isolation level: {}", other)),
+        }
+    }
+}
+
+/// Pool a query can be routed to. Classes without a pool of their own fall
+/// back to the primary pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PoolClass {
+    Primary,
+    /// Read replica; reads routed there may lag behind the latest writes.
+    Replica,
+    /// Connections to the primary reserved for long-running reads.
+    Bulk,
+}
+
+impl FromStr for PoolClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "primary" => Ok(PoolClass::Primary),
+            "replica" => Ok(PoolClass::Replica),
+            "bulk" => Ok(PoolClass::Bulk),
+            other => Err(format!("unknown pool class: {}", other)),
+        }
+    }
+}
+
+/// Queries whose pool is picked by `DB_QUERY_ROUTES`. Writes aren't routable
+/// and always use the primary pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RoutedQuery {
+    /// The statement of the hot endpoint.
+    Statement,
+    /// Full history exports, as a JSON array or NDJSON.
+    History,
+    /// Single transactions and long-polled new ones.
+    Transactions,
+    /// Balance recomputation of the consistency check.
+    Consistency,
+    /// Filtered searches of a customer's transactions.
+    Search,
+}
+
+impl FromStr for RoutedQuery {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "statement" => Ok(RoutedQuery::Statement),
+            "history" => Ok(RoutedQuery::History),
+            "transactions" => Ok(RoutedQuery::Transactions),
+            "consistency" => Ok(RoutedQuery::Consistency),
+            "search" => Ok(RoutedQuery::Search),
+            other => Err(format!("unknown routed query: {}", other)),
+        }
+    }
+}
+
+/// The pools of every class, and which one each routed query uses.
+#[derive(Clone)]
+pub struct PoolRouter {
+    primary: sqlx::Pool<Postgres>,
+    pools: HashMap<PoolClass, sqlx::Pool<Postgres>>,
+    routes: HashMap<RoutedQuery, PoolClass>,
 }
 
+impl PoolRouter {
+    pub fn new(primary: sqlx::Pool<Postgres>, routes: HashMap<RoutedQuery, PoolClass>) -> PoolRouter {
+        PoolRouter {
+            primary,
+            pools: HashMap::new(),
+            routes,
+        }
+    }
+
+    pub fn with_pool(mut self, class: PoolClass, pool: sqlx::Pool<Postgres>) -> PoolRouter {
+        self.pools.insert(class, pool);
+        self
+    }
+
+    pub fn pool(&self, query: RoutedQuery) -> sqlx::Pool<Postgres> {
+        self.routes
+            .get(&query)
+            .and_then(|class| self.pools.get(class))
+            .unwrap_or(&self.primary)
+            .clone()
+    }
+
+    /// The primary pool, for reads that must see this instance's own commits
+    /// whatever the routes say.
+    pub fn primary(&self) -> sqlx::Pool<Postgres> {
+        self.primary.clone()
+    }
+}
+
+/// Customer row joined with its ten latest transactions, plus the time the
+/// snapshot was taken. Balance, transactions and timestamp all come from the
+/// same statement, hence the same MVCC snapshot, so they always agree.
+const STATEMENT_QUERY: &str = "
+		SELECT 
+            (now() AT TIME ZONE 'utc') as statement_date,
+            c.id as customer_id,
+            c.limit as customer_limit,
+            c.balance as customer_balance,
+            c.created_at as customer_created_at,
+            t.id as transaction_id,
+            t.value as transaction_value,
+            t.type as transaction_type,
+            t.description as transaction_description,
+            t.customer_id as transaction_customer_id,
+            t.created_at as transaction_created_at
+        FROM customers c
+		LEFT JOIN transactions t ON c.id=t.customer_id
+		WHERE c.id = $1
+		ORDER BY t.created_at DESC, t.id DESC
+		LIMIT 10
+	";
+
+/// Applies a signed value to the balance unless it would go past the limit,
+/// and stamps the creation time of the transaction behind it: the database's
+/// clock, but at least a microsecond after the customer's previous
+/// transaction. Taken under the row lock, so creation times follow the order
+/// balances were updated in. Always returns one row for existing customers:
+/// the limit and balance before the update, how many rows were updated (0 or
+/// 1) and the creation time when updated.
+const UPDATE_BALANCE_QUERY: &str = "
+		with
+			c AS (SELECT * FROM customers c WHERE id = $2),
+			u AS (
+				UPDATE customers c2 SET
+					balance = balance + $1,
+					last_transaction_at = GREATEST(
+						clock_timestamp(),
+						last_transaction_at + interval '1 microsecond'
+					)
+				WHERE id = $2 AND (balance + $1) >= -\"limit\"
+				RETURNING id, \"limit\", balance, last_transaction_at
+			),
+			cu AS (SELECT COUNT(*) FROM u)
+		SELECT c.limit, c.balance, cu.count as count_update, (SELECT last_transaction_at FROM u) AS created_at
+		FROM c, cu
+    ";
+
+const INSERT_TRANSACTION_QUERY: &str = "
+      INSERT INTO transactions (id, value, \"type\", description, customer_id, uuid, created_at)
+      VALUES (COALESCE($6, nextval('transactions_id_seq')), $1, $2, $3, $4, $5, $7)
+      RETURNING id, value, \"type\", description, customer_id, created_at, uuid
+    ";
+
+/// One row of `STATEMENT_QUERY`: the customer, the snapshot time and one of
+/// the customer's transactions, or no transaction at all when there are none.
 #[derive(sqlx::FromRow, Debug, Serialize, Deserialize)]
-struct GetCustomerStatementResult {
+pub struct GetCustomerStatementResult {
+    pub statement_date: NaiveDateTime,
     // customer data
     customer_id: i32,
     customer_limit: i32,
     customer_balance: i32,
     customer_created_at: NaiveDateTime,
     // transaction data
-    transaction_id: Option<i32>,
+    pub transaction_id: Option<i64>,
     transaction_value: Option<i32>,
     transaction_type: Option<String>,
     transaction_description: Option<String>,
     transaction_customer_id: Option<i32>,
-    transaction_created_at: Option<NaiveDateTime>,
+    transaction_created_at: Option<DateTime<Utc>>,
 }
 
 impl From<GetCustomerStatementResult> for Transaction {
@@ -45,6 +229,7 @@ impl From<GetCustomerStatementResult> for Transaction {
             description: customer_statement.transaction_description,
             customer_id: customer_statement.transaction_customer_id,
             created_at: customer_statement.transaction_created_at,
+            uuid: None,
         }
     }
 }
@@ -63,9 +248,61 @@ impl From<&GetCustomerStatementResult> for Customer {
 pub async fn get_statement_db(
     pool: sqlx::Pool<sqlx::Postgres>,
     id: i64,
-) -> Result<(Customer, Vec<Transaction>), errors::AppError> {
-    let query = "
-		SELECT 
+) -> Result<Vec<GetCustomerStatementResult>, errors::AppError> {
+    deadline::within(async move {
+        let mut conn = Abandonable::new(acquire(&pool).await?);
+        let rows = sqlx::query_as::<_, GetCustomerStatementResult>(STATEMENT_QUERY)
+            .bind(id)
+            .fetch_all(&mut *conn)
+            .await;
+        conn.release();
+        rows
+    })
+    .await
+}
+
+/// A customer's row alone, with the time it was read, for statements whose
+/// transactions come from elsewhere.
+#[cfg_attr(not(feature = "redis"), allow(dead_code))]
+pub async fn get_customer_db(
+    pool: sqlx::Pool<sqlx::Postgres>,
+    id: i64,
+) -> Result<Option<(Customer, NaiveDateTime)>, errors::AppError> {
+    let row: Option<(NaiveDateTime, i32, i32, i32, NaiveDateTime)> = deadline::within(async move {
+        let mut conn = Abandonable::new(acquire(&pool).await?);
+        let row = sqlx::query_as(
+            "SELECT (now() AT TIME ZONE 'utc'), id, \"limit\", balance, created_at FROM customers WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .await;
+        conn.release();
+        row
+    })
+    .await?;
+
+    Ok(row.map(|(taken_at, id, limit, balance, created_at)| {
+        (
+            Customer {
+                id,
+                limit,
+                balance,
+                created_at,
+            },
+            taken_at,
+        )
+    }))
+}
+
+/// Every customer with its ten latest transactions, in the same row shape as
+/// `STATEMENT_QUERY`, from a single snapshot.
+pub async fn get_all_statements_db(
+    conn: &mut PgConnection,
+) -> Result<Vec<GetCustomerStatementResult>, sqlx::Error> {
+    sqlx::query_as::<_, GetCustomerStatementResult>(
+        "
+        SELECT
+            (now() AT TIME ZONE 'utc') as statement_date,
             c.id as customer_id,
             c.limit as customer_limit,
             c.balance as customer_balance,
@@ -77,107 +314,1973 @@ pub async fn get_statement_db(
             t.customer_id as transaction_customer_id,
             t.created_at as transaction_created_at
         FROM customers c
-		LEFT JOIN transactions t ON c.id=t.customer_id
-		WHERE c.id = $1
-		ORDER BY t.created_at DESC
-		LIMIT 10
-	";
+        LEFT JOIN LATERAL (
+            SELECT * FROM transactions t
+            WHERE t.customer_id = c.id
+            ORDER BY t.created_at DESC, t.id DESC
+            LIMIT 10
+        ) t ON true
+        ORDER BY c.id, t.created_at DESC, t.id DESC
+        ",
+    )
+    .fetch_all(conn)
+    .await
+}
 
-    let statement_query_res = sqlx::query_as::<_, GetCustomerStatementResult>(query)
-        .bind(id)
-        .fetch_all(&pool)
-        .await?;
+pub async fn customer_exists_db(
+    pool: sqlx::Pool<sqlx::Postgres>,
+    id: i32,
+) -> Result<bool, errors::AppError> {
+    deadline::within(async move {
+        let mut conn = Abandonable::new(acquire(&pool).await?);
+        let exists = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM customers WHERE id = $1)")
+            .bind(id)
+            .fetch_one(&mut *conn)
+            .await;
+        conn.release();
+        exists
+    })
+    .await
+}
 
-    if statement_query_res.len() == 0 {
-        return Err(errors::AppError::ErrCustomerNotFound);
-    }
+/// Streams every transaction of a customer without buffering the result set,
+/// newest first unless `oldest_first` is set, archived ones included when
+/// `include_archived` is. The connection is held until the stream is
+/// exhausted or dropped.
+pub fn stream_customer_transactions_db(
+    pool: sqlx::Pool<sqlx::Postgres>,
+    customer_id: i32,
+    oldest_first: bool,
+    include_archived: bool,
+) -> impl Stream<Item = Result<Transaction, errors::AppError>> {
+    try_stream! {
+        let query = if oldest_first {
+            "
+            SELECT id, value, type, description, customer_id, created_at
+            FROM transactions
+            WHERE customer_id = $1
+            ORDER BY created_at ASC, id ASC
+            "
+        } else if include_archived {
+            "
+            SELECT id, value, type, description, customer_id, created_at
+            FROM transactions
+            WHERE customer_id = $1
+            UNION ALL
+            SELECT id, value, type, description, customer_id, created_at
+            FROM transactions_archive
+            WHERE customer_id = $1
+            ORDER BY created_at DESC, id DESC
+            "
+        } else {
+            "
+            SELECT id, value, type, description, customer_id, created_at
+            FROM transactions
+            WHERE customer_id = $1
+            ORDER BY created_at DESC, id DESC
+            "
+        };
 
-    let first_res = statement_query_res
-        .first()
-        .ok_or(errors::AppError::ErrCustomerNotFound)?;
-    let customer: Customer = Customer::from(first_res);
-    let mut txs: Vec<Transaction> = vec![];
-    if statement_query_res.len() >= 1 {
-        let fst = statement_query_res.first().unwrap();
-        if fst.transaction_id.is_some() {
-            txs = statement_query_res
-                .into_iter()
-                .map(Transaction::from)
-                .collect();
+        let mut conn = acquire(&pool).await?;
+        let mut rows = sqlx::query_as::<_, Transaction>(query)
+            .bind(customer_id)
+            .fetch(&mut *conn);
+
+        while let Some(tx) = rows.try_next().await? {
+            yield tx;
         }
     }
+}
+
+/// A page of a customer's transactions, newest first, and how many there are
+/// in total. `after` is the id of the last transaction of the previous page.
+pub async fn page_customer_transactions_db(
+    pool: sqlx::Pool<sqlx::Postgres>,
+    customer_id: i32,
+    include_archived: bool,
+    limit: i64,
+    offset: i64,
+    after: Option<i64>,
+) -> Result<(Vec<Transaction>, i64), errors::AppError> {
+    let history = "
+        WITH history AS (
+            SELECT id, value, type, description, customer_id, created_at
+            FROM transactions
+            WHERE customer_id = $1
+            UNION ALL
+            SELECT id, value, type, description, customer_id, created_at
+            FROM transactions_archive
+            WHERE customer_id = $1 AND $2
+        )
+    ";
+    let page_query = format!(
+        "{}
+        SELECT id, value, type, description, customer_id, created_at
+        FROM history
+        WHERE $3::BIGINT IS NULL
+            OR (created_at, id) < (SELECT created_at, id FROM history WHERE id = $3)
+        ORDER BY created_at DESC, id DESC
+        LIMIT $4 OFFSET $5
+        ",
+        history
+    );
+    let total_query = format!("{} SELECT COUNT(*) FROM history", history);
+
+    deadline::within(async move {
+        let mut conn = Abandonable::new(acquire(&pool).await?);
+        let page = async {
+            let txs = sqlx::query_as::<_, Transaction>(&page_query)
+                .bind(customer_id)
+                .bind(include_archived)
+                .bind(after)
+                .bind(limit)
+                .bind(offset)
+                .fetch_all(&mut *conn)
+                .await?;
+            let total = sqlx::query_scalar(&total_query)
+                .bind(customer_id)
+                .bind(include_archived)
+                .fetch_one(&mut *conn)
+                .await?;
+            Ok::<_, sqlx::Error>((txs, total))
+        }
+        .await;
+        conn.release();
+        page
+    })
+    .await
+}
+
+/// Filters of a transaction search; unset ones match everything.
+#[derive(Debug)]
+pub struct TransactionFilter {
+    pub tx_type: Option<String>,
+    pub min_value: Option<i32>,
+    pub max_value: Option<i32>,
+    /// Inclusive.
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive.
+    pub until: Option<DateTime<Utc>>,
+    /// Matched case-insensitively anywhere in the description.
+    pub description: Option<String>,
+}
+
+/// A page of a customer's transactions matching `filter`, newest first, and
+/// how many match in total. `after` is the id of the last transaction of the
+/// previous page; `None` when it isn't one of the customer's. Archived
+/// transactions aren't searched.
+pub async fn search_customer_transactions_db(
+    pool: sqlx::Pool<sqlx::Postgres>,
+    customer_id: i32,
+    filter: &TransactionFilter,
+    limit: i64,
+    offset: i64,
+    after: Option<i64>,
+) -> Result<Option<(Vec<Transaction>, i64)>, errors::AppError> {
+    let matches = "
+        WITH matches AS (
+            SELECT id, value, type, description, customer_id, created_at
+            FROM transactions
+            WHERE customer_id = $1
+                AND ($2::TEXT IS NULL OR type = $2)
+                AND ($3::INTEGER IS NULL OR value >= $3)
+                AND ($4::INTEGER IS NULL OR value <= $4)
+                AND ($5::TIMESTAMPTZ IS NULL OR created_at >= $5)
+                AND ($6::TIMESTAMPTZ IS NULL OR created_at < $6)
+                AND ($7::TEXT IS NULL OR description ILIKE $7)
+        )
+    ";
+    let page_query = format!(
+        "{}
+        SELECT id, value, type, description, customer_id, created_at
+        FROM matches
+        WHERE $8::TIMESTAMPTZ IS NULL OR (created_at, id) < ($8, $9)
+        ORDER BY created_at DESC, id DESC
+        LIMIT $10 OFFSET $11
+        ",
+        matches
+    );
+    let total_query = format!("{} SELECT COUNT(*) FROM matches", matches);
+    // `%`, `_` and the escape character itself are literals in the search.
+    let pattern = filter.description.as_ref().map(|description| {
+        let escaped = description
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        format!("%{}%", escaped)
+    });
+
+    deadline::within(async move {
+        let mut conn = Abandonable::new(acquire(&pool).await?);
+        let page = async {
+            let after = match after {
+                Some(after_id) => {
+                    let created_at: Option<DateTime<Utc>> = sqlx::query_scalar(
+                        "SELECT created_at FROM transactions WHERE customer_id = $1 AND id = $2",
+                    )
+                    .bind(customer_id)
+                    .bind(after_id)
+                    .fetch_optional(&mut *conn)
+                    .await?;
+                    match created_at {
+                        Some(created_at) => Some((created_at, after_id)),
+                        None => return Ok(None),
+                    }
+                }
+                None => None,
+            };
+
+            let txs = sqlx::query_as::<_, Transaction>(&page_query)
+                .bind(customer_id)
+                .bind(&filter.tx_type)
+                .bind(filter.min_value)
+                .bind(filter.max_value)
+                .bind(filter.from)
+                .bind(filter.until)
+                .bind(&pattern)
+                .bind(after.map(|(created_at, _)| created_at))
+                .bind(after.map(|(_, id)| id))
+                .bind(limit)
+                .bind(offset)
+                .fetch_all(&mut *conn)
+                .await?;
+            let total = sqlx::query_scalar(&total_query)
+                .bind(customer_id)
+                .bind(&filter.tx_type)
+                .bind(filter.min_value)
+                .bind(filter.max_value)
+                .bind(filter.from)
+                .bind(filter.until)
+                .bind(&pattern)
+                .fetch_one(&mut *conn)
+                .await?;
+            Ok::<_, sqlx::Error>(Some((txs, total)))
+        }
+        .await;
+        conn.release();
+        page
+    })
+    .await
+}
+
+/// Transactions of a customer created after the one with id `after_id`, or
+/// all of them when it's 0, oldest first. They're ordered by creation time,
+/// stamped under the customer's row lock, which unlike app-assigned ids
+/// follows the order they were committed in. `None` when the customer has no
+/// transaction `after_id` in this database.
+pub async fn get_transactions_after_db(
+    pool: sqlx::Pool<sqlx::Postgres>,
+    customer_id: i32,
+    after_id: i64,
+    limit: i64,
+) -> Result<Option<Vec<Transaction>>, errors::AppError> {
+    let query = "
+        SELECT id, value, type, description, customer_id, created_at
+        FROM transactions
+        WHERE customer_id = $1 AND ($2::TIMESTAMPTZ IS NULL OR (created_at, id) > ($2, $3))
+        ORDER BY created_at ASC, id ASC
+        LIMIT $4
+    ";
+
+    deadline::within(async move {
+        let mut conn = Abandonable::new(acquire(&pool).await?);
+        let txs = async {
+            let after: Option<DateTime<Utc>> = if after_id == 0 {
+                None
+            } else {
+                let after = sqlx::query_scalar(
+                    "SELECT created_at FROM transactions WHERE customer_id = $1 AND id = $2",
+                )
+                .bind(customer_id)
+                .bind(after_id)
+                .fetch_optional(&mut *conn)
+                .await?;
+                match after {
+                    Some(after) => Some(after),
+                    None => return Ok(None),
+                }
+            };
+
+            sqlx::query_as::<_, Transaction>(query)
+                .bind(customer_id)
+                .bind(after)
+                .bind(after_id)
+                .bind(limit)
+                .fetch_all(&mut *conn)
+                .await
+                .map(Some)
+        }
+        .await;
+        conn.release();
+        txs
+    })
+    .await
+}
 
-    Ok((customer, txs))
+/// How a transaction is referred to: its primary key or its external uuid.
+pub enum TransactionRef {
+    Id(i64),
+    Uuid(Uuid),
 }
 
+pub async fn get_transaction_db(
+    pool: sqlx::Pool<sqlx::Postgres>,
+    customer_id: i32,
+    transaction: TransactionRef,
+) -> Result<Transaction, errors::AppError> {
+    let query = match transaction {
+        TransactionRef::Id(id) => sqlx::query_as::<_, Transaction>(
+            "
+            SELECT id, value, type, description, customer_id, created_at, uuid
+            FROM transactions
+            WHERE customer_id = $1 AND id = $2
+            ",
+        )
+        .bind(customer_id)
+        .bind(id),
+        TransactionRef::Uuid(uuid) => sqlx::query_as::<_, Transaction>(
+            "
+            SELECT id, value, type, description, customer_id, created_at, uuid
+            FROM transactions
+            WHERE customer_id = $1 AND uuid = $2
+            ",
+        )
+        .bind(customer_id)
+        .bind(uuid),
+    };
+
+    deadline::within(async move {
+        let mut conn = Abandonable::new(acquire(&pool).await?);
+        let tx = query.fetch_optional(&mut *conn).await;
+        conn.release();
+        tx
+    })
+    .await?
+    .ok_or(errors::AppError::ErrTransactionNotFound)
+}
+
+/// Work committed atomically with every new transaction, besides the balance
+/// update and the insert itself.
+#[derive(Debug, Clone, Copy)]
+pub struct SideEffects {
+    /// Write a `TransactionCreated` event to the outbox.
+    pub outbox: bool,
+    /// Enqueue one delivery job per active webhook of the customer, with this
+    /// many attempts.
+    pub webhook_max_attempts: Option<i32>,
+}
+
+/// Writes `new_tx` and applies `balance_delta` to the customer's balance,
+/// unless that would take it below the customer's limit. The row's id, when
+/// generated in the app, comes from `keys`. Returns the
+/// limit, the new balance and the inserted row. The request's deadline is
+/// enforced up to the COMMIT, not past it.
 pub async fn create_customer_transaction_db(
     pool: sqlx::Pool<sqlx::Postgres>,
+    new_tx: NewTransaction,
+    balance_delta: i64,
+    side_effects: SideEffects,
+    keys: &ids::TransactionKeys,
+    policy: &config::WriteConfig,
+    metrics: &metrics::Metrics,
+) -> Result<(i64, i64, Transaction), errors::AppError> {
+    let mut conn = Abandonable::new(deadline::within(acquire(&pool)).await?);
+    let id = keys.next();
+
+    let mut attempt = 1;
+    loop {
+        let result = try_create_customer_transaction(
+            &mut conn,
+            &new_tx,
+            id,
+            balance_delta,
+            side_effects,
+            policy.isolation,
+        )
+        .await;
+
+        let reason = match &result {
+            Err(errors::AppError::SQLError(err)) if attempt < policy.max_attempts => {
+                retry_reason(err, policy.isolation)
+            }
+            _ => None,
+        };
+
+        match reason {
+            Some(reason) => {
+                metrics.record_write_retry(reason);
+                deadline::within(async {
+                    tokio::time::sleep(retry_backoff(policy.retry_base_ms, attempt)).await;
+                    Ok::<_, errors::AppError>(())
+                })
+                .await?;
+                attempt += 1;
+            }
+            // Abandoned halfway, the connection may still be busy.
+            None if matches!(result, Err(errors::AppError::ErrDeadlineExceeded)) => return result,
+            None => {
+                conn.release();
+                return result;
+            }
+        }
+    }
+}
+
+/// Deadlock victims and, under SERIALIZABLE, serialization failures were
+/// rolled back by Postgres as a whole, so the transaction can simply be run
+/// again.
+fn retry_reason(err: &sqlx::Error, isolation: WriteIsolation) -> Option<RetryReason> {
+    let code = match err {
+        sqlx::Error::Database(db_err) => db_err.code(),
+        _ => None,
+    };
+    match code.as_deref() {
+        Some(DEADLOCK_DETECTED) => Some(RetryReason::Deadlock),
+        Some(SERIALIZATION_FAILURE) if isolation == WriteIsolation::Serializable => {
+            Some(RetryReason::SerializationFailure)
+        }
+        _ => None,
+    }
+}
+
+/// Exponential backoff with full jitter on top, so transactions that
+/// conflicted once don't collide again on the next attempt.
+fn retry_backoff(base_ms: u64, attempt: u32) -> Duration {
+    let exponential = base_ms.saturating_mul(1 << attempt.min(10));
+    let jitter = rand::thread_rng().gen_range(0..=base_ms.max(1));
+    Duration::from_millis(exponential + jitter)
+}
+
+async fn try_create_customer_transaction(
+    conn: &mut sqlx::PgConnection,
+    new_tx: &NewTransaction,
+    id: Option<i64>,
+    balance_delta: i64,
+    side_effects: SideEffects,
+    isolation: WriteIsolation,
+) -> Result<(i64, i64, Transaction), errors::AppError> {
+    let NewTransaction {
+        customer_id,
+        value,
+        tx_type,
+        description,
+    } = new_tx;
+    let (customer_id, value) = (*customer_id, *value);
+    let mut tx = deadline::within(conn.begin()).await?;
+
+    let (limit, new_total, created) = deadline::within(async {
+        if isolation == WriteIsolation::Serializable {
+            sqlx::query("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let (limit, total, update_count, created_at): (i32, i32, i64, Option<DateTime<Utc>>) =
+            sqlx::query_as(UPDATE_BALANCE_QUERY)
+            .bind(balance_delta)
+            .bind(customer_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|err| match err {
+                sqlx::Error::RowNotFound => errors::AppError::ErrCustomerNotFound,
+                _ => err.into(),
+            })?;
+
+        let (1, Some(created_at)) = (update_count, created_at) else {
+            return Err(errors::AppError::ErrNegativeTransactionBalance);
+        };
+
+        let created = sqlx::query_as::<_, Transaction>(INSERT_TRANSACTION_QUERY)
+            .bind(value)
+            .bind(tx_type)
+            .bind(description)
+            .bind(customer_id)
+            .bind(Uuid::new_v4())
+            .bind(id)
+            .bind(created_at)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let new_total = (total as i64) + balance_delta;
+        record_side_effects(&mut tx, side_effects, &created, new_total).await?;
+
+        Ok::<_, errors::AppError>((limit, new_total, created))
+    })
+    .await?;
+
+    // The deadline no longer applies: once COMMIT is sent the write may take
+    // effect whatever becomes of the request, so the client has to hear how
+    // it ended rather than a timeout.
+    tx.commit().await?;
+
+    Ok((limit as i64, new_total, created))
+}
+
+/// Writes the outbox event and webhook deliveries for a transaction just
+/// inserted, leaving the customer's balance at `balance`.
+async fn record_side_effects(
+    conn: &mut PgConnection,
+    side_effects: SideEffects,
+    created: &Transaction,
+    balance: i64,
+) -> Result<(), errors::AppError> {
+    let customer_id = created.customer_id.unwrap_or_default();
+    let event = events::TransactionCreated {
+        customer_id,
+        value: created.value.unwrap_or_default(),
+        tx_type: created.tx_type.clone().unwrap_or_default(),
+        balance,
+    };
+
+    if side_effects.outbox {
+        sqlx::query("INSERT INTO outbox (payload) VALUES ($1)")
+            .bind(Json(&event))
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    if let Some(max_attempts) = side_effects.webhook_max_attempts {
+        let enqueue_deliveries = "
+            INSERT INTO jobs (kind, payload, max_attempts)
+            SELECT $1, jsonb_build_object('webhook_id', w.id, 'url', w.url, 'evento', $2::jsonb), $3
+            FROM webhooks w
+            WHERE w.customer_id = $4 AND w.active
+              AND (w.tx_types IS NULL OR $5 = ANY (w.tx_types))
+        ";
+        sqlx::query(enqueue_deliveries)
+            .bind(webhooks::DELIVERY_JOB_KIND)
+            .bind(Json(&event))
+            .bind(max_attempts)
+            .bind(customer_id)
+            .bind(&event.tx_type)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Outcome of one transaction of a group commit: the limit, the new balance
+/// and the inserted row, as for `create_customer_transaction_db`.
+pub type GroupOutcome = Result<(i64, i64, Transaction), errors::AppError>;
+
+/// A customer row locked by a group commit, with the database's clock once
+/// the lock was granted.
+#[derive(sqlx::FromRow)]
+struct LockedAccount {
+    id: i32,
+    limit: i32,
+    balance: i32,
+    last_transaction_at: Option<DateTime<Utc>>,
+    locked_at: DateTime<Utc>,
+}
+
+/// Applies a group of transactions in a single database transaction: the
+/// customers involved are locked once, limits are checked here in arrival
+/// order, and the accepted transactions are inserted with one multi-row
+/// statement. Fails as a whole only on database errors; rejected transactions
+/// get their own error in the returned outcomes, in input order. Ids come from
+/// `keys` as for `create_customer_transaction_db`, and creation times from the
+/// database's clock and the locked rows, as for `UPDATE_BALANCE_QUERY`. The
+/// group runs at `policy`'s isolation level and is retried on conflicts like
+/// a single transaction. The current deadline is enforced up to the COMMIT.
+pub async fn create_transactions_group_db(
+    pool: &sqlx::Pool<Postgres>,
+    group: &[(NewTransaction, i64)],
+    side_effects: SideEffects,
+    keys: &ids::TransactionKeys,
+    policy: &config::WriteConfig,
+    metrics: &metrics::Metrics,
+) -> Result<Vec<GroupOutcome>, errors::AppError> {
+    let mut conn = Abandonable::new(deadline::within(acquire(pool)).await?);
+
+    let mut attempt = 1;
+    loop {
+        let result =
+            try_create_transactions_group(&mut conn, group, side_effects, keys, policy.isolation).await;
+
+        let reason = match &result {
+            Err(errors::AppError::SQLError(err)) if attempt < policy.max_attempts => {
+                retry_reason(err, policy.isolation)
+            }
+            _ => None,
+        };
+
+        match reason {
+            Some(reason) => {
+                metrics.record_write_retry(reason);
+                deadline::within(async {
+                    tokio::time::sleep(retry_backoff(policy.retry_base_ms, attempt)).await;
+                    Ok::<_, errors::AppError>(())
+                })
+                .await?;
+                attempt += 1;
+            }
+            // Abandoned halfway, the connection may still be busy.
+            None if matches!(result, Err(errors::AppError::ErrDeadlineExceeded)) => return result,
+            None => {
+                conn.release();
+                return result;
+            }
+        }
+    }
+}
+
+async fn try_create_transactions_group(
+    conn: &mut PgConnection,
+    group: &[(NewTransaction, i64)],
+    side_effects: SideEffects,
+    keys: &ids::TransactionKeys,
+    isolation: WriteIsolation,
+) -> Result<Vec<GroupOutcome>, errors::AppError> {
+    let mut tx = deadline::within(conn.begin()).await?;
+
+    let outcomes = deadline::within(async {
+        if isolation == WriteIsolation::Serializable {
+            sqlx::query("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let mut customer_ids: Vec<i32> = group.iter().map(|(new_tx, _)| new_tx.customer_id).collect();
+        customer_ids.sort_unstable();
+        customer_ids.dedup();
+        // Locking in id order keeps concurrent groups from deadlocking.
+        let rows: Vec<LockedAccount> = sqlx::query_as(
+            "
+            SELECT id, \"limit\", balance, last_transaction_at, clock_timestamp() AS locked_at
+            FROM customers WHERE id = ANY($1) ORDER BY id FOR UPDATE
+            ",
+        )
+        .bind(&customer_ids)
+        .fetch_all(&mut *tx)
+        .await?;
+        let now = rows.iter().map(|row| row.locked_at).max().unwrap_or_else(Utc::now);
+        let mut last_created: HashMap<i32, Option<DateTime<Utc>>> =
+            rows.iter().map(|row| (row.id, row.last_transaction_at)).collect();
+        let mut accounts: HashMap<i32, (i64, i64)> = rows
+            .into_iter()
+            .map(|row| (row.id, (row.limit as i64, row.balance as i64)))
+            .collect();
+
+        let mut outcomes: Vec<Option<GroupOutcome>> = Vec::with_capacity(group.len());
+        // Index in `group`, limit and balance right after the transaction.
+        let mut accepted: Vec<(usize, i64, i64)> = Vec::new();
+        for (i, (new_tx, delta)) in group.iter().enumerate() {
+            let Some((limit, balance)) = accounts.get_mut(&new_tx.customer_id) else {
+                outcomes.push(Some(Err(errors::AppError::ErrCustomerNotFound)));
+                continue;
+            };
+            if !domain::within_limit(*balance, *limit, *delta) {
+                outcomes.push(Some(Err(errors::AppError::ErrNegativeTransactionBalance)));
+                continue;
+            }
+            *balance += delta;
+            accepted.push((i, *limit, *balance));
+            outcomes.push(None);
+        }
+
+        if !accepted.is_empty() {
+            let txs: Vec<&NewTransaction> = accepted.iter().map(|(i, _, _)| &group[*i].0).collect();
+            let tx_ids: Vec<Option<i64>> = txs.iter().map(|_| keys.next()).collect();
+            let created_ats: Vec<DateTime<Utc>> = txs
+                .iter()
+                .map(|tx| {
+                    let last = last_created.entry(tx.customer_id).or_default();
+                    let stamp = match *last {
+                        Some(previous) if previous >= now => previous + chrono::Duration::microseconds(1),
+                        _ => now,
+                    };
+                    *last = Some(stamp);
+                    stamp
+                })
+                .collect();
+            let mut inserted = sqlx::query_as::<_, Transaction>(
+                "
+                INSERT INTO transactions (id, value, \"type\", description, customer_id, uuid, created_at)
+                SELECT COALESCE(id, nextval('transactions_id_seq')), value, tx_type, description, customer_id, uuid, created_at
+                FROM UNNEST(
+                    $1::INTEGER[], $2::TEXT[], $3::TEXT[], $4::INTEGER[], $5::UUID[], $6::BIGINT[], $7::TIMESTAMPTZ[]
+                ) WITH ORDINALITY AS t(value, tx_type, description, customer_id, uuid, id, created_at, n)
+                ORDER BY n
+                RETURNING id, value, \"type\", description, customer_id, created_at, uuid
+                ",
+            )
+            .bind(txs.iter().map(|tx| tx.value).collect::<Vec<_>>())
+            .bind(txs.iter().map(|tx| tx.tx_type.clone()).collect::<Vec<_>>())
+            .bind(txs.iter().map(|tx| tx.description.clone()).collect::<Vec<_>>())
+            .bind(txs.iter().map(|tx| tx.customer_id).collect::<Vec<_>>())
+            .bind(txs.iter().map(|_| Uuid::new_v4()).collect::<Vec<_>>())
+            .bind(tx_ids)
+            .bind(created_ats)
+            .fetch_all(&mut *tx)
+            .await?;
+            // Ids follow the insertion order, RETURNING isn't guaranteed to.
+            inserted.sort_by_key(|row| row.id);
+
+            let (ids, balances): (Vec<i32>, Vec<i32>) = accounts
+                .iter()
+                .map(|(id, (_, balance))| (*id, *balance as i32))
+                .unzip();
+            let last_created: Vec<Option<DateTime<Utc>>> = ids.iter().map(|id| last_created[id]).collect();
+            sqlx::query(
+                "
+                UPDATE customers c SET balance = u.balance, last_transaction_at = u.last_transaction_at
+                FROM UNNEST($1::INTEGER[], $2::INTEGER[], $3::TIMESTAMPTZ[]) AS u(id, balance, last_transaction_at)
+                WHERE c.id = u.id
+                    AND (c.balance <> u.balance OR c.last_transaction_at IS DISTINCT FROM u.last_transaction_at)
+                ",
+            )
+            .bind(ids)
+            .bind(balances)
+            .bind(last_created)
+            .execute(&mut *tx)
+            .await?;
+
+            for ((i, limit, balance), created) in accepted.into_iter().zip(inserted) {
+                record_side_effects(&mut tx, side_effects, &created, balance).await?;
+                outcomes[i] = Some(Ok((limit, balance, created)));
+            }
+        }
+
+        Ok::<_, errors::AppError>(outcomes)
+    })
+    .await?;
+
+    // As in `try_create_customer_transaction`, the deadline stops at the
+    // COMMIT.
+    tx.commit().await?;
+    Ok(outcomes.into_iter().map(|outcome| outcome.expect("every transaction has an outcome")).collect())
+}
+
+/// Checks a connection out of the pool, recording how long the caller had to
+/// wait for it.
+async fn acquire(
+    pool: &sqlx::Pool<Postgres>,
+) -> Result<PoolConnection<Postgres>, sqlx::Error> {
+    let started = Instant::now();
+    let conn = pool.acquire().await;
+    context::record_pool_wait(started.elapsed());
+    conn
+}
+
+/// Pooled connection for work a request may abandon halfway. Dropped before
+/// `release`, it is closed instead of going back to the pool, where it would
+/// stay busy until Postgres finished the abandoned query; Postgres stops the
+/// query and rolls back its transaction once it notices the client gone.
+struct Abandonable(Option<PoolConnection<Postgres>>);
+
+impl Abandonable {
+    fn new(conn: PoolConnection<Postgres>) -> Abandonable {
+        Abandonable(Some(conn))
+    }
+
+    /// Returns the connection to the pool, once nothing is in flight on it.
+    fn release(mut self) {
+        self.0.take();
+    }
+}
+
+impl Deref for Abandonable {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &PgConnection {
+        self.0.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for Abandonable {
+    fn deref_mut(&mut self) -> &mut PgConnection {
+        self.0.as_mut().unwrap()
+    }
+}
+
+impl Drop for Abandonable {
+    fn drop(&mut self) {
+        if let Some(conn) = self.0.take() {
+            // Detaching lets the pool open a replacement; dropping the
+            // connection closes its socket.
+            drop(conn.detach());
+        }
+    }
+}
+
+/// Moves up to `batch_size` transactions created more than `retention_days`
+/// ago to `transactions_archive`, returning how many were moved per customer. Rows locked
+/// by a concurrent run are skipped, so every instance can run this.
+pub async fn archive_transactions_db(
+    pool: &sqlx::Pool<Postgres>,
+    retention_days: u32,
+    batch_size: i64,
+) -> Result<Vec<(i32, i64)>, errors::AppError> {
+    let query = "
+        WITH moved AS (
+            DELETE FROM transactions
+            WHERE id IN (
+                SELECT id FROM transactions
+                WHERE created_at < now() - make_interval(days => $1)
+                ORDER BY created_at
+                LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, value, \"type\", description, customer_id, created_at, uuid
+        ),
+        archived AS (
+            INSERT INTO transactions_archive (id, value, \"type\", description, customer_id, created_at, uuid)
+            SELECT id, value, \"type\", description, customer_id, created_at, uuid FROM moved
+            RETURNING customer_id
+        )
+        SELECT customer_id, COUNT(*) FROM archived GROUP BY customer_id
+    ";
+
+    let moved: Vec<(i32, i64)> = sqlx::query_as(query)
+        .bind(retention_days as i32)
+        .bind(batch_size)
+        .fetch_all(&mut *acquire(pool).await?)
+        .await?;
+    Ok(moved)
+}
+
+/// Last day with end-of-day balances written, if any.
+pub async fn last_closed_day_db(pool: &sqlx::Pool<Postgres>) -> Result<Option<NaiveDate>, errors::AppError> {
+    let day = sqlx::query_scalar("SELECT MAX(day) FROM daily_balances")
+        .fetch_one(&mut *acquire(pool).await?)
+        .await?;
+    Ok(day)
+}
+
+/// Writes the balance every customer existing by the end of `day` (UTC) had
+/// then: the current balance minus everything transacted since. Read from a
+/// single snapshot, so writes in flight can't skew it. Days already closed
+/// are left as they are. Returns how many balances were written.
+pub async fn close_day_db(pool: &sqlx::Pool<Postgres>, day: NaiveDate) -> Result<u64, errors::AppError> {
+    let query = "
+        INSERT INTO daily_balances (customer_id, day, balance)
+        SELECT c.id, $1, c.balance - COALESCE(later.total, 0)
+        FROM customers c
+        LEFT JOIN (
+            SELECT customer_id, SUM(CASE WHEN type = 'c' THEN value ELSE -value END) AS total
+            FROM (
+                SELECT customer_id, type, value FROM transactions
+                WHERE created_at >= ($1 + 1)::TIMESTAMP AT TIME ZONE 'UTC'
+                UNION ALL
+                SELECT customer_id, type, value FROM transactions_archive
+                WHERE created_at >= ($1 + 1)::TIMESTAMP AT TIME ZONE 'UTC'
+            ) t
+            GROUP BY customer_id
+        ) later ON later.customer_id = c.id
+        WHERE c.created_at < ($1 + 1)::TIMESTAMP
+        ON CONFLICT (customer_id, day) DO NOTHING
+    ";
+
+    let result = sqlx::query(query)
+        .bind(day)
+        .execute(&mut *acquire(pool).await?)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// A customer's end-of-day balances from `from` to `until`, both inclusive,
+/// oldest first.
+pub async fn get_daily_balances_db(
+    pool: sqlx::Pool<Postgres>,
+    customer_id: i32,
+    from: NaiveDate,
+    until: NaiveDate,
+) -> Result<Vec<(NaiveDate, i32)>, errors::AppError> {
+    let query = "
+        SELECT day, balance
+        FROM daily_balances
+        WHERE customer_id = $1 AND day BETWEEN $2 AND $3
+        ORDER BY day
+    ";
+
+    deadline::within(async move {
+        let mut conn = Abandonable::new(acquire(&pool).await?);
+        let rows = sqlx::query_as(query)
+            .bind(customer_id)
+            .bind(from)
+            .bind(until)
+            .fetch_all(&mut *conn)
+            .await;
+        conn.release();
+        rows
+    })
+    .await
+}
+
+/// Every customer's stored balance next to the one computed from its
+/// transactions, archived ones included, as `(id, stored, computed)`. Read
+/// from a single snapshot, so writes in flight don't show up as drift.
+pub async fn balances_db(pool: &sqlx::Pool<Postgres>) -> Result<Vec<(i32, i64, i64)>, errors::AppError> {
+    let query = "
+        SELECT c.id, c.balance::BIGINT, (COALESCE(t.total, 0) + COALESCE(a.total, 0))::BIGINT
+        FROM customers c
+        LEFT JOIN (
+            SELECT customer_id, SUM(CASE WHEN type = 'c' THEN value ELSE -value END) AS total
+            FROM transactions
+            GROUP BY customer_id
+        ) t ON t.customer_id = c.id
+        LEFT JOIN (
+            SELECT customer_id, SUM(CASE WHEN type = 'c' THEN value ELSE -value END) AS total
+            FROM transactions_archive
+            GROUP BY customer_id
+        ) a ON a.customer_id = c.id
+        ORDER BY c.id
+    ";
+
+    let balances = sqlx::query_as(query)
+        .fetch_all(&mut *acquire(pool).await?)
+        .await?;
+    Ok(balances)
+}
+
+/// Sets a customer's balance to the one computed from its transactions, if
+/// they differ, and audits the correction. The customer row is locked, which
+/// holds off writes to it until the correction commits, and an advisory lock
+/// keeps concurrent repairs of the same customer apart. Returns the stored
+/// and the corrected balance when there was drift.
+pub async fn repair_balance_db(
+    pool: &sqlx::Pool<Postgres>,
     customer_id: i32,
-    value: i32,
-    tx_type: String,
-    description: String,
-) -> Result<(i64, i64), errors::AppError> {
-    // TODO -> add rollbacks if needed
+    request_id: Option<&str>,
+) -> Result<Option<(i64, i64)>, errors::AppError> {
+    let mut conn = acquire(pool).await?;
+    let mut tx = conn.begin().await?;
+
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext('balance_repair'), $1)")
+        .bind(customer_id)
+        .execute(&mut *tx)
+        .await?;
+    let balance: Option<i32> = sqlx::query_scalar("SELECT balance FROM customers WHERE id = $1 FOR UPDATE")
+        .bind(customer_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let Some(balance) = balance else {
+        return Ok(None);
+    };
+    // Runs after the lock is granted, so it sees every write committed
+    // before it.
+    let computed: i64 = sqlx::query_scalar(
+        "
+        SELECT COALESCE(SUM(CASE WHEN type = 'c' THEN value ELSE -value END), 0)::BIGINT
+        FROM (
+            SELECT type, value FROM transactions WHERE customer_id = $1
+            UNION ALL
+            SELECT type, value FROM transactions_archive WHERE customer_id = $1
+        ) t
+        ",
+    )
+    .bind(customer_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    if computed == balance as i64 {
+        return Ok(None);
+    }
+
+    sqlx::query("UPDATE customers SET balance = $2 WHERE id = $1")
+        .bind(customer_id)
+        .bind(computed as i32)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "
+        INSERT INTO audit_log (action, customer_id, details)
+        VALUES ('balance_repaired', $1, jsonb_build_object('saldo', $2::BIGINT, 'saldo_calculado', $3::BIGINT, 'request_id', $4::TEXT))
+        ",
+    )
+    .bind(customer_id)
+    .bind(balance as i64)
+    .bind(computed)
+    .bind(request_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(Some((balance as i64, computed)))
+}
+
+/// Description left on the transactions of an anonymized customer.
+const ANONYMIZED_DESCRIPTION: &str = "***";
+
+/// Scrubs the descriptions of every transaction of a customer, archived ones
+/// included, marks the customer anonymized and records it in the audit log,
+/// all at once. Amounts and balances are left untouched. Returns when the
+/// customer was first anonymized and how many transactions were scrubbed now,
+/// or `None` when the customer doesn't exist.
+pub async fn anonymize_customer_db(
+    pool: &sqlx::Pool<Postgres>,
+    customer_id: i32,
+    request_id: Option<&str>,
+) -> Result<Option<(DateTime<Utc>, u64)>, errors::AppError> {
+    let mut conn = acquire(pool).await?;
+    let mut tx = conn.begin().await?;
+
+    let anonymized_at: Option<DateTime<Utc>> = sqlx::query_scalar(
+        "UPDATE customers SET anonymized_at = COALESCE(anonymized_at, now()) WHERE id = $1 RETURNING anonymized_at",
+    )
+    .bind(customer_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(anonymized_at) = anonymized_at else {
+        return Ok(None);
+    };
+
+    let mut scrubbed = 0;
+    for table in ["transactions", "transactions_archive"] {
+        scrubbed += sqlx::query(&format!(
+            "UPDATE {} SET description = $2 WHERE customer_id = $1 AND description <> $2",
+            table
+        ))
+        .bind(customer_id)
+        .bind(ANONYMIZED_DESCRIPTION)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    sqlx::query(
+        "
+        INSERT INTO audit_log (action, customer_id, details)
+        VALUES ('customer_anonymized', $1, jsonb_build_object('transacoes', $2::BIGINT, 'request_id', $3::TEXT))
+        ",
+    )
+    .bind(customer_id)
+    .bind(scrubbed as i64)
+    .bind(request_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(Some((anonymized_at, scrubbed)))
+}
+
+/// A page of customers by id, and how many there are in total. `after` is
+/// the id of the last customer of the previous page.
+pub async fn page_customers_db(
+    pool: sqlx::Pool<Postgres>,
+    limit: i64,
+    offset: i64,
+    after: Option<i64>,
+) -> Result<(Vec<Customer>, i64), errors::AppError> {
+    let query = "
+        SELECT id, \"limit\", balance, created_at
+        FROM customers
+        WHERE $1::BIGINT IS NULL OR id > $1
+        ORDER BY id
+        LIMIT $2 OFFSET $3
+    ";
+
+    let (rows, total): (Vec<(i32, i32, i32, NaiveDateTime)>, i64) = deadline::within(async move {
+        let mut conn = Abandonable::new(acquire(&pool).await?);
+        let page = async {
+            let rows = sqlx::query_as(query)
+                .bind(after)
+                .bind(limit)
+                .bind(offset)
+                .fetch_all(&mut *conn)
+                .await?;
+            let total = sqlx::query_scalar("SELECT COUNT(*) FROM customers")
+                .fetch_one(&mut *conn)
+                .await?;
+            Ok::<_, sqlx::Error>((rows, total))
+        }
+        .await;
+        conn.release();
+        page
+    })
+    .await?;
+
+    let customers = rows
+        .into_iter()
+        .map(|(id, limit, balance, created_at)| Customer {
+            id,
+            limit,
+            balance,
+            created_at,
+        })
+        .collect();
+    Ok((customers, total))
+}
+
+/// A customer to import, the id picked by the sequence when missing.
+#[derive(Debug, Clone)]
+pub struct NewCustomer {
+    pub id: Option<i32>,
+    pub limit: i32,
+    pub balance: i32,
+}
+
+/// Rows per `COPY` of an import.
+const IMPORT_BATCH_ROWS: usize = 1000;
+/// Description of the transaction backing an imported customer's balance.
+const OPENING_BALANCE_DESCRIPTION: &str = "saldo inicial";
+
+/// Inserts customers, each tagged with its line in the upload, all at once,
+/// and audits the import. Rows are copied in batches into a staging table,
+/// since `COPY` can't target a table under row-level security, and moved
+/// from there; the sequence is bumped past any explicit ids so later
+/// customers don't collide with them. A non-zero initial balance comes with
+/// a credit or debit of that amount, so the ledger still adds up to it.
+/// Returns how many customers were
+/// inserted and the lines of those skipped because their id was taken.
+pub async fn import_customers_db(
+    pool: &sqlx::Pool<Postgres>,
+    customers: &[(u64, NewCustomer)],
+    request_id: Option<&str>,
+) -> Result<(u64, Vec<u64>), errors::AppError> {
+    let mut conn = acquire(pool).await?;
+    let mut tx = conn.begin().await?;
+
+    sqlx::query(
+        "CREATE TEMPORARY TABLE customer_import (line BIGINT, id INTEGER, \"limit\" INTEGER, balance INTEGER) ON COMMIT DROP",
+    )
+    .execute(&mut *tx)
+    .await?;
+    for batch in customers.chunks(IMPORT_BATCH_ROWS) {
+        let mut data = String::new();
+        for (line, customer) in batch {
+            let id = customer.id.map(|id| id.to_string()).unwrap_or_default();
+            data.push_str(&format!("{},{},{},{}\n", line, id, customer.limit, customer.balance));
+        }
+        let mut copy = tx
+            .copy_in_raw("COPY customer_import (line, id, \"limit\", balance) FROM STDIN WITH (FORMAT csv)")
+            .await?;
+        copy.send(data.as_bytes()).await?;
+        copy.finish().await?;
+    }
+
+    let with_id: Vec<i32> = sqlx::query_scalar(
+        "
+        INSERT INTO customers (id, \"limit\", balance)
+        SELECT id, \"limit\", balance FROM customer_import WHERE id IS NOT NULL ORDER BY line
+        ON CONFLICT (id) DO NOTHING
+        RETURNING id
+        ",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let taken: Vec<i64> = sqlx::query_scalar(
+        "SELECT line FROM customer_import WHERE id IS NOT NULL AND id <> ALL($1) ORDER BY line",
+    )
+    .bind(&with_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    if !with_id.is_empty() {
+        sqlx::query(
+            "SELECT setval(pg_get_serial_sequence('customers', 'id'), GREATEST(MAX(id), nextval(pg_get_serial_sequence('customers', 'id')))) FROM customers",
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    let without_id: Vec<i32> = sqlx::query_scalar(
+        "
+        INSERT INTO customers (\"limit\", balance)
+        SELECT \"limit\", balance FROM customer_import WHERE id IS NULL ORDER BY line
+        RETURNING id
+        ",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let imported_ids: Vec<i32> = with_id.iter().chain(&without_id).copied().collect();
+    sqlx::query(
+        "
+        WITH opened AS (
+            UPDATE customers SET last_transaction_at = clock_timestamp()
+            WHERE id = ANY($1) AND balance <> 0
+            RETURNING id, balance, last_transaction_at
+        )
+        INSERT INTO transactions (value, \"type\", description, customer_id, uuid, created_at)
+        SELECT abs(balance), CASE WHEN balance > 0 THEN 'c' ELSE 'd' END, $2, id, gen_random_uuid(), last_transaction_at
+        FROM opened
+        ",
+    )
+    .bind(&imported_ids)
+    .bind(OPENING_BALANCE_DESCRIPTION)
+    .execute(&mut *tx)
+    .await?;
+
+    let imported = imported_ids.len() as u64;
+    sqlx::query(
+        "
+        INSERT INTO audit_log (action, details)
+        VALUES ('customers_imported', jsonb_build_object('clientes', $1::BIGINT, 'ignorados', $2::BIGINT, 'request_id', $3::TEXT))
+        ",
+    )
+    .bind(imported as i64)
+    .bind(taken.len() as i64)
+    .bind(request_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok((imported, taken.into_iter().map(|line| line as u64).collect()))
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub action: String,
+    pub customer_id: Option<i32>,
+    pub details: Json<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A page of the audit log, newest first, optionally of one customer only,
+/// and how many entries match in total. `after` is the id of the last entry
+/// of the previous page.
+pub async fn page_audit_log_db(
+    pool: sqlx::Pool<Postgres>,
+    customer_id: Option<i32>,
+    limit: i64,
+    offset: i64,
+    after: Option<i64>,
+) -> Result<(Vec<AuditEntry>, i64), errors::AppError> {
+    let query = "
+        SELECT id, action, customer_id, details, created_at
+        FROM audit_log
+        WHERE ($1::INTEGER IS NULL OR customer_id = $1)
+            AND ($2::BIGINT IS NULL OR id < $2)
+        ORDER BY id DESC
+        LIMIT $3 OFFSET $4
+    ";
+
+    deadline::within(async move {
+        let mut conn = Abandonable::new(acquire(&pool).await?);
+        let page = async {
+            let entries = sqlx::query_as::<_, AuditEntry>(query)
+                .bind(customer_id)
+                .bind(after)
+                .bind(limit)
+                .bind(offset)
+                .fetch_all(&mut *conn)
+                .await?;
+            let total = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM audit_log WHERE $1::INTEGER IS NULL OR customer_id = $1",
+            )
+            .bind(customer_id)
+            .fetch_one(&mut *conn)
+            .await?;
+            Ok::<_, sqlx::Error>((entries, total))
+        }
+        .await;
+        conn.release();
+        page
+    })
+    .await
+}
+
+/// Unsent outbox events, oldest first. Rows are locked until the surrounding
+/// transaction ends, so concurrent relays never pick the same event.
+pub async fn lock_unsent_outbox_events_db(
+    conn: &mut sqlx::PgConnection,
+    limit: i64,
+) -> Result<Vec<(i64, Json<events::TransactionCreated>)>, errors::AppError> {
+    let query = "
+        SELECT id, payload
+        FROM outbox
+        WHERE sent_at IS NULL
+        ORDER BY id
+        LIMIT $1
+        FOR UPDATE SKIP LOCKED
+    ";
+
+    let rows = sqlx::query_as(query).bind(limit).fetch_all(conn).await?;
+    Ok(rows)
+}
+
+pub async fn mark_outbox_events_sent_db(
+    conn: &mut sqlx::PgConnection,
+    ids: &[i64],
+) -> Result<(), errors::AppError> {
+    sqlx::query("UPDATE outbox SET sent_at = now() WHERE id = ANY($1)")
+        .bind(ids)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct Job {
+    pub id: i64,
+    pub kind: String,
+    pub payload: Json<serde_json::Value>,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: NaiveDateTime,
+    pub locked_by: Option<String>,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+const JOB_COLUMNS: &str =
+    "id, kind, payload, status, attempts, max_attempts, run_at, locked_by, last_error, created_at";
+
+pub async fn enqueue_job_db<'e, E>(
+    executor: E,
+    kind: &str,
+    payload: serde_json::Value,
+    max_attempts: i32,
+) -> Result<i64, errors::AppError>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let id = sqlx::query_scalar(
+        "INSERT INTO jobs (kind, payload, max_attempts) VALUES ($1, $2, $3) RETURNING id",
+    )
+    .bind(kind)
+    .bind(Json(payload))
+    .bind(max_attempts)
+    .fetch_one(executor)
+    .await?;
+
+    Ok(id)
+}
+
+/// Makes sure a job of the recurring `kind` is pending or running. A new one
+/// is due `interval` after the last one of the kind finished, or right away
+/// if none ever ran. The advisory lock keeps instances scheduling at the same
+/// time from adding two.
+pub async fn schedule_recurring_job_db(
+    pool: &sqlx::Pool<Postgres>,
+    kind: &str,
+    interval: Duration,
+) -> Result<(), errors::AppError> {
+    let query = "
+        INSERT INTO jobs (kind, payload, max_attempts, run_at)
+        SELECT $1, '{}', 1, GREATEST(now(), COALESCE(MAX(updated_at) + make_interval(secs => $2), now()))
+        FROM jobs
+        WHERE kind = $1 AND status IN ('done', 'failed')
+        HAVING NOT EXISTS (
+            SELECT 1 FROM jobs WHERE kind = $1 AND status IN ('pending', 'running')
+        )
+    ";
+
     let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext('jobs:' || $1))")
+        .bind(kind)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(query)
+        .bind(kind)
+        .bind(interval.as_secs_f64())
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
 
-    let update_query = "
-		with
-			c AS (SELECT * FROM customers c WHERE id = $2),
-			u AS (
-				UPDATE customers c2 SET balance = balance + $1
-				WHERE id = $2 AND (balance + $1) >= -\"limit\"
-				RETURNING id, \"limit\", balance
-			),
-			cu AS (SELECT COUNT(*) FROM u)
-		SELECT c.limit, c.balance, cu.count as count_update FROM c, cu
+/// Claims the next due pending job for `worker_id`, if any. `SKIP LOCKED`
+/// lets several workers (and instances) poll the same table without blocking
+/// each other.
+pub async fn claim_job_db(
+    pool: &sqlx::Pool<Postgres>,
+    worker_id: &str,
+) -> Result<Option<Job>, errors::AppError> {
+    let query = format!(
+        "
+        UPDATE jobs
+        SET status = 'running', locked_by = $1, locked_at = now(),
+            attempts = attempts + 1, updated_at = now()
+        WHERE id = (
+            SELECT id FROM jobs
+            WHERE status = 'pending' AND run_at <= now()
+            ORDER BY run_at, id
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING {}
+        ",
+        JOB_COLUMNS
+    );
+
+    let job = sqlx::query_as::<_, Job>(&query)
+        .bind(worker_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(job)
+}
+
+pub async fn complete_job_db(pool: &sqlx::Pool<Postgres>, id: i64) -> Result<(), errors::AppError> {
+    sqlx::query(
+        "UPDATE jobs SET status = 'done', locked_by = NULL, locked_at = NULL, updated_at = now() WHERE id = $1",
+    )
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Records a failed attempt: the job goes back to `pending` to run again
+/// after `retry_in`, or to `failed` when `retry_in` is `None`.
+pub async fn fail_job_db(
+    pool: &sqlx::Pool<Postgres>,
+    id: i64,
+    error: &str,
+    retry_in: Option<Duration>,
+) -> Result<(), errors::AppError> {
+    let query = "
+        UPDATE jobs
+        SET status = CASE WHEN $3::float8 IS NULL THEN 'failed' ELSE 'pending' END,
+            run_at = COALESCE(now() + make_interval(secs => $3), run_at),
+            last_error = $2,
+            locked_by = NULL,
+            locked_at = NULL,
+            updated_at = now()
+        WHERE id = $1
     ";
 
-    let insert_query = "
-      INSERT INTO transactions (value, \"type\", description, customer_id)
-      VALUES ($1, $2, $3, $4)
+    sqlx::query(query)
+        .bind(id)
+        .bind(error)
+        .bind(retry_in.map(|delay| delay.as_secs_f64()))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Puts back jobs whose worker died mid-run (locked for longer than
+/// `timeout_secs`), returning how many were released.
+pub async fn release_stale_jobs_db(
+    pool: &sqlx::Pool<Postgres>,
+    timeout_secs: i64,
+) -> Result<u64, errors::AppError> {
+    let query = "
+        UPDATE jobs
+        SET status = 'pending', locked_by = NULL, locked_at = NULL, updated_at = now()
+        WHERE status = 'running' AND locked_at < now() - make_interval(secs => $1)
+    ";
+
+    let res = sqlx::query(query)
+        .bind(timeout_secs as f64)
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected())
+}
+
+pub async fn list_jobs_db(
+    pool: sqlx::Pool<Postgres>,
+    status: Option<String>,
+    limit: i64,
+) -> Result<Vec<Job>, errors::AppError> {
+    let query = format!(
+        "
+        SELECT {}
+        FROM jobs
+        WHERE $1::text IS NULL OR status = $1
+        ORDER BY id DESC
+        LIMIT $2
+        ",
+        JOB_COLUMNS
+    );
+
+    let jobs = sqlx::query_as::<_, Job>(&query)
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&mut *acquire(&pool).await?)
+        .await?;
+    Ok(jobs)
+}
+
+/// Schedules a failed job to run again with a fresh attempt budget. Returns
+/// `false` when there is no failed job with that id.
+pub async fn retry_failed_job_db(pool: sqlx::Pool<Postgres>, id: i64) -> Result<bool, errors::AppError> {
+    let query = "
+        UPDATE jobs
+        SET status = 'pending', attempts = 0, run_at = now(), last_error = NULL, updated_at = now()
+        WHERE id = $1 AND status = 'failed'
     ";
 
-    let mut update_value = value as i64;
-    if tx_type == "d" {
-        update_value = -update_value
+    let res = sqlx::query(query)
+        .bind(id)
+        .execute(&mut *acquire(&pool).await?)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct Webhook {
+    pub id: i64,
+    pub customer_id: i32,
+    pub url: String,
+    pub active: bool,
+    pub tx_types: Option<Vec<String>>,
+    pub secret: String,
+    pub created_at: NaiveDateTime,
+}
+
+const WEBHOOK_COLUMNS: &str = "id, customer_id, url, active, tx_types, secret, created_at";
+
+pub async fn create_webhook_db(
+    pool: sqlx::Pool<Postgres>,
+    customer_id: i32,
+    url: String,
+    tx_types: Option<Vec<String>>,
+    secret: String,
+) -> Result<Webhook, errors::AppError> {
+    let query = format!(
+        "INSERT INTO webhooks (customer_id, url, tx_types, secret) VALUES ($1, $2, $3, $4) RETURNING {}",
+        WEBHOOK_COLUMNS
+    );
+
+    let webhook = sqlx::query_as::<_, Webhook>(&query)
+        .bind(customer_id)
+        .bind(url)
+        .bind(tx_types)
+        .bind(secret)
+        .fetch_one(&mut *acquire(&pool).await?)
+        .await?;
+    Ok(webhook)
+}
+
+pub async fn list_webhooks_db(
+    pool: sqlx::Pool<Postgres>,
+    customer_id: i32,
+) -> Result<Vec<Webhook>, errors::AppError> {
+    let query = format!(
+        "SELECT {} FROM webhooks WHERE customer_id = $1 ORDER BY id",
+        WEBHOOK_COLUMNS
+    );
+
+    let webhooks = sqlx::query_as::<_, Webhook>(&query)
+        .bind(customer_id)
+        .fetch_all(&mut *acquire(&pool).await?)
+        .await?;
+    Ok(webhooks)
+}
+
+pub async fn set_webhook_active_db(
+    pool: sqlx::Pool<Postgres>,
+    customer_id: i32,
+    id: i64,
+    active: bool,
+) -> Result<Webhook, errors::AppError> {
+    let query = format!(
+        "UPDATE webhooks SET active = $3 WHERE id = $1 AND customer_id = $2 RETURNING {}",
+        WEBHOOK_COLUMNS
+    );
+
+    sqlx::query_as::<_, Webhook>(&query)
+        .bind(id)
+        .bind(customer_id)
+        .bind(active)
+        .fetch_optional(&mut *acquire(&pool).await?)
+        .await?
+        .ok_or(errors::AppError::ErrWebhookNotFound)
+}
+
+pub async fn rotate_webhook_secret_db(
+    pool: sqlx::Pool<Postgres>,
+    customer_id: i32,
+    id: i64,
+    secret: String,
+) -> Result<Webhook, errors::AppError> {
+    let query = format!(
+        "UPDATE webhooks SET secret = $3 WHERE id = $1 AND customer_id = $2 RETURNING {}",
+        WEBHOOK_COLUMNS
+    );
+
+    sqlx::query_as::<_, Webhook>(&query)
+        .bind(id)
+        .bind(customer_id)
+        .bind(secret)
+        .fetch_optional(&mut *acquire(&pool).await?)
+        .await?
+        .ok_or(errors::AppError::ErrWebhookNotFound)
+}
+
+/// Current signing secret of a webhook, or `None` if it was deleted.
+pub async fn get_webhook_secret_db(
+    pool: &sqlx::Pool<Postgres>,
+    id: i64,
+) -> Result<Option<String>, errors::AppError> {
+    let secret = sqlx::query_scalar("SELECT secret FROM webhooks WHERE id = $1")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(secret)
+}
+
+pub async fn delete_webhook_db(
+    pool: sqlx::Pool<Postgres>,
+    customer_id: i32,
+    id: i64,
+) -> Result<(), errors::AppError> {
+    let res = sqlx::query("DELETE FROM webhooks WHERE id = $1 AND customer_id = $2")
+        .bind(id)
+        .bind(customer_id)
+        .execute(&mut *acquire(&pool).await?)
+        .await?;
+
+    if res.rows_affected() == 0 {
+        return Err(errors::AppError::ErrWebhookNotFound);
     }
+    Ok(())
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct WebhookDeadLetter {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub job_id: i64,
+    pub payload: Json<serde_json::Value>,
+    pub attempts: i32,
+    pub last_error: String,
+    pub failed_at: NaiveDateTime,
+    pub requeued_at: Option<NaiveDateTime>,
+}
+
+pub async fn insert_webhook_dead_letter_db(
+    pool: &sqlx::Pool<Postgres>,
+    webhook_id: i64,
+    job: &Job,
+    last_error: &str,
+) -> Result<(), errors::AppError> {
+    let query = "
+        INSERT INTO webhook_dead_letters (webhook_id, job_id, payload, attempts, last_error)
+        VALUES ($1, $2, $3, $4, $5)
+    ";
+
+    sqlx::query(query)
+        .bind(webhook_id)
+        .bind(job.id)
+        .bind(&job.payload)
+        .bind(job.attempts)
+        .bind(last_error)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn list_webhook_dead_letters_db(
+    pool: sqlx::Pool<Postgres>,
+    include_requeued: bool,
+    limit: i64,
+) -> Result<Vec<WebhookDeadLetter>, errors::AppError> {
+    let query = "
+        SELECT id, webhook_id, job_id, payload, attempts, last_error, failed_at, requeued_at
+        FROM webhook_dead_letters
+        WHERE $1 OR requeued_at IS NULL
+        ORDER BY id DESC
+        LIMIT $2
+    ";
+
+    let dead_letters = sqlx::query_as::<_, WebhookDeadLetter>(query)
+        .bind(include_requeued)
+        .bind(limit)
+        .fetch_all(&mut *acquire(&pool).await?)
+        .await?;
+    Ok(dead_letters)
+}
+
+/// Enqueues a new delivery job for a dead letter that was not requeued yet
+/// and marks it as requeued, returning the new job id.
+pub async fn requeue_webhook_dead_letter_db(
+    pool: sqlx::Pool<Postgres>,
+    id: i64,
+    max_attempts: i32,
+) -> Result<Option<i64>, errors::AppError> {
+    let mut conn = acquire(&pool).await?;
+    let mut tx = conn.begin().await?;
+
+    let payload: Option<Json<serde_json::Value>> = sqlx::query_scalar(
+        "UPDATE webhook_dead_letters SET requeued_at = now() WHERE id = $1 AND requeued_at IS NULL RETURNING payload",
+    )
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let job_id = match payload {
+        Some(payload) => {
+            Some(enqueue_job_db(&mut *tx, webhooks::DELIVERY_JOB_KIND, payload.0, max_attempts).await?)
+        }
+        None => None,
+    };
+
+    tx.commit().await?;
+    Ok(job_id)
+}
+
+/// Brings the ledger back to its initial state: no transactions, archived
+/// ones included, no closed days and every balance at zero. Running it again
+/// is a no-op.
+pub async fn reset_state_db(pool: &sqlx::Pool<Postgres>) -> Result<(), errors::AppError> {
+    let mut conn = pool.acquire().await?;
+    let mut tx = conn.begin().await?;
+
+    sqlx::query("TRUNCATE transactions, transactions_archive, daily_balances")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE customers SET balance = 0 WHERE balance <> 0")
+        .execute(&mut *tx)
+        .await?;
 
-    let (limit, total, update_count): (i32, i32, i64) = sqlx::query_as(update_query)
-        .bind(update_value)
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn list_customer_ids_db(pool: &sqlx::Pool<Postgres>) -> Result<Vec<i32>, errors::AppError> {
+    let ids = sqlx::query_scalar("SELECT id FROM customers ORDER BY id")
+        .fetch_all(pool)
+        .await?;
+    Ok(ids)
+}
+
+/// Runs both hot-path queries on `conn` so their prepared statements are
+/// cached on it. The write runs with a zero value inside a transaction that
+/// is rolled back, leaving no trace.
+pub async fn warm_connection_db(
+    conn: &mut sqlx::PgConnection,
+    customer_id: i32,
+) -> Result<(), errors::AppError> {
+    sqlx::query_as::<_, GetCustomerStatementResult>(STATEMENT_QUERY)
+        .bind(customer_id as i64)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let mut tx = conn.begin().await?;
+    let _: Option<(i32, i32, i64)> = sqlx::query_as(UPDATE_BALANCE_QUERY)
+        .bind(0i64)
+        .bind(customer_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    sqlx::query_as::<_, Transaction>(INSERT_TRANSACTION_QUERY)
+        .bind(0)
+        .bind("c")
+        .bind("warmup")
         .bind(customer_id)
+        .bind(Uuid::new_v4())
+        .bind(None::<i64>)
+        .bind(Utc::now())
         .fetch_one(&mut *tx)
+        .await?;
+    tx.rollback().await?;
+
+    Ok(())
+}
+
+/// Version of the last migration built into this binary.
+pub fn latest_migration_version() -> i64 {
+    MIGRATOR.iter().map(|migration| migration.version).max().unwrap_or_default()
+}
+
+/// Version of the last migration applied to the current schema.
+pub async fn applied_migration_version_db(
+    pool: &sqlx::Pool<Postgres>,
+) -> Result<Option<i64>, errors::AppError> {
+    let version = sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success")
+        .fetch_one(&mut *acquire(pool).await?)
+        .await?;
+    Ok(version)
+}
+
+/// A round trip to the database.
+pub async fn ping_db(pool: &sqlx::Pool<Postgres>) -> Result<(), errors::AppError> {
+    sqlx::query("SELECT 1").execute(&mut *acquire(pool).await?).await?;
+    Ok(())
+}
+
+/// Migrates the pool's own schema and then each tenant's schema, creating
+/// them when missing.
+pub async fn run_migrations(
+    pool: &sqlx::Pool<Postgres>,
+    schema: &tenant::Schema,
+    tenant_schemas: &[tenant::Schema],
+) -> Result<(), errors::CustomError> {
+    create_schema(pool, schema).await?;
+    MIGRATOR
+        .run(pool)
         .await
-        .map_err(|err| match err {
-            sqlx::Error::RowNotFound => errors::AppError::ErrCustomerNotFound,
-            _ => err.into(),
-        })?;
+        .map_err(|err| errors::CustomError::StandardError(Box::new(err)))?;
 
-    if update_count == 0 {
-        return Err(errors::AppError::ErrNegativeTransactionBalance);
+    for schema in tenant_schemas {
+        create_schema(pool, schema).await?;
+        tenant::scope(schema.clone(), MIGRATOR.run(pool))
+            .await
+            .map_err(|err| errors::CustomError::StandardError(Box::new(err)))?;
     }
+    Ok(())
+}
 
-    let _ = sqlx::query(insert_query)
-        .bind(value)
-        .bind(tx_type)
-        .bind(description)
-        .bind(customer_id)
-        .execute(&mut *tx)
+async fn create_schema(pool: &sqlx::Pool<Postgres>, schema: &tenant::Schema) -> Result<(), sqlx::Error> {
+    sqlx::query(&format!("CREATE SCHEMA IF NOT EXISTS \"{}\"", schema.0))
+        .execute(pool)
         .await?;
+    Ok(())
+}
 
-    tx.commit().await?;
+/// Request-scoped settings applied to pooled connections on every checkout,
+/// each costing a round trip.
+#[derive(Debug, Clone)]
+pub struct SessionHooks {
+    /// Schema of every connection outside a tenant, set once per connection
+    /// unless it is Postgres' default.
+    pub schema: tenant::Schema,
+    /// Point `search_path` at the current tenant's schema.
+    pub tenant_schema: bool,
+    /// Set the customer the row-level security policies filter on.
+    pub current_customer: bool,
+}
 
-    Ok((limit as i64, (total as i64) + update_value))
+async fn apply_session_hooks(conn: &mut PgConnection, hooks: &SessionHooks) -> Result<(), sqlx::Error> {
+    if hooks.tenant_schema {
+        tenant::set_search_path(conn, &hooks.schema).await?;
+    }
+    if hooks.current_customer {
+        rls::set_current_customer(conn).await?;
+    }
+    Ok(())
 }
 
-pub async fn get_pool(
+/// Prefix of the socket files Postgres creates in its socket directory,
+/// followed by the port.
+const SOCKET_FILE_PREFIX: &str = ".s.PGSQL.";
+
+/// Options to connect with: the connection string, with the socket and SSL
+/// settings of `cfg` applied over it.
+pub fn connect_options(
     conn_string: &str,
+    cfg: &config::DbConnectionConfig,
+) -> Result<PgConnectOptions, errors::CustomError> {
+    let config_error = errors::CustomError::StringError;
+    // The connection string may hold a password, so it isn't quoted.
+    let mut options = PgConnectOptions::from_str(conn_string)
+        .map_err(|err| config_error(format!("invalid DB_CONN_STR: {}", err)))?;
+
+    if let Some(socket) = &cfg.socket {
+        let path = Path::new(socket);
+        let metadata = std::fs::metadata(path)
+            .map_err(|err| config_error(format!("DB_SOCKET {}: {}", socket, err)))?;
+        if metadata.is_dir() {
+            options = options.socket(path);
+        } else {
+            // sqlx wants the directory and finds the file from the port.
+            let port = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(SOCKET_FILE_PREFIX))
+                .and_then(|port| port.parse().ok())
+                .ok_or_else(|| {
+                    config_error(format!(
+                        "DB_SOCKET {} is neither a directory nor a Postgres socket named {}<port>",
+                        socket, SOCKET_FILE_PREFIX
+                    ))
+                })?;
+            let dir = path.parent().unwrap_or(Path::new("."));
+            options = options.socket(dir).port(port);
+        }
+    }
+
+    if let Some(mode) = &cfg.ssl_mode {
+        let mode = PgSslMode::from_str(mode).map_err(|_| {
+            config_error(format!(
+                "invalid DB_SSL_MODE {:?}: expected disable, allow, prefer, require, verify-ca or verify-full",
+                mode
+            ))
+        })?;
+        options = options.ssl_mode(mode);
+    }
+    if let Some(cert) = &cfg.ssl_root_cert {
+        std::fs::File::open(cert)
+            .map_err(|err| config_error(format!("DB_SSL_ROOT_CERT {}: {}", cert, err)))?;
+        options = options.ssl_root_cert(cert);
+    }
+
+    if let Some(socket) = options.get_socket() {
+        match options.get_ssl_mode() {
+            PgSslMode::Require | PgSslMode::VerifyCa | PgSslMode::VerifyFull => {
+                return Err(config_error(format!(
+                    "SSL mode {:?} can't be used over the Unix socket in {}: Postgres has no SSL on sockets",
+                    options.get_ssl_mode(),
+                    socket.display()
+                )));
+            }
+            // Asking a socket for SSL is a wasted round trip per connection.
+            PgSslMode::Prefer | PgSslMode::Allow => options = options.ssl_mode(PgSslMode::Disable),
+            PgSslMode::Disable => {}
+        }
+    }
+
+    Ok(options)
+}
+
+/// Where `options` connect to, for error messages.
+fn describe_target(options: &PgConnectOptions) -> String {
+    match options.get_socket() {
+        Some(dir) => format!(
+            "{}/{}{}",
+            dir.display(),
+            SOCKET_FILE_PREFIX,
+            options.get_port()
+        ),
+        None => format!("{}:{}", options.get_host(), options.get_port()),
+    }
+}
+
+/// With `client_check_interval`, Postgres looks that often for a vanished
+/// client while running a query, and cancels the query if it is gone.
+pub fn with_client_check(
+    connect_options: PgConnectOptions,
+    client_check_interval: Option<Duration>,
+) -> PgConnectOptions {
+    match client_check_interval {
+        Some(interval) => connect_options.options([(
+            "client_connection_check_interval",
+            format!("{}ms", interval.as_millis()),
+        )]),
+        None => connect_options,
+    }
+}
+
+/// With `failover`, connections to a read-only host are refused and those
+/// opened before a switch to another host are dropped instead of handed out.
+pub async fn get_pool(
+    connect_options: PgConnectOptions,
     n_max_connections: u32,
+    hooks: SessionHooks,
+    failover: Option<Arc<failover::Failover>>,
 ) -> Result<sqlx::Pool<sqlx::Postgres>, errors::CustomError> {
     // Create a connection pool
-    let pool = PgPoolOptions::new()
-        .max_connections(n_max_connections)
-        .connect(conn_string)
-        .await?;
+    let mut options = PgPoolOptions::new().max_connections(n_max_connections);
+    let own_schema = &*hooks.schema.0 != tenant::DEFAULT_SCHEMA && !hooks.tenant_schema;
+    let read_write = failover.is_some();
+    if own_schema || hooks.tenant_schema || hooks.current_customer || read_write {
+        // Fresh connections skip `before_acquire`, so they are set up here too.
+        let after_connect = hooks.clone();
+        options = options.after_connect(move |conn, _| {
+            let hooks = after_connect.clone();
+            Box::pin(async move {
+                // Counted as an outage, so the failover looks for another host.
+                if read_write && !failover::is_writable(conn).await? {
+                    return Err(sqlx::Error::Io(std::io::Error::other("Postgres host is read-only")));
+                }
+                if own_schema {
+                    tenant::set_schema(conn, &hooks.schema).await?;
+                }
+                apply_session_hooks(conn, &hooks).await
+            })
+        });
+    }
+    if hooks.tenant_schema || hooks.current_customer || failover.is_some() {
+        options = options.before_acquire(move |conn, meta| {
+            let hooks = hooks.clone();
+            let stale = failover
+                .as_ref()
+                .is_some_and(|failover| failover.predates_switch(meta.age));
+            Box::pin(async move {
+                if stale {
+                    return Ok(false);
+                }
+                apply_session_hooks(conn, &hooks).await.map(|_| true)
+            })
+        });
+    }
+    let target = describe_target(&connect_options);
+    let pool = options.connect_with(connect_options).await.map_err(|err| {
+        errors::CustomError::StringError(format!("connecting to Postgres at {}: {}", target, err))
+    })?;
 
     Ok(pool)
 }
@@ -0,0 +1,84 @@
+use std::future::Future;
+use std::time::Duration;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::HeaderName;
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::web;
+use tokio::time::Instant;
+
+use crate::server::MyData;
+use crate::{error_catalog, errors};
+
+/// Milliseconds the client is willing to wait for the response.
+pub const REQUEST_TIMEOUT_HEADER: HeaderName = HeaderName::from_static("x-request-timeout");
+
+tokio::task_local! {
+    static CURRENT: Instant;
+}
+
+/// Point past which nobody waits for the response to the request handled by
+/// the current task, if it has one.
+pub fn current() -> Option<Instant> {
+    CURRENT.try_with(|deadline| *deadline).ok()
+}
+
+/// Runs `future` with `deadline` as the current one, for work carried out of
+/// the request's task.
+pub async fn scope<F: Future>(deadline: Instant, future: F) -> F::Output {
+    CURRENT.scope(deadline, future).await
+}
+
+/// Gives the request a deadline from its `X-Request-Timeout` header and the
+/// configured request timeout, whichever is shorter.
+pub async fn assign(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let requested = match req.headers().get(&REQUEST_TIMEOUT_HEADER) {
+        Some(value) => match value.to_str().ok().and_then(|ms| ms.trim().parse().ok()) {
+            Some(ms) => Some(Duration::from_millis(ms)),
+            None => {
+                let res = errors::error_response(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    &error_catalog::INVALID_REQUEST_TIMEOUT,
+                );
+                let (req, _) = req.into_parts();
+                return Ok(ServiceResponse::new(req, res));
+            }
+        },
+        None => None,
+    };
+    let configured = req
+        .app_data::<web::Data<MyData>>()
+        .and_then(|data| data.request_timeout);
+
+    let timeout = match (requested, configured) {
+        (Some(requested), Some(configured)) => Some(requested.min(configured)),
+        (timeout, None) | (None, timeout) => timeout,
+    };
+    let res = match timeout {
+        Some(timeout) => CURRENT.scope(Instant::now() + timeout, next.call(req)).await?,
+        None => next.call(req).await?,
+    };
+    Ok(res.map_into_boxed_body())
+}
+
+/// Runs `future`, giving up with `ErrDeadlineExceeded` once the current
+/// request's deadline passes. Dropping database work halfway cancels it, see
+/// `db::Abandonable`.
+pub async fn within<T, E, F>(future: F) -> Result<T, errors::AppError>
+where
+    F: Future<Output = Result<T, E>>,
+    errors::AppError: From<E>,
+{
+    match current() {
+        Some(deadline) => match tokio::time::timeout_at(deadline, future).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(errors::AppError::ErrDeadlineExceeded),
+        },
+        None => Ok(future.await?),
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use hashlink::LruCache;
+
+use crate::domain::{CreatedTransaction, NewTransaction};
+use crate::{config, tenant};
+
+type Key = (Option<tenant::Schema>, NewTransaction);
+
+/// Recently applied transactions, keyed by tenant and request contents. An
+/// identical request arriving within `window` of the previous one gets the
+/// earlier result instead of being applied again. Catches client retries, not
+/// identical requests in flight at the same time.
+pub struct Dedup {
+    window: Duration,
+    recent: Mutex<LruCache<Key, (Instant, CreatedTransaction)>>,
+}
+
+impl Dedup {
+    pub fn from_config(cfg: &config::DedupConfig) -> Option<Dedup> {
+        (cfg.window_ms > 0).then(|| Dedup {
+            window: Duration::from_millis(cfg.window_ms),
+            recent: Mutex::new(LruCache::new(cfg.capacity.max(1))),
+        })
+    }
+
+    /// The result of an identical transaction applied within the window.
+    pub fn recent(&self, new_tx: &NewTransaction) -> Option<CreatedTransaction> {
+        let key = (tenant::current(), new_tx.clone());
+        let mut recent = self.recent.lock().unwrap();
+        match recent.get(&key) {
+            Some((applied_at, created)) if applied_at.elapsed() <= self.window => {
+                Some(created.clone())
+            }
+            Some(_) => {
+                recent.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn remember(&self, new_tx: NewTransaction, created: &CreatedTransaction) {
+        self.recent
+            .lock()
+            .unwrap()
+            .insert((tenant::current(), new_tx), (Instant::now(), created.clone()));
+    }
+
+    /// Drops the results remembered for a customer of the current tenant, or
+    /// for all of its customers with `None`.
+    pub fn forget(&self, customer_id: Option<i64>) {
+        let tenant = tenant::current();
+        let mut recent = self.recent.lock().unwrap();
+        let keys: Vec<Key> = recent
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|(schema, new_tx)| {
+                *schema == tenant && customer_id.is_none_or(|id| new_tx.customer_id as i64 == id)
+            })
+            .cloned()
+            .collect();
+        for key in keys {
+            recent.remove(&key);
+        }
+    }
+}
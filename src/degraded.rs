@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{config, failover, tenant};
+use crate::domain::Statement;
+use crate::errors::AppError;
+
+/// Tenant and customer id.
+type StatementKey = (Option<tenant::Schema>, i64);
+
+/// Read-only operation while the database is unreachable: a circuit breaker
+/// trips after consecutive outage errors, and while it is open statements are
+/// served from the last copy this instance read and writes are refused.
+/// With several hosts, outages only count once no host is writable; until
+/// then the failover moving to another host is expected to end them.
+pub struct DegradedMode {
+    breaker: CircuitBreaker,
+    statements: Mutex<HashMap<StatementKey, (Statement, Instant)>>,
+    failover: Option<Arc<failover::Failover>>,
+}
+
+impl DegradedMode {
+    pub fn from_config(
+        cfg: &config::DegradedConfig,
+        failover: Option<Arc<failover::Failover>>,
+    ) -> Option<DegradedMode> {
+        cfg.enabled.then(|| DegradedMode {
+            breaker: CircuitBreaker::new(cfg.failure_threshold, Duration::from_secs(cfg.open_secs)),
+            statements: Mutex::new(HashMap::new()),
+            failover,
+        })
+    }
+
+    /// Whether requests should go to the database at all.
+    pub fn allows_requests(&self) -> bool {
+        self.breaker.allows_requests()
+    }
+
+    /// Feeds the outcome of a database call to the breaker. Errors the
+    /// database answered with, such as an unknown customer, count as success.
+    pub fn record<T>(&self, result: &Result<T, AppError>) {
+        match result {
+            Err(err) if is_outage(err) => match &self.failover {
+                Some(failover) if !failover.all_down() => failover.report_outage(),
+                _ => self.breaker.record_failure(),
+            },
+            _ => self.breaker.record_success(),
+        }
+    }
+
+    pub fn remember(&self, customer_id: i64, statement: &Statement) {
+        self.statements
+            .lock()
+            .unwrap()
+            .insert((tenant::current(), customer_id), (statement.clone(), Instant::now()));
+    }
+
+    /// Drops the statement kept for a customer of the current tenant, or for
+    /// all of its customers with `None`.
+    pub fn forget(&self, customer_id: Option<i64>) {
+        let tenant = tenant::current();
+        self.statements.lock().unwrap().retain(|(schema, id), _| {
+            *schema != tenant || customer_id.is_some_and(|customer_id| customer_id != *id)
+        });
+    }
+
+    /// The last statement read for the customer and how long ago it was read.
+    pub fn last_known(&self, customer_id: i64) -> Option<(Statement, Duration)> {
+        self.statements
+            .lock()
+            .unwrap()
+            .get(&(tenant::current(), customer_id))
+            .map(|(statement, read_at)| (statement.clone(), read_at.elapsed()))
+    }
+}
+
+/// Errors meaning the database couldn't be reached, as opposed to errors it
+/// answered with.
+fn is_outage(err: &AppError) -> bool {
+    match err {
+        AppError::ErrPoolExhausted | AppError::ErrDatabaseUnavailable => true,
+        AppError::SQLError(err) => matches!(
+            err,
+            sqlx::Error::Io(..)
+                | sqlx::Error::Tls(..)
+                | sqlx::Error::PoolClosed
+                | sqlx::Error::PoolTimedOut
+                | sqlx::Error::WorkerCrashed
+        ),
+        _ => false,
+    }
+}
+
+/// Opens after `threshold` consecutive failures and stays open for
+/// `open_for`. After that requests go through again; the first failure
+/// reopens it and the first success closes it.
+struct CircuitBreaker {
+    threshold: u32,
+    open_for: Duration,
+    failures: AtomicU32,
+    opened_at: Mutex<Option<Instant>>,
+}
+
+impl CircuitBreaker {
+    fn new(threshold: u32, open_for: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            threshold: threshold.max(1),
+            open_for,
+            failures: AtomicU32::new(0),
+            opened_at: Mutex::new(None),
+        }
+    }
+
+    fn allows_requests(&self) -> bool {
+        match *self.opened_at.lock().unwrap() {
+            Some(opened_at) => opened_at.elapsed() >= self.open_for,
+            None => true,
+        }
+    }
+
+    fn record_success(&self) {
+        if self.failures.swap(0, Ordering::Relaxed) >= self.threshold {
+            log::info!("database reachable again, leaving degraded mode");
+        }
+        *self.opened_at.lock().unwrap() = None;
+    }
+
+    fn record_failure(&self) {
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= self.threshold {
+            if failures == self.threshold {
+                log::warn!("database unreachable after {} failures, entering degraded mode", failures);
+            }
+            *self.opened_at.lock().unwrap() = Some(Instant::now());
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::web;
+use futures_util::Stream;
+use rinha_servico_rust::json;
+use rinha_servico_rust::schema::Dialect;
+use serde::Serialize;
+
+use crate::server::MyData;
+use crate::{context, error_catalog, errors};
+
+/// Dialect the client wants the bodies in, `pt` or `en`.
+pub const ACCEPT_PROFILE_HEADER: HeaderName = HeaderName::from_static("accept-profile");
+/// Dialect of the response body, sent back when the client asked for one.
+pub const CONTENT_PROFILE_HEADER: HeaderName = HeaderName::from_static("content-profile");
+
+tokio::task_local! {
+    static CURRENT: Dialect;
+}
+
+/// Dialect of the response to the request handled by the current task;
+/// Portuguese outside a request.
+pub fn current() -> Dialect {
+    CURRENT.try_with(|dialect| *dialect).unwrap_or_default()
+}
+
+/// Encodes a response body in the current request's dialect.
+pub fn to_string<T: Serialize>(value: &T) -> Result<String, json::Error> {
+    json::to_string_in(value, current())
+}
+
+/// Picks the dialect from the `Accept-Profile` header, or the configured one
+/// when there is none. Request bodies are read in either dialect regardless.
+pub async fn negotiate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let configured = req
+        .app_data::<web::Data<MyData>>()
+        .map(|data| data.api_dialect)
+        .unwrap_or_default();
+    let requested = match req.headers().get(&ACCEPT_PROFILE_HEADER) {
+        Some(value) => match value.to_str().ok().and_then(|profile| profile.parse().ok()) {
+            Some(dialect) => Some(dialect),
+            None => {
+                let res = errors::error_response(
+                    StatusCode::NOT_ACCEPTABLE,
+                    &error_catalog::UNKNOWN_PROFILE,
+                );
+                let (req, _) = req.into_parts();
+                return Ok(ServiceResponse::new(req, res));
+            }
+        },
+        None => None,
+    };
+
+    let dialect = requested.unwrap_or(configured);
+    let mut res = CURRENT.scope(dialect, next.call(req)).await?;
+    if requested.is_some() {
+        let profile = match dialect {
+            Dialect::Portuguese => "pt",
+            Dialect::English => "en",
+        };
+        res.headers_mut()
+            .insert(CONTENT_PROFILE_HEADER, HeaderValue::from_static(profile));
+    }
+    Ok(res.map_into_boxed_body())
+}
+
+/// Keeps the current dialect for a response body stream.
+pub fn scoped<S: Stream>(stream: S) -> impl Stream<Item = S::Item> {
+    context::scoped_stream(&CURRENT, Some(current()), stream)
+}
@@ -0,0 +1,91 @@
+use std::any::Any;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Extensions, ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::rt::net::TcpStream;
+use actix_web::web;
+use socket2::SockRef;
+
+use crate::server::MyData;
+
+/// Status of requests their client gave up on, as nginx has it. Nobody ever
+/// receives it.
+const CLIENT_CLOSED_REQUEST: u16 = 499;
+/// How long to wait before looking at the socket again while it holds data
+/// actix hasn't read yet, such as a pipelined request.
+const UNREAD_DATA_RECHECK: Duration = Duration::from_millis(50);
+
+/// Second handle on a client's socket, used to notice the client closing it
+/// while one of its requests is being handled. actix only finds out once it
+/// writes the response.
+#[derive(Clone)]
+struct ClientSocket(Arc<TcpStream>);
+
+impl ClientSocket {
+    /// Resolves once the client closed or reset the connection. A client that
+    /// only shut down its sending side counts as gone.
+    async fn closed(&self) {
+        let mut buf = [0; 1];
+        loop {
+            match self.0.peek(&mut buf).await {
+                Ok(0) | Err(_) => return,
+                Ok(_) => tokio::time::sleep(UNREAD_DATA_RECHECK).await,
+            }
+        }
+    }
+}
+
+/// `on_connect` hook keeping a handle on each TCP connection's socket.
+pub fn on_connect(conn: &dyn Any, data: &mut Extensions) {
+    let Some(stream) = conn.downcast_ref::<TcpStream>() else {
+        return;
+    };
+    match duplicate(stream) {
+        Ok(socket) => {
+            data.insert(ClientSocket(Arc::new(socket)));
+        }
+        Err(err) => log::warn!("can't watch connection for disconnects: {}", err),
+    }
+}
+
+fn duplicate(stream: &TcpStream) -> io::Result<TcpStream> {
+    let socket: std::net::TcpStream = SockRef::from(stream).try_clone()?.into();
+    socket.set_nonblocking(true)?;
+    TcpStream::from_std(socket)
+}
+
+/// Drops the handling of a request as soon as its client disconnects, which
+/// cancels the database work in progress for it: connections dropped in the
+/// middle of a query are closed instead of going back to the pool, and
+/// Postgres stops the query once it sees its client gone.
+pub async fn cancel(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let Some(client) = req.conn_data::<ClientSocket>().cloned() else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let method = req.method().clone();
+    let path = req.path().to_string();
+    let metrics = req
+        .app_data::<web::Data<MyData>>()
+        .map(|data| data.metrics.clone());
+
+    tokio::select! {
+        res = next.call(req) => Ok(res?.map_into_boxed_body()),
+        () = client.closed() => {
+            log::debug!("{} {} abandoned by the client", method, path);
+            if let Some(metrics) = metrics {
+                metrics.record_client_disconnect();
+            }
+            let status = StatusCode::from_u16(CLIENT_CLOSED_REQUEST).unwrap();
+            Err(InternalError::new("client disconnected", status).into())
+        }
+    }
+}
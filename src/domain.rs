@@ -0,0 +1,70 @@
+use sqlx::types::chrono::{DateTime, NaiveDateTime, Utc};
+use uuid::Uuid;
+
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct Customer {
+    pub id: i32,
+    pub limit: i32,
+    pub balance: i32,
+    pub created_at: NaiveDateTime,
+}
+
+#[allow(dead_code)]
+#[derive(sqlx::FromRow, Clone)]
+pub struct Transaction {
+    pub id: Option<i64>,
+    pub value: Option<i32>,
+    #[sqlx(rename = "type")]
+    pub tx_type: Option<String>,
+    pub description: Option<String>,
+    pub customer_id: Option<i32>,
+    pub created_at: Option<DateTime<Utc>>,
+    /// External identifier; only loaded by the queries that need it.
+    #[sqlx(default)]
+    pub uuid: Option<Uuid>,
+}
+
+/// A transaction as requested by the client.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NewTransaction {
+    pub customer_id: i32,
+    pub value: i32,
+    pub tx_type: String,
+    pub description: String,
+}
+
+/// A customer's balance and latest transactions as of `taken_at`.
+#[derive(Clone)]
+pub struct Statement {
+    pub customer: Customer,
+    pub transactions: Vec<Transaction>,
+    pub taken_at: NaiveDateTime,
+}
+
+/// Outcome of an accepted transaction: the customer's limit and balance
+/// right after it, plus the stored transaction.
+#[derive(Clone)]
+pub struct CreatedTransaction {
+    pub limit: i64,
+    pub balance: i64,
+    pub transaction: Transaction,
+}
+
+/// Change a transaction makes to the customer's balance: credits (`c`) add
+/// their value, debits (`d`) subtract it. `None` for any other type, which
+/// no transaction may have whatever the validation chain allows.
+pub fn balance_delta(tx: &NewTransaction) -> Option<i64> {
+    let value = tx.value as i64;
+    match tx.tx_type.as_str() {
+        "c" => Some(value),
+        "d" => Some(-value),
+        _ => None,
+    }
+}
+
+/// Whether applying `delta` keeps the balance within the limit. The balance
+/// may go down to the negative of the limit and no further.
+pub fn within_limit(balance: i64, limit: i64, delta: i64) -> bool {
+    balance + delta >= -limit
+}
@@ -0,0 +1,180 @@
+use crate::i18n::Text;
+
+/// A user-facing error: a stable code clients can match on and its message.
+/// Every message shown to clients lives in this module, so the same failure
+/// reads the same wherever it's raised.
+#[derive(Debug)]
+pub struct Entry {
+    pub code: &'static str,
+    pub text: Text,
+}
+
+const fn entry(code: &'static str, pt_br: &'static str, en: &'static str) -> Entry {
+    Entry {
+        code,
+        text: Text::new(pt_br, en),
+    }
+}
+
+pub static LIMIT_EXCEEDED: Entry = entry(
+    "limit_exceeded",
+    "transação ultrapassa o limite do cliente",
+    "transaction exceeds the customer's limit",
+);
+pub static CUSTOMER_NOT_FOUND: Entry =
+    entry("customer_not_found", "cliente não encontrado", "customer not found");
+pub static TRANSACTION_NOT_FOUND: Entry = entry(
+    "transaction_not_found",
+    "transação não encontrada",
+    "transaction not found",
+);
+pub static JOB_NOT_FOUND: Entry = entry("job_not_found", "job não encontrado", "job not found");
+pub static DEAD_LETTER_NOT_FOUND: Entry = entry(
+    "dead_letter_not_found",
+    "entrega falha não encontrada",
+    "dead letter not found",
+);
+pub static WEBHOOK_NOT_FOUND: Entry =
+    entry("webhook_not_found", "webhook não encontrado", "webhook not found");
+pub static INVALID_ADMIN_TOKEN: Entry = entry(
+    "invalid_admin_token",
+    "token de administrador inválido",
+    "invalid admin token",
+);
+pub static ADMIN_TOKEN_REQUIRED: Entry = entry(
+    "admin_token_required",
+    "operação requer um token de administrador configurado",
+    "operation requires an admin token to be configured",
+);
+pub static INTERNAL_ERROR: Entry = entry("internal_error", "erro interno", "internal error");
+pub static DATABASE_BUSY: Entry = entry(
+    "database_busy",
+    "banco de dados sobrecarregado, tente novamente",
+    "database is busy, try again",
+);
+pub static DATABASE_UNAVAILABLE: Entry = entry(
+    "database_unavailable",
+    "banco de dados indisponível, tente novamente",
+    "database unavailable, try again",
+);
+pub static DEADLINE_EXCEEDED: Entry = entry(
+    "deadline_exceeded",
+    "prazo da requisição esgotado",
+    "request deadline exceeded",
+);
+pub static UNDER_MAINTENANCE: Entry = entry(
+    "under_maintenance",
+    "serviço em manutenção",
+    "service under maintenance",
+);
+
+pub static INVALID_BODY: Entry = entry("invalid_body", "corpo inválido", "invalid body");
+pub static INVALID_AMOUNT: Entry = entry("invalid_amount", "valor inválido", "invalid amount");
+pub static INVALID_TRANSACTION_TYPE: Entry = entry(
+    "invalid_transaction_type",
+    "tipo de transação inválido",
+    "invalid transaction type",
+);
+pub static INVALID_DESCRIPTION: Entry = entry(
+    "invalid_description",
+    "tamanho de descrição inválido",
+    "invalid description length",
+);
+pub static INVALID_WAIT: Entry = entry(
+    "invalid_wait",
+    "parâmetro wait inválido",
+    "invalid wait parameter",
+);
+pub static INVALID_PAGINATION: Entry = entry(
+    "invalid_pagination",
+    "paginação inválida: use pagina ou cursor, com limite dentro do permitido",
+    "invalid pagination: use page or cursor, with limit within bounds",
+);
+pub static UNKNOWN_CURSOR: Entry = entry(
+    "unknown_cursor",
+    "cursor não corresponde a uma transação do cliente",
+    "cursor doesn't match a transaction of the customer",
+);
+pub static INVALID_SEARCH: Entry = entry(
+    "invalid_search",
+    "filtros de busca inválidos",
+    "invalid search filters",
+);
+pub static INVALID_DATE_RANGE: Entry = entry(
+    "invalid_date_range",
+    "intervalo de datas inválido",
+    "invalid date range",
+);
+pub static INVALID_IMPORT_HEADER: Entry = entry(
+    "invalid_import_header",
+    "cabeçalho do CSV sem a coluna limite",
+    "CSV header lacks a limit column",
+);
+pub static IMPORT_MALFORMED_ROW: Entry = entry(
+    "import_malformed_row",
+    "linha do CSV malformada",
+    "malformed CSV row",
+);
+pub static IMPORT_INVALID_ID: Entry = entry("import_invalid_id", "id inválido", "invalid id");
+pub static IMPORT_INVALID_LIMIT: Entry =
+    entry("import_invalid_limit", "limite inválido", "invalid limit");
+pub static IMPORT_INVALID_BALANCE: Entry = entry(
+    "import_invalid_balance",
+    "saldo inicial inválido ou abaixo do limite",
+    "initial balance invalid or below the limit",
+);
+pub static IMPORT_DUPLICATE_ID: Entry = entry(
+    "import_duplicate_id",
+    "id repetido no arquivo",
+    "id repeated in the file",
+);
+pub static IMPORT_EXISTING_ID: Entry = entry(
+    "import_existing_id",
+    "já existe cliente com esse id",
+    "a customer with this id already exists",
+);
+pub static INVALID_REQUEST_TIMEOUT: Entry = entry(
+    "invalid_request_timeout",
+    "cabeçalho X-Request-Timeout inválido",
+    "invalid X-Request-Timeout header",
+);
+pub static INVALID_WEBHOOK_URL: Entry = entry(
+    "invalid_webhook_url",
+    "url de webhook inválida",
+    "invalid webhook url",
+);
+pub static INVALID_WEBHOOK_TYPES: Entry = entry(
+    "invalid_webhook_types",
+    "tipos de transação inválidos",
+    "invalid transaction types",
+);
+pub static DEBIT_RATE_EXCEEDED: Entry = entry(
+    "debit_rate_exceeded",
+    "limite de débitos por minuto excedido",
+    "too many debits in the last minute",
+);
+pub static DEBIT_VOLUME_EXCEEDED: Entry = entry(
+    "debit_volume_exceeded",
+    "limite de valor debitado por hora excedido",
+    "too much debited in the last hour",
+);
+pub static DEBIT_TOO_LARGE: Entry = entry(
+    "debit_too_large",
+    "débito grande demais para o limite do cliente",
+    "debit too large for the customer's limit",
+);
+
+pub static UNKNOWN_PROFILE: Entry = entry(
+    "unknown_profile",
+    "perfil do cabeçalho Accept-Profile desconhecido, use pt ou en",
+    "unknown Accept-Profile, use pt or en",
+);
+pub static UNKNOWN_TENANT: Entry =
+    entry("unknown_tenant", "tenant desconhecido", "unknown tenant");
+pub static ROUTE_NOT_FOUND: Entry =
+    entry("route_not_found", "rota não encontrada", "route not found");
+pub static METHOD_NOT_ALLOWED: Entry = entry(
+    "method_not_allowed",
+    "método não permitido",
+    "method not allowed",
+);
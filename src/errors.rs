@@ -1,6 +1,11 @@
 use std::{io, fmt, num};
 use actix_web::{http, HttpResponse};
+use rinha_servico_rust::schema::ErrorEnvelope;
 
+use crate::error_catalog::{self, Entry};
+use crate::{dialect, request_id};
+
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum CustomError {
     ParseIntError(num::ParseIntError),
@@ -10,6 +15,20 @@ pub enum CustomError {
     StandardError(Box<dyn std::error::Error>),
 }
 
+impl fmt::Display for CustomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CustomError::ParseIntError(err) => write!(f, "parse error: {}", err),
+            CustomError::IoError(err) => write!(f, "io error: {}", err),
+            CustomError::SQLError(err) => write!(f, "sql error: {}", err),
+            CustomError::StringError(msg) => write!(f, "{}", msg),
+            CustomError::StandardError(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for CustomError {}
+
 impl From<num::ParseIntError> for CustomError {
     fn from(error: num::ParseIntError) -> Self {
         CustomError::ParseIntError(error)
@@ -33,6 +52,21 @@ impl From<sqlx::Error> for CustomError {
 pub enum AppError {
     ErrNegativeTransactionBalance,
     ErrCustomerNotFound,
+    ErrTransactionNotFound,
+    ErrJobNotFound,
+    ErrDeadLetterNotFound,
+    ErrWebhookNotFound,
+    ErrInvalidAdminToken,
+    ErrAdminTokenRequired,
+    /// A request that parsed but breaks one of the API's rules; the message
+    /// is shown to the client.
+    ErrValidation(&'static Entry),
+    /// No pool connection became available within the acquire timeout.
+    ErrPoolExhausted,
+    /// The database is considered down and the operation needs it.
+    ErrDatabaseUnavailable,
+    /// The request's deadline passed before the database answered.
+    ErrDeadlineExceeded,
     SQLError(sqlx::Error),
 }
 
@@ -43,6 +77,18 @@ impl fmt::Display for AppError {
                 write!(f, "operation results in negative transaction balance")
             }
             AppError::ErrCustomerNotFound => write!(f, "customer not found"),
+            AppError::ErrTransactionNotFound => write!(f, "transaction not found"),
+            AppError::ErrJobNotFound => write!(f, "job not found"),
+            AppError::ErrDeadLetterNotFound => write!(f, "dead letter not found"),
+            AppError::ErrWebhookNotFound => write!(f, "webhook not found"),
+            AppError::ErrInvalidAdminToken => write!(f, "invalid admin token"),
+            AppError::ErrAdminTokenRequired => {
+                write!(f, "operation requires an admin token to be configured")
+            }
+            AppError::ErrValidation(entry) => write!(f, "{}", entry.text.en),
+            AppError::ErrPoolExhausted => write!(f, "timed out waiting for a database connection"),
+            AppError::ErrDatabaseUnavailable => write!(f, "database unavailable"),
+            AppError::ErrDeadlineExceeded => write!(f, "request deadline exceeded"),
             // The wrapped error contains additional information and is available
             // via the source() method.
             AppError::SQLError(..) => write!(f, "sql error"),
@@ -50,22 +96,92 @@ impl fmt::Display for AppError {
     }
 }
 
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::SQLError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl AppError {
+    /// Catalog entry shown to clients, as opposed to the `Display` output
+    /// meant for logs.
+    fn entry(&self) -> &'static Entry {
+        match *self {
+            AppError::ErrNegativeTransactionBalance => &error_catalog::LIMIT_EXCEEDED,
+            AppError::ErrCustomerNotFound => &error_catalog::CUSTOMER_NOT_FOUND,
+            AppError::ErrTransactionNotFound => &error_catalog::TRANSACTION_NOT_FOUND,
+            AppError::ErrJobNotFound => &error_catalog::JOB_NOT_FOUND,
+            AppError::ErrDeadLetterNotFound => &error_catalog::DEAD_LETTER_NOT_FOUND,
+            AppError::ErrWebhookNotFound => &error_catalog::WEBHOOK_NOT_FOUND,
+            AppError::ErrInvalidAdminToken => &error_catalog::INVALID_ADMIN_TOKEN,
+            AppError::ErrAdminTokenRequired => &error_catalog::ADMIN_TOKEN_REQUIRED,
+            AppError::ErrValidation(entry) => entry,
+            AppError::ErrPoolExhausted => &error_catalog::DATABASE_BUSY,
+            AppError::ErrDatabaseUnavailable => &error_catalog::DATABASE_UNAVAILABLE,
+            AppError::ErrDeadlineExceeded => &error_catalog::DEADLINE_EXCEEDED,
+            AppError::SQLError(..) => &error_catalog::INTERNAL_ERROR,
+        }
+    }
+}
+
 impl From<sqlx::Error> for AppError {
     fn from(err: sqlx::Error) -> AppError {
-        AppError::SQLError(err)
+        match err {
+            sqlx::Error::PoolTimedOut => AppError::ErrPoolExhausted,
+            err => AppError::SQLError(err),
+        }
+    }
+}
+
+/// Seconds clients are asked to wait before retrying when the database is
+/// saturated or down. Both are usually short-lived, so it's kept short.
+const DATABASE_RETRY_AFTER_SECS: u32 = 1;
+
+/// Builds an error response in the unified envelope, with the message in the
+/// language negotiated for the request and the request id, so clients can
+/// quote it when reporting a problem.
+pub fn error_response(status: http::StatusCode, entry: &Entry) -> HttpResponse {
+    let envelope = ErrorEnvelope {
+        code: entry.code.to_string(),
+        message: entry.text.localized().to_string(),
+        request_id: request_id::current().map(|id| id.0),
+    };
+    match dialect::to_string(&envelope) {
+        Ok(body) => HttpResponse::build(status)
+            .content_type(http::header::ContentType::json())
+            .body(body),
+        Err(_) => HttpResponse::build(status).json(envelope),
     }
 }
 
 impl actix_web::error::ResponseError for AppError {
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code())
-            .insert_header(http::header::ContentType::plaintext())
-            .body(self.to_string())
+        let mut response = error_response(self.status_code(), self.entry());
+        if let AppError::ErrPoolExhausted | AppError::ErrDatabaseUnavailable = self {
+            response.headers_mut().insert(
+                http::header::RETRY_AFTER,
+                http::header::HeaderValue::from(DATABASE_RETRY_AFTER_SECS),
+            );
+        }
+        response
     }
     fn status_code(&self) -> http::StatusCode {
         match *self {
             AppError::ErrNegativeTransactionBalance => http::StatusCode::UNPROCESSABLE_ENTITY,
             AppError::ErrCustomerNotFound => http::StatusCode::NOT_FOUND,
+            AppError::ErrTransactionNotFound => http::StatusCode::NOT_FOUND,
+            AppError::ErrJobNotFound => http::StatusCode::NOT_FOUND,
+            AppError::ErrDeadLetterNotFound => http::StatusCode::NOT_FOUND,
+            AppError::ErrWebhookNotFound => http::StatusCode::NOT_FOUND,
+            AppError::ErrInvalidAdminToken => http::StatusCode::UNAUTHORIZED,
+            AppError::ErrAdminTokenRequired => http::StatusCode::FORBIDDEN,
+            AppError::ErrValidation(..) => http::StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::ErrPoolExhausted => http::StatusCode::SERVICE_UNAVAILABLE,
+            AppError::ErrDatabaseUnavailable => http::StatusCode::SERVICE_UNAVAILABLE,
+            AppError::ErrDeadlineExceeded => http::StatusCode::GATEWAY_TIMEOUT,
             AppError::SQLError(..) => http::StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
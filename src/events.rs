@@ -0,0 +1,270 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{config, errors};
+
+pub type PublishError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Emitted once a transaction is committed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionCreated {
+    #[serde(rename = "cliente_id")]
+    pub customer_id: i32,
+    #[serde(rename = "valor")]
+    pub value: i32,
+    #[serde(rename = "tipo")]
+    pub tx_type: String,
+    #[serde(rename = "saldo")]
+    pub balance: i64,
+}
+
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    async fn publish(&self, event: &TransactionCreated) -> Result<(), PublishError>;
+}
+
+/// Broker used to publish events.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventsBackend {
+    None,
+    Nats,
+    Kafka,
+    Amqp,
+}
+
+impl FromStr for EventsBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "" | "none" => Ok(EventsBackend::None),
+            "nats" => Ok(EventsBackend::Nats),
+            "kafka" => Ok(EventsBackend::Kafka),
+            "amqp" | "rabbitmq" => Ok(EventsBackend::Amqp),
+            other => Err(format!("unknown events backend: {}", other)),
+        }
+    }
+}
+
+/// Publisher used when no broker is configured.
+pub struct NoopPublisher;
+
+#[async_trait]
+impl EventPublisher for NoopPublisher {
+    async fn publish(&self, _: &TransactionCreated) -> Result<(), PublishError> {
+        Ok(())
+    }
+}
+
+pub async fn connect(
+    cfg: &config::EventsConfig,
+) -> Result<Arc<dyn EventPublisher>, errors::CustomError> {
+    match cfg.backend {
+        EventsBackend::None => Ok(Arc::new(NoopPublisher)),
+        EventsBackend::Nats => connect_nats(cfg).await,
+        EventsBackend::Kafka => connect_kafka(cfg),
+        EventsBackend::Amqp => connect_amqp(cfg),
+    }
+}
+
+#[cfg(feature = "nats")]
+pub struct NatsPublisher {
+    client: async_nats::Client,
+    subject: String,
+}
+
+#[cfg(feature = "nats")]
+#[async_trait]
+impl EventPublisher for NatsPublisher {
+    async fn publish(&self, event: &TransactionCreated) -> Result<(), PublishError> {
+        let payload = serde_json::to_vec(event)?;
+        self.client
+            .publish(self.subject.clone(), payload.into())
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "nats")]
+async fn connect_nats(
+    cfg: &config::EventsConfig,
+) -> Result<Arc<dyn EventPublisher>, errors::CustomError> {
+    let client = async_nats::connect(cfg.url.0.as_str())
+        .await
+        .map_err(|err| errors::CustomError::StandardError(Box::new(err)))?;
+
+    Ok(Arc::new(NatsPublisher {
+        client,
+        subject: cfg.topic.clone(),
+    }))
+}
+
+#[cfg(not(feature = "nats"))]
+async fn connect_nats(
+    cfg: &config::EventsConfig,
+) -> Result<Arc<dyn EventPublisher>, errors::CustomError> {
+    Err(errors::CustomError::StringError(format!(
+        "cannot publish to {}: built without the `nats` feature",
+        cfg.topic
+    )))
+}
+
+#[cfg(feature = "kafka")]
+pub struct KafkaPublisher {
+    producer: rdkafka::producer::FutureProducer,
+    topic: String,
+}
+
+#[cfg(feature = "kafka")]
+#[async_trait]
+impl EventPublisher for KafkaPublisher {
+    async fn publish(&self, event: &TransactionCreated) -> Result<(), PublishError> {
+        use rdkafka::producer::FutureRecord;
+
+        let payload = serde_json::to_vec(event)?;
+        let key = event.customer_id.to_string();
+        self.producer
+            .send(
+                FutureRecord::to(&self.topic).key(&key).payload(&payload),
+                std::time::Duration::from_secs(5),
+            )
+            .await
+            .map_err(|(err, _)| err)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "kafka")]
+fn connect_kafka(
+    cfg: &config::EventsConfig,
+) -> Result<Arc<dyn EventPublisher>, errors::CustomError> {
+    let producer = rdkafka::ClientConfig::new()
+        .set("bootstrap.servers", &cfg.url.0)
+        .create()
+        .map_err(|err| errors::CustomError::StandardError(Box::new(err)))?;
+
+    Ok(Arc::new(KafkaPublisher {
+        producer,
+        topic: cfg.topic.clone(),
+    }))
+}
+
+#[cfg(not(feature = "kafka"))]
+fn connect_kafka(
+    cfg: &config::EventsConfig,
+) -> Result<Arc<dyn EventPublisher>, errors::CustomError> {
+    Err(errors::CustomError::StringError(format!(
+        "cannot publish to {}: built without the `kafka` feature",
+        cfg.topic
+    )))
+}
+
+/// RabbitMQ publisher. Events go to a durable queue named after the configured
+/// topic, with publisher confirms. There is no in-memory buffer for when the
+/// broker is down: the outbox is that buffer. An event only leaves it once the
+/// broker confirmed it, so while the broker is unreachable `publish` fails,
+/// the event waits in the outbox for the next relay pass, and nothing is lost
+/// or bounded by memory. The connection is opened on the first publish and
+/// again after any failure, so the service starts without the broker.
+#[cfg(feature = "amqp")]
+pub struct AmqpPublisher {
+    url: String,
+    queue: String,
+    connection: tokio::sync::Mutex<Option<(lapin::Connection, lapin::Channel)>>,
+}
+
+#[cfg(feature = "amqp")]
+impl AmqpPublisher {
+    async fn open(&self) -> Result<(lapin::Connection, lapin::Channel), lapin::Error> {
+        use lapin::options::{ConfirmSelectOptions, QueueDeclareOptions};
+
+        let connection =
+            lapin::Connection::connect(&self.url, lapin::ConnectionProperties::default()).await?;
+        let channel = connection.create_channel().await?;
+        channel
+            .confirm_select(ConfirmSelectOptions::default())
+            .await?;
+        channel
+            .queue_declare(
+                &self.queue,
+                QueueDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                lapin::types::FieldTable::default(),
+            )
+            .await?;
+
+        Ok((connection, channel))
+    }
+}
+
+#[cfg(feature = "amqp")]
+async fn send_amqp(channel: &lapin::Channel, queue: &str, payload: &[u8]) -> Result<(), PublishError> {
+    use lapin::options::BasicPublishOptions;
+    use lapin::publisher_confirm::Confirmation;
+
+    let confirmation = channel
+        .basic_publish(
+            "",
+            queue,
+            BasicPublishOptions::default(),
+            payload,
+            lapin::BasicProperties::default().with_delivery_mode(2),
+        )
+        .await?
+        .await?;
+
+    match confirmation {
+        Confirmation::Nack(_) => Err("amqp broker rejected the event".into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(feature = "amqp")]
+#[async_trait]
+impl EventPublisher for AmqpPublisher {
+    async fn publish(&self, event: &TransactionCreated) -> Result<(), PublishError> {
+        let payload = serde_json::to_vec(event)?;
+        let mut connection = self.connection.lock().await;
+
+        let connected = connection
+            .as_ref()
+            .is_some_and(|(_, channel)| channel.status().connected());
+        if !connected {
+            *connection = Some(self.open().await?);
+        }
+        let sent = match connection.as_ref() {
+            Some((_, channel)) => send_amqp(channel, &self.queue, &payload).await,
+            None => Err("amqp connection unavailable".into()),
+        };
+        if sent.is_err() {
+            *connection = None;
+        }
+        sent
+    }
+}
+
+#[cfg(feature = "amqp")]
+fn connect_amqp(
+    cfg: &config::EventsConfig,
+) -> Result<Arc<dyn EventPublisher>, errors::CustomError> {
+    Ok(Arc::new(AmqpPublisher {
+        url: cfg.url.0.clone(),
+        queue: cfg.topic.clone(),
+        connection: tokio::sync::Mutex::new(None),
+    }))
+}
+
+#[cfg(not(feature = "amqp"))]
+fn connect_amqp(
+    cfg: &config::EventsConfig,
+) -> Result<Arc<dyn EventPublisher>, errors::CustomError> {
+    Err(errors::CustomError::StringError(format!(
+        "cannot publish to {}: built without the `amqp` feature",
+        cfg.topic
+    )))
+}
@@ -0,0 +1,204 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use sqlx::postgres::PgConnectOptions;
+use sqlx::{Connection, PgConnection, Postgres};
+use tokio::sync::Notify;
+
+use crate::{config, errors, health};
+
+/// The writable host among several Postgres hosts, like libpq's
+/// `target_session_attrs=read-write`: hosts are tried in order and the first
+/// that accepts writes is used. A background task watches it and, once it is
+/// gone or turns read-only, moves the pools to the next writable host, so a
+/// promoted standby takes over without restarting the app.
+pub struct Failover {
+    candidates: Vec<PgConnectOptions>,
+    current: AtomicUsize,
+    all_down: AtomicBool,
+    switched_at: Mutex<Option<Instant>>,
+    outage: Notify,
+    check_interval: Duration,
+    connect_timeout: Duration,
+}
+
+impl Failover {
+    /// One candidate per host of `cfg`, each `options` with its host and
+    /// port replaced. `None` with fewer than two hosts, when there is nothing
+    /// to fail over to.
+    pub fn from_config(
+        options: &PgConnectOptions,
+        cfg: &config::DbFailoverConfig,
+    ) -> Result<Option<Failover>, errors::CustomError> {
+        if cfg.hosts.len() < 2 {
+            return Ok(None);
+        }
+        if options.get_socket().is_some() {
+            return Err(errors::CustomError::StringError(
+                "DB_HOSTS can't be combined with DB_SOCKET".to_string(),
+            ));
+        }
+
+        let mut candidates = Vec::new();
+        for host in &cfg.hosts {
+            let candidate = match host.rsplit_once(':') {
+                Some((name, port)) => port
+                    .parse()
+                    .ok()
+                    .map(|port| options.clone().host(name).port(port)),
+                None => Some(options.clone().host(host)),
+            };
+            let candidate = candidate.ok_or_else(|| {
+                errors::CustomError::StringError(format!("invalid host in DB_HOSTS: {}", host))
+            })?;
+            candidates.push(candidate);
+        }
+        Ok(Some(Failover {
+            candidates,
+            current: AtomicUsize::new(0),
+            all_down: AtomicBool::new(false),
+            switched_at: Mutex::new(None),
+            outage: Notify::new(),
+            check_interval: Duration::from_millis(cfg.check_interval_ms.max(1)),
+            connect_timeout: Duration::from_millis(cfg.connect_timeout_ms.max(1)),
+        }))
+    }
+
+    /// Options of the host in use.
+    pub fn current(&self) -> PgConnectOptions {
+        self.candidates[self.current.load(Ordering::Relaxed)].clone()
+    }
+
+    /// Settles on the first writable host, failing when there is none.
+    pub async fn elect(&self) -> Result<PgConnectOptions, errors::CustomError> {
+        if self.find_writable().await.is_none() {
+            return Err(errors::CustomError::StringError(format!(
+                "no writable Postgres among {}",
+                self.describe_hosts()
+            )));
+        }
+        Ok(self.current())
+    }
+
+    /// Whether the last look for a writable host found none.
+    pub fn all_down(&self) -> bool {
+        self.all_down.load(Ordering::Relaxed)
+    }
+
+    /// Tells the watcher a database call couldn't reach the host, so it
+    /// checks it now instead of at its next interval.
+    pub fn report_outage(&self) {
+        self.outage.notify_one();
+    }
+
+    /// Whether a pooled connection of that age was opened before the last
+    /// switch, to a host no longer in use.
+    pub fn predates_switch(&self, age: Duration) -> bool {
+        match *self.switched_at.lock().unwrap() {
+            Some(switched_at) => age > switched_at.elapsed(),
+            None => false,
+        }
+    }
+
+    /// Starts the task that watches the host in use through `pools` and
+    /// points them at another one when it fails.
+    pub fn spawn(self: Arc<Self>, pools: Vec<sqlx::Pool<Postgres>>, heartbeats: &health::Heartbeats) {
+        let heartbeat = heartbeats.register("failover", self.check_interval);
+        tokio::spawn(async move {
+            loop {
+                heartbeat.beat();
+                let was_down = self.all_down();
+                if self.check(&pools).await {
+                    if was_down {
+                        self.all_down.store(false, Ordering::Relaxed);
+                        log::info!("Postgres at {} writable again", describe(&self.current()));
+                    }
+                } else {
+                    let before = self.current.load(Ordering::Relaxed);
+                    match self.find_writable().await {
+                        Some(index) if index != before => {
+                            log::warn!(
+                                "Postgres failed over from {} to {}",
+                                describe(&self.candidates[before]),
+                                describe(&self.candidates[index])
+                            );
+                            let options = self.current();
+                            for pool in &pools {
+                                pool.set_connect_options(options.clone());
+                            }
+                            *self.switched_at.lock().unwrap() = Some(Instant::now());
+                        }
+                        Some(_) if was_down => log::info!(
+                            "Postgres at {} writable again",
+                            describe(&self.candidates[before])
+                        ),
+                        Some(_) => {}
+                        None if !was_down => {
+                            log::error!("no writable Postgres among {}", self.describe_hosts())
+                        }
+                        None => {}
+                    }
+                }
+                let _ = tokio::time::timeout(self.check_interval, self.outage.notified()).await;
+            }
+        });
+    }
+
+    /// Whether the host in use is reachable and writable, asked through the
+    /// first pool.
+    async fn check(&self, pools: &[sqlx::Pool<Postgres>]) -> bool {
+        let Some(pool) = pools.first() else {
+            return true;
+        };
+        let check = async {
+            let mut conn = pool.acquire().await?;
+            is_writable(&mut conn).await
+        };
+        matches!(tokio::time::timeout(self.connect_timeout, check).await, Ok(Ok(true)))
+    }
+
+    /// Tries every host, starting with the one in use, and makes the first
+    /// writable one current.
+    async fn find_writable(&self) -> Option<usize> {
+        let start = self.current.load(Ordering::Relaxed);
+        for offset in 0..self.candidates.len() {
+            let index = (start + offset) % self.candidates.len();
+            let probe = async {
+                let mut conn = PgConnection::connect_with(&self.candidates[index]).await?;
+                let writable = is_writable(&mut conn).await;
+                let _ = conn.close().await;
+                writable
+            };
+            match tokio::time::timeout(self.connect_timeout, probe).await {
+                Ok(Ok(true)) => {
+                    self.current.store(index, Ordering::Relaxed);
+                    self.all_down.store(false, Ordering::Relaxed);
+                    return Some(index);
+                }
+                Ok(Ok(false)) => log::debug!("Postgres at {} is read-only", describe(&self.candidates[index])),
+                Ok(Err(err)) => log::debug!("Postgres at {} unreachable: {}", describe(&self.candidates[index]), err),
+                Err(_) => log::debug!("Postgres at {} timed out", describe(&self.candidates[index])),
+            }
+        }
+        self.all_down.store(true, Ordering::Relaxed);
+        None
+    }
+
+    fn describe_hosts(&self) -> String {
+        self.candidates.iter().map(describe).collect::<Vec<_>>().join(", ")
+    }
+}
+
+/// Whether the connection accepts writes, the check libpq makes for
+/// `target_session_attrs=read-write`.
+pub async fn is_writable(conn: &mut PgConnection) -> Result<bool, sqlx::Error> {
+    let read_only: String = sqlx::query_scalar("SHOW transaction_read_only")
+        .fetch_one(conn)
+        .await?;
+    Ok(read_only == "off")
+}
+
+fn describe(options: &PgConnectOptions) -> String {
+    format!("{}:{}", options.get_host(), options.get_port())
+}
@@ -0,0 +1,58 @@
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::domain::Transaction;
+use crate::ports::EventPort;
+use crate::tenant;
+
+const FEED_CAPACITY: usize = 1024;
+
+/// In-process fan-out of committed transactions, used by endpoints that wait
+/// for or follow new transactions. Only transactions created by this instance
+/// are seen, and subscribers only see those of their own tenant.
+pub struct Feed {
+    sender: broadcast::Sender<(Option<tenant::Schema>, Transaction)>,
+}
+
+impl Feed {
+    pub fn new() -> Feed {
+        let (sender, _) = broadcast::channel(FEED_CAPACITY);
+        Feed { sender }
+    }
+
+    pub fn publish(&self, tx: Transaction) {
+        // An error only means there are no subscribers right now.
+        let _ = self.sender.send((tenant::current(), tx));
+    }
+
+    pub fn subscribe(&self) -> Subscription {
+        Subscription {
+            tenant: tenant::current(),
+            receiver: self.sender.subscribe(),
+        }
+    }
+}
+
+impl EventPort for Feed {
+    fn transaction_created(&self, tx: &Transaction) {
+        self.publish(tx.clone());
+    }
+}
+
+/// Receiver of the transactions published for the tenant current when it was
+/// created.
+pub struct Subscription {
+    tenant: Option<tenant::Schema>,
+    receiver: broadcast::Receiver<(Option<tenant::Schema>, Transaction)>,
+}
+
+impl Subscription {
+    pub async fn recv(&mut self) -> Result<Transaction, RecvError> {
+        loop {
+            let (tenant, tx) = self.receiver.recv().await?;
+            if tenant == self.tenant {
+                return Ok(tx);
+            }
+        }
+    }
+}
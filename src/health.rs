@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::db;
+use crate::server::MyData;
+
+/// Round trips slower than this are reported as a warning.
+const SLOW_ROUND_TRIP: Duration = Duration::from_millis(100);
+/// Share of the pool in use from which it is reported as a warning.
+const BUSY_POOL_RATIO: f64 = 0.9;
+/// A worker is late when it hasn't been seen for this long on top of three
+/// of its intervals.
+const LATE_WORKER_GRACE: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Serialize)]
+struct Component {
+    status: Status,
+    #[serde(flatten)]
+    details: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct HealthResponse {
+    status: Status,
+    #[serde(rename = "componentes")]
+    components: BTreeMap<&'static str, Component>,
+}
+
+/// Last time each background worker went through its loop.
+#[derive(Default)]
+pub struct Heartbeats {
+    workers: Mutex<BTreeMap<String, Arc<Beat>>>,
+}
+
+struct Beat {
+    interval: Duration,
+    last: Mutex<Instant>,
+}
+
+/// Handle a worker uses to report it is alive.
+pub struct Heartbeat(Arc<Beat>);
+
+impl Heartbeat {
+    pub fn beat(&self) {
+        *self.0.last.lock().unwrap() = Instant::now();
+    }
+}
+
+impl Heartbeats {
+    pub fn new() -> Heartbeats {
+        Heartbeats::default()
+    }
+
+    /// Tracks a worker expected to beat about every `interval`.
+    pub fn register(&self, name: &str, interval: Duration) -> Heartbeat {
+        let beat = Arc::new(Beat {
+            interval,
+            last: Mutex::new(Instant::now()),
+        });
+        self.workers.lock().unwrap().insert(name.to_string(), beat.clone());
+        Heartbeat(beat)
+    }
+
+    /// Seconds since each worker's last beat, and whether any is late.
+    fn check(&self) -> (BTreeMap<String, f64>, bool) {
+        let mut late = false;
+        let ages = self
+            .workers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, beat)| {
+                let age = beat.last.lock().unwrap().elapsed();
+                late |= age > beat.interval * 3 + LATE_WORKER_GRACE;
+                (name.clone(), age.as_secs_f64())
+            })
+            .collect();
+        (ages, late)
+    }
+}
+
+/// `GET /health`: the state of every component the instance depends on. The
+/// response is `503` when any of them fails and `200` otherwise, with
+/// warnings only in the body.
+pub async fn health(d: web::Data<MyData>, _: HttpRequest) -> Result<HttpResponse, actix_web::Error> {
+    let mut components = BTreeMap::new();
+
+    let started = Instant::now();
+    let round_trip = db::ping_db(&d.pool).await;
+    let elapsed = started.elapsed();
+    components.insert(
+        "banco",
+        match round_trip {
+            Ok(()) => Component {
+                status: if elapsed > SLOW_ROUND_TRIP { Status::Warn } else { Status::Pass },
+                details: json!({ "latencia_ms": elapsed.as_secs_f64() * 1000.0 }),
+            },
+            Err(err) => Component {
+                status: Status::Fail,
+                details: json!({ "erro": err.to_string() }),
+            },
+        },
+    );
+
+    let in_use = d.pool.size().saturating_sub(d.pool.num_idle() as u32);
+    let utilization = in_use as f64 / d.db_max_connections.max(1) as f64;
+    components.insert(
+        "pool",
+        Component {
+            status: if utilization >= BUSY_POOL_RATIO { Status::Warn } else { Status::Pass },
+            details: json!({
+                "em_uso": in_use,
+                "ociosas": d.pool.num_idle(),
+                "maximo": d.db_max_connections,
+                "utilizacao": utilization,
+            }),
+        },
+    );
+
+    let expected = db::latest_migration_version();
+    components.insert(
+        "migracoes",
+        match db::applied_migration_version_db(&d.pool).await {
+            Ok(applied) => Component {
+                status: if applied < Some(expected) { Status::Warn } else { Status::Pass },
+                details: json!({ "aplicada": applied, "esperada": expected }),
+            },
+            Err(err) => Component {
+                status: Status::Fail,
+                details: json!({ "erro": err.to_string() }),
+            },
+        },
+    );
+
+    let read_model = d.read_model.as_ref().map(|model| model.is_ready());
+    let degraded = d.transactions.degraded();
+    components.insert(
+        "cache",
+        Component {
+            status: if read_model == Some(false) || degraded == Some(true) {
+                Status::Warn
+            } else {
+                Status::Pass
+            },
+            details: json!({ "read_model_pronto": read_model, "modo_degradado": degraded }),
+        },
+    );
+
+    let (workers, late) = d.heartbeats.check();
+    components.insert(
+        "workers",
+        Component {
+            status: if late { Status::Warn } else { Status::Pass },
+            details: json!({ "segundos_desde_ultimo_sinal": workers }),
+        },
+    );
+
+    let status = components
+        .values()
+        .map(|component| component.status)
+        .max()
+        .unwrap_or(Status::Pass);
+    let code = if status == Status::Fail {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+
+    let res = serde_json::to_string(&HealthResponse { status, components })
+        .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::build(code).content_type(ContentType::json()).body(res))
+}
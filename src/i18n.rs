@@ -0,0 +1,89 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::ACCEPT_LANGUAGE;
+use actix_web::middleware::Next;
+
+/// Languages user-facing messages are available in.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Locale {
+    #[default]
+    PtBr,
+    En,
+}
+
+impl Locale {
+    fn from_tag(tag: &str) -> Option<Locale> {
+        let primary = tag.split('-').next().unwrap_or_default().to_lowercase();
+        match primary.as_str() {
+            "pt" => Some(Locale::PtBr),
+            "en" => Some(Locale::En),
+            _ => None,
+        }
+    }
+
+    /// Picks the supported language with the highest weight in an
+    /// `Accept-Language` header, falling back to pt-BR.
+    pub fn negotiate(accept_language: &str) -> Locale {
+        let mut best: Option<(Locale, f32)> = None;
+        for range in accept_language.split(',') {
+            let mut parts = range.split(';').map(str::trim);
+            let locale = match parts.next().and_then(Locale::from_tag) {
+                Some(locale) => locale,
+                None => continue,
+            };
+            let weight = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if weight > 0.0 && best.is_none_or(|(_, best)| weight > best) {
+                best = Some((locale, weight));
+            }
+        }
+        best.map(|(locale, _)| locale).unwrap_or_default()
+    }
+}
+
+/// A user-facing message in every supported language.
+#[derive(Debug, Clone, Copy)]
+pub struct Text {
+    pub pt_br: &'static str,
+    pub en: &'static str,
+}
+
+impl Text {
+    pub const fn new(pt_br: &'static str, en: &'static str) -> Text {
+        Text { pt_br, en }
+    }
+
+    /// The message in the language negotiated for the current request.
+    pub fn localized(&self) -> &'static str {
+        match current() {
+            Locale::PtBr => self.pt_br,
+            Locale::En => self.en,
+        }
+    }
+}
+
+tokio::task_local! {
+    static CURRENT: Locale;
+}
+
+/// Language negotiated for the request handled by the current task; pt-BR
+/// outside a request.
+pub fn current() -> Locale {
+    CURRENT.try_with(|locale| *locale).unwrap_or_default()
+}
+
+pub async fn negotiate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let locale = req
+        .headers()
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .map(Locale::negotiate)
+        .unwrap_or_default();
+
+    CURRENT.scope(locale, next.call(req)).await
+}
@@ -0,0 +1,79 @@
+//! Time-ordered 64-bit transaction ids assigned in the application, so
+//! inserting a transaction doesn't go through the table's sequence.
+//! Creation times are stamped by the database, under the customer's row
+//! lock.
+//!
+//! An id is the milliseconds since `EPOCH_MS` in the top 41 bits, then the
+//! worker id in 10 bits and a per-millisecond sequence in the low 12 bits.
+//! Ids of different workers never collide, and ids of all workers sort by
+//! creation time up to clock skew between their hosts.
+
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 2024-01-01T00:00:00Z.
+const EPOCH_MS: u64 = 1_704_067_200_000;
+const WORKER_BITS: u32 = 10;
+const SEQUENCE_BITS: u32 = 12;
+const SEQUENCE_MASK: u64 = (1 << SEQUENCE_BITS) - 1;
+
+pub const MAX_WORKER_ID: u16 = (1 << WORKER_BITS) - 1;
+
+pub struct Snowflake {
+    worker_id: u64,
+    /// Millisecond of the last id and its sequence number.
+    state: Mutex<(u64, u64)>,
+}
+
+impl Snowflake {
+    /// `worker_id` must be unique among the instances writing to the same
+    /// database and at most `MAX_WORKER_ID`.
+    pub fn new(worker_id: u16) -> Snowflake {
+        Snowflake {
+            worker_id: (worker_id & MAX_WORKER_ID) as u64,
+            state: Mutex::new((0, 0)),
+        }
+    }
+
+    /// The next id, greater than every id this generator returned before.
+    /// When the clock goes back or a millisecond's sequence runs out, ids
+    /// borrow from the following milliseconds instead of waiting.
+    pub fn next_id(&self) -> i64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default()
+            .saturating_sub(EPOCH_MS);
+
+        let mut state = self.state.lock().unwrap();
+        let (last_ms, sequence) = *state;
+        *state = if now > last_ms {
+            (now, 0)
+        } else if sequence < SEQUENCE_MASK {
+            (last_ms, sequence + 1)
+        } else {
+            (last_ms + 1, 0)
+        };
+
+        let (ms, sequence) = *state;
+        ((ms << (WORKER_BITS + SEQUENCE_BITS)) | (self.worker_id << SEQUENCE_BITS) | sequence) as i64
+    }
+}
+
+/// What new transactions get from the app besides their data: an id, unless
+/// the table's sequence assigns them.
+pub struct TransactionKeys {
+    snowflake: Option<Snowflake>,
+}
+
+impl TransactionKeys {
+    pub fn new(worker_id: Option<u16>) -> TransactionKeys {
+        TransactionKeys {
+            snowflake: worker_id.map(Snowflake::new),
+        }
+    }
+
+    pub fn next(&self) -> Option<i64> {
+        self.snowflake.as_ref().map(Snowflake::next_id)
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error_catalog::{self, Entry};
+use crate::{db, errors};
+
+/// A CSV row as read, before validation. Empty cells count as missing.
+#[derive(Debug, Deserialize)]
+struct Row {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default, alias = "limit")]
+    limite: Option<String>,
+    #[serde(default, alias = "initial_balance")]
+    saldo_inicial: Option<String>,
+}
+
+/// A rejected row and why, with its line in the upload.
+#[derive(Debug, Serialize)]
+pub struct RowError {
+    #[serde(rename = "linha")]
+    pub line: u64,
+    #[serde(rename = "codigo")]
+    pub code: &'static str,
+    #[serde(rename = "erro")]
+    pub message: &'static str,
+}
+
+impl RowError {
+    pub fn new(line: u64, entry: &'static Entry) -> RowError {
+        RowError {
+            line,
+            code: entry.code,
+            message: entry.text.localized(),
+        }
+    }
+}
+
+/// Outcome of reading an upload.
+#[derive(Debug)]
+pub struct Parsed {
+    /// Valid rows, with their line.
+    pub customers: Vec<(u64, db::NewCustomer)>,
+    pub rejected: Vec<RowError>,
+}
+
+/// Reads customers from a CSV with a header naming the columns `id`
+/// (optional), `limite` and `saldo_inicial` (0 when missing), in any order.
+/// Ids must be positive and unique within the file, limits non-negative, and
+/// initial balances no lower than the negated limit. Valid rows come back
+/// with their line; the rest as errors. Fails as a whole only when the
+/// header lacks `limite`.
+pub fn parse(csv: &[u8]) -> Result<Parsed, errors::AppError> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(csv);
+    let headers = match reader.headers() {
+        Ok(headers) if headers.iter().any(|name| name == "limite" || name == "limit") => headers.clone(),
+        _ => return Err(errors::AppError::ErrValidation(&error_catalog::INVALID_IMPORT_HEADER)),
+    };
+
+    let mut customers = Vec::new();
+    let mut rejected = Vec::new();
+    let mut ids = HashSet::new();
+    let mut record = csv::StringRecord::new();
+    loop {
+        let row = match reader.read_record(&mut record) {
+            Ok(false) => break,
+            Ok(true) => record.deserialize::<Row>(Some(&headers)).ok(),
+            Err(_) => None,
+        };
+        let line = record.position().map_or(0, |position| position.line());
+        let row = match row {
+            Some(row) => validate(row, &mut ids),
+            None => Err(&error_catalog::IMPORT_MALFORMED_ROW),
+        };
+        match row {
+            Ok(customer) => customers.push((line, customer)),
+            Err(entry) => rejected.push(RowError::new(line, entry)),
+        }
+    }
+    Ok(Parsed {
+        customers,
+        rejected,
+    })
+}
+
+fn validate(row: Row, ids: &mut HashSet<i32>) -> Result<db::NewCustomer, &'static Entry> {
+    let present = |cell: Option<String>| cell.filter(|cell| !cell.is_empty());
+
+    let id = match present(row.id) {
+        Some(id) => match id.parse::<i32>() {
+            Ok(id) if id > 0 => Some(id),
+            _ => return Err(&error_catalog::IMPORT_INVALID_ID),
+        },
+        None => None,
+    };
+    let limit = match present(row.limite).map(|limit| limit.parse::<i32>()) {
+        Some(Ok(limit)) if limit >= 0 => limit,
+        _ => return Err(&error_catalog::IMPORT_INVALID_LIMIT),
+    };
+    let balance = match present(row.saldo_inicial).map(|balance| balance.parse::<i32>()) {
+        None => 0,
+        Some(Ok(balance)) if balance >= -limit => balance,
+        Some(_) => return Err(&error_catalog::IMPORT_INVALID_BALANCE),
+    };
+    if let Some(id) = id {
+        if !ids.insert(id) {
+            return Err(&error_catalog::IMPORT_DUPLICATE_ID);
+        }
+    }
+
+    Ok(db::NewCustomer { id, limit, balance })
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::{config, db, health};
+
+pub type JobError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Work executed for every job of a given kind. Returning an error schedules
+/// another attempt, with exponential backoff, until the job runs out of
+/// attempts and is marked failed.
+#[async_trait]
+pub trait JobHandler: Send + Sync {
+    async fn run(&self, payload: &serde_json::Value) -> Result<(), JobError>;
+
+    /// Called once when the last attempt of a job fails.
+    async fn on_exhausted(&self, _job: &db::Job, _error: &str) -> Result<(), JobError> {
+        Ok(())
+    }
+}
+
+/// Pool of workers processing the `jobs` table. Every instance can run one;
+/// rows are claimed with `SKIP LOCKED`, so each job runs on a single worker.
+pub struct JobRunner {
+    pool: sqlx::Pool<sqlx::Postgres>,
+    cfg: config::JobsConfig,
+    handlers: HashMap<String, Arc<dyn JobHandler>>,
+    recurring: Vec<(String, Duration)>,
+}
+
+impl JobRunner {
+    pub fn new(pool: sqlx::Pool<sqlx::Postgres>, cfg: config::JobsConfig) -> JobRunner {
+        JobRunner {
+            pool,
+            cfg,
+            handlers: HashMap::new(),
+            recurring: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, kind: &str, handler: Arc<dyn JobHandler>) {
+        self.handlers.insert(kind.to_string(), handler);
+    }
+
+    /// Registers `handler` for a kind that runs periodically: a job of the
+    /// kind is kept scheduled, due `interval` after the previous one finished.
+    /// Its payload is empty and a failed run isn't retried, the next one is
+    /// simply due an interval later.
+    pub fn schedule(&mut self, kind: &str, handler: Arc<dyn JobHandler>, interval: Duration) {
+        self.register(kind, handler);
+        self.recurring.push((kind.to_string(), interval));
+    }
+
+    pub fn spawn(self, heartbeats: &health::Heartbeats) {
+        if self.cfg.workers == 0 {
+            return;
+        }
+
+        let runner = Arc::new(self);
+        let poll_interval = Duration::from_millis(runner.cfg.poll_interval_ms);
+        for n in 0..runner.cfg.workers {
+            let runner = runner.clone();
+            let worker_id = format!("{}-{}", runner.cfg.worker_id, n);
+            let heartbeat = heartbeats.register(&format!("jobs-{}", n), poll_interval);
+            tokio::spawn(async move { runner.work(worker_id, heartbeat).await });
+        }
+        if !runner.recurring.is_empty() {
+            let runner = runner.clone();
+            let heartbeat = heartbeats.register("jobs-scheduler", runner.schedule_interval());
+            tokio::spawn(async move { runner.schedule_recurring(heartbeat).await });
+        }
+        tokio::spawn(async move { runner.release_stale().await });
+    }
+
+    async fn work(&self, worker_id: String, heartbeat: health::Heartbeat) {
+        let poll_interval = Duration::from_millis(self.cfg.poll_interval_ms);
+        loop {
+            heartbeat.beat();
+            match db::claim_job_db(&self.pool, &worker_id).await {
+                Ok(Some(job)) => self.run(job).await,
+                Ok(None) => tokio::time::sleep(poll_interval).await,
+                Err(err) => {
+                    log::error!("job worker {} failed to claim a job: {}", worker_id, err);
+                    tokio::time::sleep(poll_interval).await;
+                }
+            }
+        }
+    }
+
+    async fn run(&self, job: db::Job) {
+        let handler = self.handlers.get(&job.kind);
+        let result = match handler {
+            Some(handler) => handler.run(&job.payload).await,
+            None => Err(format!("no handler registered for job kind {}", job.kind).into()),
+        };
+
+        let saved = match result {
+            Ok(()) => db::complete_job_db(&self.pool, job.id).await,
+            Err(err) => {
+                let retry_in = (job.attempts < job.max_attempts).then(|| self.backoff(job.attempts));
+                if let (None, Some(handler)) = (retry_in, handler) {
+                    if let Err(hook_err) = handler.on_exhausted(&job, &err.to_string()).await {
+                        log::error!("exhaustion hook of job {} failed: {}", job.id, hook_err);
+                    }
+                }
+                log::warn!(
+                    "job {} ({}) attempt {}/{} failed: {}",
+                    job.id,
+                    job.kind,
+                    job.attempts,
+                    job.max_attempts,
+                    err
+                );
+                db::fail_job_db(&self.pool, job.id, &err.to_string(), retry_in).await
+            }
+        };
+
+        if let Err(err) = saved {
+            log::error!("saving the outcome of job {} failed: {}", job.id, err);
+        }
+    }
+
+    /// Delay before the next attempt: the base delay doubled for every
+    /// attempt already made, capped at the configured maximum.
+    fn backoff(&self, attempts: i32) -> Duration {
+        let exponent = attempts.saturating_sub(1).clamp(0, 20) as u32;
+        let delay = self.cfg.retry_delay_ms.saturating_mul(1 << exponent);
+        Duration::from_millis(delay.min(self.cfg.retry_max_delay_ms))
+    }
+
+    /// How often recurring kinds are checked: their shortest interval, but at
+    /// least once a minute so a finished run is followed by the next one soon.
+    fn schedule_interval(&self) -> Duration {
+        self.recurring
+            .iter()
+            .map(|(_, interval)| *interval)
+            .chain([Duration::from_secs(60)])
+            .min()
+            .unwrap_or_default()
+            .max(Duration::from_millis(self.cfg.poll_interval_ms))
+    }
+
+    async fn schedule_recurring(&self, heartbeat: health::Heartbeat) {
+        let interval = self.schedule_interval();
+        loop {
+            heartbeat.beat();
+            for (kind, every) in &self.recurring {
+                if let Err(err) = db::schedule_recurring_job_db(&self.pool, kind, *every).await {
+                    log::error!("scheduling the next {} job failed: {}", kind, err);
+                }
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    async fn release_stale(&self) {
+        let interval = Duration::from_secs(self.cfg.lock_timeout_secs.max(1) as u64);
+        loop {
+            tokio::time::sleep(interval).await;
+            match db::release_stale_jobs_db(&self.pool, self.cfg.lock_timeout_secs).await {
+                Ok(0) => {}
+                Ok(n) => log::warn!("released {} jobs left running by dead workers", n),
+                Err(err) => log::error!("releasing stale jobs failed: {}", err),
+            }
+        }
+    }
+}
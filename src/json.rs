@@ -0,0 +1,91 @@
+//! JSON encoding of the hot endpoints' bodies. It goes through serde_json by
+//! default and through simd-json when built with the `simd-json` feature; both
+//! use the same serde derives, so the wire format doesn't change.
+
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::schema::{self, Dialect};
+
+#[derive(Debug)]
+pub struct Error(Inner);
+
+#[cfg(not(feature = "simd-json"))]
+type Inner = serde_json::Error;
+#[cfg(feature = "simd-json")]
+type Inner = simd_json::Error;
+
+impl Error {
+    fn custom(err: serde_json::Error) -> Error {
+        Error(serde::ser::Error::custom(err))
+    }
+
+    /// Whether the input was valid JSON that doesn't fit the target type, as
+    /// opposed to input that isn't JSON at all.
+    #[cfg(not(feature = "simd-json"))]
+    pub fn is_data(&self) -> bool {
+        self.0.is_data()
+    }
+
+    #[cfg(feature = "simd-json")]
+    pub fn is_data(&self) -> bool {
+        use simd_json::ErrorType;
+
+        matches!(
+            self.0.error(),
+            ErrorType::Unexpected(..)
+                | ErrorType::Serde(_)
+                | ErrorType::ExpectedArray
+                | ErrorType::ExpectedBoolean
+                | ErrorType::ExpectedEnum
+                | ErrorType::ExpectedFloat
+                | ErrorType::ExpectedInteger
+                | ErrorType::ExpectedMap
+                | ErrorType::ExpectedNumber
+                | ErrorType::ExpectedSigned
+                | ErrorType::ExpectedString
+                | ErrorType::ExpectedUnsigned
+        )
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Decodes `bytes`. simd-json parses in place, so the buffer may be left
+/// modified.
+pub fn from_slice<T: DeserializeOwned>(bytes: &mut [u8]) -> Result<T, Error> {
+    #[cfg(not(feature = "simd-json"))]
+    let value = serde_json::from_slice(bytes);
+    #[cfg(feature = "simd-json")]
+    let value = simd_json::from_slice(bytes);
+    value.map_err(Error)
+}
+
+pub fn to_string<T: Serialize>(value: &T) -> Result<String, Error> {
+    #[cfg(not(feature = "simd-json"))]
+    let encoded = serde_json::to_string(value);
+    #[cfg(feature = "simd-json")]
+    let encoded = simd_json::to_string(value);
+    encoded.map_err(Error)
+}
+
+/// Encodes `value` with the field names of `dialect`. Renaming goes through
+/// serde_json's tree, so English bodies take the slow path.
+pub fn to_string_in<T: Serialize>(value: &T, dialect: Dialect) -> Result<String, Error> {
+    match dialect {
+        Dialect::Portuguese => to_string(value),
+        Dialect::English => {
+            let mut tree = serde_json::to_value(value).map_err(Error::custom)?;
+            schema::to_english(&mut tree);
+            serde_json::to_string(&tree).map_err(Error::custom)
+        }
+    }
+}
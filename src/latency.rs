@@ -0,0 +1,136 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::web;
+use hdrhistogram::Histogram;
+use serde::Serialize;
+
+use crate::server;
+
+/// Width of each histogram in a route's ring. The reported window is rounded
+/// to whole slots.
+const SLOT: Duration = Duration::from_secs(1);
+/// Highest latency tracked precisely; slower requests are recorded as this.
+const MAX_LATENCY_US: u64 = 60_000_000;
+const SIGNIFICANT_DIGITS: u8 = 3;
+
+/// Rolling latency histograms per route, covering the last `window`.
+pub struct LatencyTracker {
+    window: Duration,
+    started: Instant,
+    routes: Mutex<HashMap<String, VecDeque<Slot>>>,
+}
+
+/// Histogram of the requests that finished during one `SLOT`, numbered from
+/// the tracker's start.
+struct Slot {
+    index: u64,
+    histogram: Histogram<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RouteLatency {
+    #[serde(rename = "requisicoes")]
+    count: u64,
+    p50_us: u64,
+    p90_us: u64,
+    p99_us: u64,
+    p999_us: u64,
+    max_us: u64,
+}
+
+impl LatencyTracker {
+    pub fn new(window: Duration) -> LatencyTracker {
+        LatencyTracker {
+            window,
+            started: Instant::now(),
+            routes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    fn slots(&self) -> u64 {
+        (self.window.as_secs() / SLOT.as_secs()).max(1)
+    }
+
+    fn current_slot(&self) -> u64 {
+        self.started.elapsed().as_secs() / SLOT.as_secs()
+    }
+
+    pub fn record(&self, route: String, elapsed: Duration) {
+        let index = self.current_slot();
+        let oldest = index.saturating_sub(self.slots() - 1);
+        let latency_us = (elapsed.as_micros() as u64).clamp(1, MAX_LATENCY_US);
+
+        let mut routes = self.routes.lock().unwrap();
+        let ring = routes.entry(route).or_default();
+        while ring.front().is_some_and(|slot| slot.index < oldest) {
+            ring.pop_front();
+        }
+        if ring.back().map(|slot| slot.index) != Some(index) {
+            // Bounds are constants known to be valid.
+            let histogram =
+                Histogram::new_with_bounds(1, MAX_LATENCY_US, SIGNIFICANT_DIGITS).unwrap();
+            ring.push_back(Slot { index, histogram });
+        }
+        if let Some(slot) = ring.back_mut() {
+            slot.histogram.saturating_record(latency_us);
+        }
+    }
+
+    /// Percentiles per route over the window, keyed by `METHOD pattern`.
+    pub fn summary(&self) -> BTreeMap<String, RouteLatency> {
+        let oldest = self.current_slot().saturating_sub(self.slots() - 1);
+        let routes = self.routes.lock().unwrap();
+
+        routes
+            .iter()
+            .filter_map(|(route, ring)| {
+                let mut merged: Option<Histogram<u64>> = None;
+                for slot in ring.iter().filter(|slot| slot.index >= oldest) {
+                    match &mut merged {
+                        Some(merged) => merged.add(&slot.histogram).ok()?,
+                        None => merged = Some(slot.histogram.clone()),
+                    }
+                }
+                let merged = merged.filter(|histogram| !histogram.is_empty())?;
+                let latency = RouteLatency {
+                    count: merged.len(),
+                    p50_us: merged.value_at_quantile(0.5),
+                    p90_us: merged.value_at_quantile(0.9),
+                    p99_us: merged.value_at_quantile(0.99),
+                    p999_us: merged.value_at_quantile(0.999),
+                    max_us: merged.max(),
+                };
+                Some((route.clone(), latency))
+            })
+            .collect()
+    }
+}
+
+pub async fn track(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let started = Instant::now();
+    let res = next.call(req).await?;
+
+    // Unmatched requests are left out, so arbitrary paths can't grow the map.
+    let route = res
+        .request()
+        .match_pattern()
+        .map(|pattern| format!("{} {}", res.request().method(), pattern));
+    let data = res.request().app_data::<web::Data<server::MyData>>();
+    if let (Some(route), Some(data)) = (route, data) {
+        data.latency.record(route, started.elapsed());
+    }
+
+    Ok(res)
+}
@@ -0,0 +1,7 @@
+//! Types and encodings shared between the server binary, API clients and
+//! benchmarks.
+
+#[cfg(feature = "client")]
+pub mod client;
+pub mod json;
+pub mod schema;
@@ -0,0 +1,35 @@
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::config;
+
+/// Creates the listening socket with the options in `ListenConfig`. Accepted
+/// connections inherit TCP_NODELAY, SO_LINGER and the buffer sizes from the
+/// listener on Linux, so they don't need to be set per connection. The server
+/// still sets TCP_NODELAY on each accepted connection explicitly.
+pub fn bind(port: u16, cfg: &config::ListenConfig) -> io::Result<TcpListener> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
+
+    socket.set_reuse_address(true)?;
+    socket.set_nodelay(cfg.tcp_nodelay)?;
+    if let Some(linger_secs) = cfg.linger_secs {
+        socket.set_linger(Some(Duration::from_secs(linger_secs)))?;
+    }
+    // Buffer sizes have to be set before listening to affect the TCP window
+    // scale negotiated with clients.
+    if let Some(size) = cfg.recv_buffer_size {
+        socket.set_recv_buffer_size(size)?;
+    }
+    if let Some(size) = cfg.send_buffer_size {
+        socket.set_send_buffer_size(size)?;
+    }
+
+    socket.bind(&addr.into())?;
+    socket.listen(cfg.backlog as i32)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
@@ -1,18 +1,319 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use actix_web::web;
 
+mod access_log;
+mod adapters;
+mod admin;
+mod archival;
+mod body_log;
+mod buffered;
+mod chaos;
+#[cfg(feature = "client")]
+mod cli;
 mod config;
+mod consistency;
+mod context;
+mod daily_balances;
 mod db;
+mod deadline;
+mod dedup;
+mod degraded;
+mod dialect;
+mod disconnect;
+mod domain;
+mod error_catalog;
 mod errors;
+mod events;
+mod failover;
+mod feed;
+mod health;
+mod i18n;
+mod import;
+mod ids;
+mod jobs;
+mod latency;
+mod listener;
+mod maintenance;
+mod methods;
+mod metrics;
+mod mirror;
+mod outbox;
+mod pagination;
+mod panics;
+mod ports;
+mod replication;
+mod rules;
+mod request_id;
+mod response_policy;
+mod rls;
 mod server;
+mod service;
+#[cfg(feature = "client")]
+mod smoke;
+mod statsd;
+mod tenant;
+mod warmup;
+mod validation;
+mod version;
+mod webhooks;
 
 
 #[tokio::main]
 async fn main() -> Result<(), errors::CustomError> {
+    #[cfg(feature = "client")]
+    {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        match cli::parse(&args) {
+            Ok(None) => {}
+            Ok(Some(invocation)) => cli::exit_on_error(cli::run(invocation).await),
+            Err(err) => cli::exit_on_error(Err(err)),
+        }
+    }
+
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("debug"));
+
     let cfg = config::load_config()?;
     println!("Config: {:?}", cfg);
 
-    let pool = db::get_pool(cfg.db_conn_string.as_str(), cfg.db_n_max_connections).await?;
-    let server_data = web::Data::new(server::MyData { pool });
+    let schema = tenant::Schema(Arc::from(cfg.db_schema.as_str()));
+    // Set on every connection option, so hosts failed over to keep it.
+    let client_check_interval = ((cfg.disconnect.enabled || cfg.request_timeout_ms.is_some())
+        && cfg.disconnect.db_check_interval_ms > 0)
+        .then(|| Duration::from_millis(cfg.disconnect.db_check_interval_ms));
+    let mut connect_options = db::with_client_check(
+        db::connect_options(&cfg.db_conn_string, &cfg.db_connection)?,
+        client_check_interval,
+    );
+    let failover = failover::Failover::from_config(&connect_options, &cfg.db_failover)?.map(Arc::new);
+    if let Some(failover) = &failover {
+        connect_options = failover.elect().await?;
+    }
+    let hooks = db::SessionHooks {
+        schema: schema.clone(),
+        tenant_schema: !cfg.tenants.registry.is_empty(),
+        current_customer: cfg.row_level_security,
+    };
+    let pool = db::get_pool(
+        connect_options.clone(),
+        cfg.db_n_max_connections,
+        hooks.clone(),
+        failover.clone(),
+    )
+    .await?;
+    let mut primary_pools = vec![pool.clone()];
+
+    let mut pools = db::PoolRouter::new(pool.clone(), cfg.db_routing.routes.clone());
+    if let Some(replica) = &cfg.db_routing.replica_conn_string {
+        let replica_options = db::with_client_check(
+            db::connect_options(
+                &replica.0,
+                &config::DbConnectionConfig {
+                    socket: None,
+                    ..cfg.db_connection.clone()
+                },
+            )?,
+            client_check_interval,
+        );
+        let replica = db::get_pool(
+            replica_options,
+            cfg.db_routing.replica_max_connections,
+            hooks.clone(),
+            None,
+        )
+        .await?;
+        pools = pools.with_pool(db::PoolClass::Replica, replica);
+    }
+    if cfg.db_routing.bulk_max_connections > 0 {
+        let bulk = db::get_pool(
+            connect_options.clone(),
+            cfg.db_routing.bulk_max_connections,
+            hooks,
+            failover.clone(),
+        )
+        .await?;
+        primary_pools.push(bulk.clone());
+        pools = pools.with_pool(db::PoolClass::Bulk, bulk);
+    }
+    if cfg.db_run_migrations {
+        db::run_migrations(&pool, &schema, &tenant::schemas(&cfg.tenants)).await?;
+    }
+
+    let heartbeats = health::Heartbeats::new();
+
+    if let Some(failover) = &failover {
+        failover.clone().spawn(primary_pools, &heartbeats);
+    }
+
+    let mut job_runner = jobs::JobRunner::new(pool.clone(), cfg.jobs.clone());
+    job_runner.register(
+        webhooks::DELIVERY_JOB_KIND,
+        Arc::new(webhooks::DeliveryHandler::new(pool.clone(), &cfg.webhooks)),
+    );
+
+    daily_balances::register(
+        &mut job_runner,
+        pool.clone(),
+        cfg.daily_balances.clone(),
+        tenant::schemas(&cfg.tenants),
+    );
+
+    let mirror = mirror::Mirror::from_config(&cfg.mirror)?.map(Arc::new);
+
+    let outbox_enabled = cfg.events.backend != events::EventsBackend::None;
+    if outbox_enabled {
+        let publisher = events::connect(&cfg.events).await?;
+        outbox::spawn_relay(
+            pool.clone(),
+            publisher,
+            Duration::from_millis(cfg.events.relay_interval_ms),
+            &heartbeats,
+        );
+    }
+
+    let metrics = Arc::new(metrics::Metrics::new());
+    statsd::spawn(metrics.clone(), cfg.statsd.clone());
+    let feed = Arc::new(feed::Feed::new());
+
+    let mut read_model = None;
+    let (statements, transactions): (
+        Arc<dyn ports::StatementPort>,
+        Arc<dyn ports::TransactionPort>,
+    ) = match cfg.storage_backend {
+        adapters::StorageBackend::Postgres => {
+            let side_effects = db::SideEffects {
+                outbox: outbox_enabled,
+                webhook_max_attempts: cfg
+                    .webhooks
+                    .enabled
+                    .then_some(cfg.webhooks.max_attempts),
+            };
+            let keys = Arc::new(ids::TransactionKeys::new(cfg.id_worker_id));
+            let adapter = Arc::new(adapters::postgres::PostgresAdapter::new(
+                pool.clone(),
+                pools.pool(db::RoutedQuery::Statement),
+                side_effects,
+                cfg.write.clone(),
+                metrics.clone(),
+                keys.clone(),
+            ));
+            let statements: Arc<dyn ports::StatementPort> = if cfg.read_model.enabled {
+                let model = Arc::new(adapters::read_model::ReadModel::new());
+                replication::spawn(
+                    connect_options.clone(),
+                    failover.clone(),
+                    schema.clone(),
+                    model.clone(),
+                    Duration::from_millis(cfg.read_model.poll_interval_ms),
+                    &heartbeats,
+                );
+                read_model = Some(model.clone());
+                Arc::new(adapters::read_model::ReadModelAdapter::new(model, adapter.clone()))
+            } else {
+                adapter.clone()
+            };
+            let transactions: Arc<dyn ports::TransactionPort> =
+                if cfg.write.group_commit_window_us > 0 {
+                    Arc::new(adapters::group_commit::GroupCommitWriter::new(
+                        pool.clone(),
+                        side_effects,
+                        keys,
+                        cfg.write.clone(),
+                        metrics.clone(),
+                    ))
+                } else {
+                    adapter
+                };
+            if cfg.redis.url.is_some() && !cfg.read_model.enabled {
+                adapters::redis::wrap(&cfg.redis, schema.clone(), pools.pool(db::RoutedQuery::Statement), statements, transactions).await?
+            } else {
+                (statements, transactions)
+            }
+        }
+        adapters::StorageBackend::Memory => {
+            let adapter = Arc::new(adapters::memory::MemoryAdapter::new());
+            (adapter.clone(), adapter)
+        }
+    };
+    let transactions: Arc<dyn ports::TransactionPort> = if cfg.write.shards > 0 {
+        Arc::new(adapters::sharded::ShardedWriter::new(
+            transactions,
+            cfg.write.shards,
+            cfg.write.queue_size,
+        ))
+    } else {
+        transactions
+    };
+    archival::register(
+        &mut job_runner,
+        pool.clone(),
+        statements.clone(),
+        cfg.archival.clone(),
+        tenant::schemas(&cfg.tenants),
+    );
+    job_runner.spawn(&heartbeats);
+
+    let validators = validation::Pipeline::from_config(&cfg.validation, statements.clone())?;
+    let transactions = service::TransactionService::new(
+        statements,
+        transactions,
+        feed.clone(),
+        validators,
+        degraded::DegradedMode::from_config(&cfg.degraded, failover.clone()),
+        dedup::Dedup::from_config(&cfg.dedup),
+    );
+
+    let server_data = web::Data::new(server::MyData {
+        pool,
+        pools,
+        chaos: cfg.chaos.clone(),
+        mirror,
+        body_log: cfg.body_log.clone(),
+        access_log_format: cfg.access_log_format,
+        created_responses: cfg.created_responses,
+        api_dialect: cfg.api_dialect,
+        feed,
+        transactions,
+        webhooks: cfg.webhooks.clone(),
+        metrics,
+        latency_enabled: cfg.latency.enabled,
+        latency: latency::LatencyTracker::new(Duration::from_secs(cfg.latency.window_secs)),
+        admin_token: cfg.admin_token.clone(),
+        db_max_connections: cfg.db_n_max_connections,
+        warmup_lock: tokio::sync::Mutex::new(()),
+        maintenance: maintenance::Maintenance::new(),
+        tenants: cfg.tenants.clone(),
+        row_level_security: cfg.row_level_security,
+        cancel_on_disconnect: cfg.disconnect.enabled,
+        request_timeout: cfg.request_timeout_ms.map(Duration::from_millis),
+        read_model,
+        heartbeats,
+        pagination: cfg.pagination.clone(),
+    });
+
+    if cfg.boot_warmup {
+        boot_warmup(&server_data).await;
+    }
+
+    server::run_server(server_data, cfg.port, &cfg.listen).await
+}
+
+/// Touches every customer and opens the whole pool through the hot queries,
+/// then runs the JSON paths once, so the first seconds of traffic don't
+/// include cold-start costs. Failures are reported but don't stop startup.
+async fn boot_warmup(data: &server::MyData) {
+    match warmup::warm_up(&data.pool, data.db_max_connections).await {
+        Ok(report) => log::info!(
+            "Warm-up: {} customers, {} connections in {}ms",
+            report.customers,
+            report.connections,
+            report.elapsed.as_millis()
+        ),
+        Err(err) => log::warn!("Warm-up failed: {:?}", err),
+    }
 
-    server::run_server(server_data, cfg.port).await
+    if let Err(err) = warmup::warm_serialization() {
+        log::warn!("Serialization warm-up failed: {}", err);
+    }
 }
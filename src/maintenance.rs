@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::web;
+
+use crate::{error_catalog, errors, server};
+
+/// Paths still served while in maintenance, so operators can inspect the
+/// instance and turn maintenance off again.
+const EXEMPT_PREFIXES: [&str; 2] = ["/admin", "/metrics"];
+
+/// Maintenance flag shared by every worker, flipped through
+/// `POST /admin/maintenance`.
+#[derive(Default)]
+pub struct Maintenance {
+    enabled: AtomicBool,
+    retry_after_secs: AtomicU32,
+}
+
+impl Maintenance {
+    pub fn new() -> Maintenance {
+        Maintenance::default()
+    }
+
+    pub fn set(&self, enabled: bool, retry_after_secs: u32) {
+        self.retry_after_secs.store(retry_after_secs, Ordering::Relaxed);
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn retry_after_secs(&self) -> u32 {
+        self.retry_after_secs.load(Ordering::Relaxed)
+    }
+}
+
+/// Answers requests to public endpoints with `503` and `Retry-After` while
+/// maintenance is on.
+pub async fn guard(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let retry_after = req
+        .app_data::<web::Data<server::MyData>>()
+        .map(|data| &data.maintenance)
+        .filter(|maintenance| maintenance.enabled())
+        .map(Maintenance::retry_after_secs);
+    let exempt = EXEMPT_PREFIXES
+        .iter()
+        .any(|prefix| req.path().starts_with(prefix));
+
+    match retry_after {
+        Some(retry_after) if !exempt => {
+            let mut res = errors::error_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                &error_catalog::UNDER_MAINTENANCE,
+            );
+            res.headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+            let (req, _) = req.into_parts();
+            Ok(ServiceResponse::new(req, res))
+        }
+        _ => Ok(next.call(req).await?.map_into_boxed_body()),
+    }
+}
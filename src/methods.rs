@@ -0,0 +1,174 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderValue, ALLOW};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::HttpResponse;
+
+use crate::{error_catalog, errors};
+
+/// HEAD and OPTIONS support for every registered resource.
+///
+/// HEAD requests are routed as GET. The HTTP codec still knows the request
+/// was a HEAD, so it sends the GET response's headers, `Content-Length`
+/// included, without the body.
+///
+/// OPTIONS requests match no route and get the resource's `405` response,
+/// whose `Allow` header lists the methods registered for it. That response is
+/// replaced by a `204` advertising the same methods.
+///
+/// Any other method a resource doesn't serve keeps the `405`, with the
+/// `Allow` header completed with the methods provided here and a body in the
+/// unified error envelope.
+pub async fn handle(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let is_options = req.method() == Method::OPTIONS;
+    if req.method() == Method::HEAD {
+        req.head_mut().method = Method::GET;
+    }
+
+    let res = next.call(req).await?.map_into_boxed_body();
+    if res.status() != StatusCode::METHOD_NOT_ALLOWED {
+        return Ok(res);
+    }
+
+    let allow = res
+        .headers()
+        .get(ALLOW)
+        .and_then(|value| value.to_str().ok())
+        .map(allowed_methods);
+    let allow = match allow.and_then(|allow| HeaderValue::from_str(&allow).ok()) {
+        Some(allow) => allow,
+        None => return Ok(res),
+    };
+
+    if is_options {
+        let (req, _) = res.into_parts();
+        let res = HttpResponse::NoContent().insert_header((ALLOW, allow)).finish();
+        return Ok(ServiceResponse::new(req, res));
+    }
+
+    let (req, _) = res.into_parts();
+    let mut res = errors::error_response(
+        StatusCode::METHOD_NOT_ALLOWED,
+        &error_catalog::METHOD_NOT_ALLOWED,
+    );
+    res.headers_mut().insert(ALLOW, allow);
+    Ok(ServiceResponse::new(req, res))
+}
+
+/// Completes the methods a resource registered with HEAD, when it serves
+/// GET, and OPTIONS.
+fn allowed_methods(registered: &str) -> String {
+    let mut methods: Vec<&str> = registered
+        .split(',')
+        .map(str::trim)
+        .filter(|method| !method.is_empty())
+        .collect();
+
+    if methods.contains(&Method::GET.as_str()) && !methods.contains(&Method::HEAD.as_str()) {
+        methods.push(Method::HEAD.as_str());
+    }
+    if !methods.contains(&Method::OPTIONS.as_str()) {
+        methods.push(Method::OPTIONS.as_str());
+    }
+    methods.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+    use actix_web::{middleware, web, App, HttpResponse};
+
+    use super::*;
+
+    fn app() -> App<
+        impl actix_web::dev::ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<BoxBody>,
+            Error = actix_web::Error,
+            InitError = (),
+        >,
+    > {
+        App::new().wrap(middleware::from_fn(handle)).service(
+            web::resource("/clientes/{id}/transacoes")
+                .route(web::get().to(HttpResponse::Ok))
+                .route(web::post().to(HttpResponse::Ok)),
+        )
+    }
+
+    /// The service's own route table, whose handlers are never reached by
+    /// wrong-method requests.
+    fn served_routes() -> App<
+        impl actix_web::dev::ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<BoxBody>,
+            Error = actix_web::Error,
+            InitError = (),
+        >,
+    > {
+        App::new()
+            .wrap(middleware::from_fn(handle))
+            .configure(crate::server::routes)
+    }
+
+    #[actix_web::test]
+    async fn statement_answers_post_with_405() {
+        let app = init_service(served_routes()).await;
+        let req = TestRequest::post().uri("/clientes/1/extrato").to_request();
+        let res = call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.headers().get(ALLOW).unwrap(), "GET, HEAD, OPTIONS");
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["codigo"], error_catalog::METHOD_NOT_ALLOWED.code);
+    }
+
+    #[actix_web::test]
+    async fn unsupported_method_gets_405_with_allow() {
+        let app = init_service(served_routes()).await;
+        let req = TestRequest::delete().uri("/clientes/1/transacoes").to_request();
+        let res = call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.headers().get(ALLOW).unwrap(), "GET, POST, HEAD, OPTIONS");
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["codigo"], error_catalog::METHOD_NOT_ALLOWED.code);
+    }
+
+    #[actix_web::test]
+    async fn options_gets_204_with_allow() {
+        let app = init_service(app()).await;
+        let req = TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/clientes/1/transacoes")
+            .to_request();
+        let res = call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(res.headers().get(ALLOW).unwrap(), "GET, POST, HEAD, OPTIONS");
+    }
+
+    #[actix_web::test]
+    async fn head_is_served_as_get() {
+        let app = init_service(app()).await;
+        let req = TestRequest::default()
+            .method(Method::HEAD)
+            .uri("/clientes/1/transacoes")
+            .to_request();
+        let res = call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn allowed_methods_adds_head_only_with_get() {
+        assert_eq!(allowed_methods("GET"), "GET, HEAD, OPTIONS");
+        assert_eq!(allowed_methods("POST"), "POST, OPTIONS");
+        assert_eq!(allowed_methods("GET, HEAD, OPTIONS"), "GET, HEAD, OPTIONS");
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+
+use crate::db::RetryReason;
+use crate::errors::AppError;
+use crate::server::MyData;
+
+/// Counters describing business outcomes of the public endpoints, rendered in
+/// the Prometheus text format by `GET /metrics`.
+#[derive(Default)]
+pub struct Metrics {
+    limit_exceeded: AtomicU64,
+    customer_not_found: AtomicU64,
+    validation_failed: AtomicU64,
+    deadlock_retries: AtomicU64,
+    serialization_retries: AtomicU64,
+    pool_timeouts: AtomicU64,
+    deadlines_exceeded: AtomicU64,
+    duplicate_transactions: AtomicU64,
+    panics: AtomicU64,
+    client_disconnects: AtomicU64,
+    /// Successful transactions keyed by customer id and transaction type.
+    transactions: Mutex<BTreeMap<(i32, String), u64>>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Counts the outcomes of interest among application errors; the others
+    /// are ignored.
+    pub fn record_app_error(&self, err: &AppError) {
+        match err {
+            AppError::ErrNegativeTransactionBalance => {
+                self.limit_exceeded.fetch_add(1, Ordering::Relaxed);
+            }
+            AppError::ErrCustomerNotFound => {
+                self.customer_not_found.fetch_add(1, Ordering::Relaxed);
+            }
+            AppError::ErrPoolExhausted => {
+                self.pool_timeouts.fetch_add(1, Ordering::Relaxed);
+            }
+            AppError::ErrDeadlineExceeded => {
+                self.deadlines_exceeded.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn record_validation_error(&self) {
+        self.validation_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_duplicate_transaction(&self) {
+        self.duplicate_transactions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_panic(&self) {
+        self.panics.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_client_disconnect(&self) {
+        self.client_disconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_write_retry(&self, reason: RetryReason) {
+        let counter = match reason {
+            RetryReason::Deadlock => &self.deadlock_retries,
+            RetryReason::SerializationFailure => &self.serialization_retries,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_transaction(&self, customer_id: i32, tx_type: &str) {
+        let mut transactions = self.transactions.lock().unwrap();
+        *transactions
+            .entry((customer_id, tx_type.to_string()))
+            .or_insert(0) += 1;
+    }
+
+    /// Current value of every counter, the source of both the Prometheus
+    /// endpoint and the StatsD exporter.
+    pub fn samples(&self) -> Vec<Sample> {
+        let counter = |name, help, value: &AtomicU64| Sample {
+            name,
+            help,
+            labels: vec![],
+            value: value.load(Ordering::Relaxed),
+        };
+        let mut samples = vec![
+            counter(
+                "limit_exceeded",
+                "Transactions rejected for exceeding the customer limit.",
+                &self.limit_exceeded,
+            ),
+            counter("customer_not_found", "Requests for unknown customers.", &self.customer_not_found),
+            counter(
+                "validation_errors",
+                "Transactions rejected by request validation.",
+                &self.validation_failed,
+            ),
+            counter(
+                "db_deadlock_retries",
+                "Write transactions retried after being picked as deadlock victims.",
+                &self.deadlock_retries,
+            ),
+            counter(
+                "db_serialization_retries",
+                "Write transactions retried after a serialization failure.",
+                &self.serialization_retries,
+            ),
+            counter(
+                "db_pool_timeouts",
+                "Requests rejected after timing out waiting for a pool connection.",
+                &self.pool_timeouts,
+            ),
+            counter(
+                "deadlines_exceeded",
+                "Requests answered with 504 after their deadline passed during database work.",
+                &self.deadlines_exceeded,
+            ),
+            counter(
+                "duplicate_transactions",
+                "Repeated transactions answered with the earlier result instead of being applied.",
+                &self.duplicate_transactions,
+            ),
+            counter("panics", "Requests answered with 500 after their handler panicked.", &self.panics),
+            counter(
+                "client_disconnects",
+                "Requests dropped, with their database work, after their client disconnected.",
+                &self.client_disconnects,
+            ),
+        ];
+
+        for ((customer_id, tx_type), count) in self.transactions.lock().unwrap().iter() {
+            samples.push(Sample {
+                name: "transactions",
+                help: "Successful transactions per customer and type.",
+                labels: vec![("cliente", customer_id.to_string()), ("tipo", tx_type.clone())],
+                value: *count,
+            });
+        }
+
+        samples
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let mut last_name = "";
+        for sample in self.samples() {
+            if sample.name != last_name {
+                let _ = writeln!(out, "# HELP rinha_{}_total {}", sample.name, sample.help);
+                let _ = writeln!(out, "# TYPE rinha_{}_total counter", sample.name);
+                last_name = sample.name;
+            }
+            let labels: Vec<String> = sample
+                .labels
+                .iter()
+                .map(|(label, value)| format!("{}=\"{}\"", label, value))
+                .collect();
+            if labels.is_empty() {
+                let _ = writeln!(out, "rinha_{}_total {}", sample.name, sample.value);
+            } else {
+                let _ = writeln!(out, "rinha_{}_total{{{}}} {}", sample.name, labels.join(","), sample.value);
+            }
+        }
+        out
+    }
+}
+
+/// One counter, or one label combination of a counter.
+pub struct Sample {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub labels: Vec<(&'static str, String)>,
+    pub value: u64,
+}
+
+pub async fn metrics(d: web::Data<MyData>, _: HttpRequest) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(d.metrics.render())
+}
@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::web;
+use rand::Rng;
+use serde_json::Value;
+
+use crate::{buffered, config, errors, server};
+
+/// Fields whose values are expected to differ between two deployments and are
+/// ignored when comparing primary and shadow responses.
+const VOLATILE_FIELDS: [&str; 4] = ["id", "uuid", "data_extrato", "realizada_em"];
+
+pub struct Mirror {
+    client: reqwest::Client,
+    base_url: String,
+    percentage: f64,
+}
+
+/// A request that was already answered by this instance, along with the
+/// response sent to the client, waiting to be replayed against the shadow.
+struct MirroredRequest {
+    method: String,
+    path_and_query: String,
+    content_type: Option<String>,
+    body: web::Bytes,
+    primary_status: StatusCode,
+    primary_body: web::Bytes,
+}
+
+impl Mirror {
+    pub fn from_config(cfg: &config::MirrorConfig) -> Result<Option<Mirror>, errors::CustomError> {
+        let base_url = match &cfg.base_url {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => return Ok(None),
+        };
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(cfg.timeout_ms))
+            .build()
+            .map_err(|err| errors::CustomError::StandardError(Box::new(err)))?;
+
+        Ok(Some(Mirror {
+            client,
+            base_url,
+            percentage: cfg.percentage,
+        }))
+    }
+
+    fn should_mirror(&self) -> bool {
+        self.percentage > 0.0 && rand::thread_rng().gen_range(0.0..100.0) < self.percentage
+    }
+
+    async fn replay(&self, mirrored: MirroredRequest) {
+        let url = format!("{}{}", self.base_url, mirrored.path_and_query);
+        let method = match reqwest::Method::from_bytes(mirrored.method.as_bytes()) {
+            Ok(method) => method,
+            Err(_) => return,
+        };
+
+        let mut shadow_req = self.client.request(method, &url).body(mirrored.body);
+        if let Some(content_type) = mirrored.content_type {
+            shadow_req = shadow_req.header(reqwest::header::CONTENT_TYPE, content_type);
+        }
+
+        let shadow_res = match shadow_req.send().await {
+            Ok(res) => res,
+            Err(err) => {
+                log::warn!("shadow request {} {} failed: {}", mirrored.method, url, err);
+                return;
+            }
+        };
+
+        let shadow_status = shadow_res.status().as_u16();
+        let shadow_body = match shadow_res.bytes().await {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                log::warn!("reading shadow response for {} {} failed: {}", mirrored.method, url, err);
+                return;
+            }
+        };
+
+        if shadow_status != mirrored.primary_status.as_u16()
+            || bodies_diverge(&mirrored.primary_body, &shadow_body)
+        {
+            log::warn!(
+                "shadow divergence on {} {}: primary {} {:?}, shadow {} {:?}",
+                mirrored.method,
+                mirrored.path_and_query,
+                mirrored.primary_status.as_u16(),
+                String::from_utf8_lossy(&mirrored.primary_body),
+                shadow_status,
+                String::from_utf8_lossy(&shadow_body),
+            );
+        }
+    }
+}
+
+/// Copies a sample of requests to the shadow deployment after the primary
+/// response is produced. The shadow call runs on a detached task, so it never
+/// delays or alters what the client receives.
+pub async fn mirror_traffic(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let mirror = req
+        .app_data::<web::Data<server::MyData>>()
+        .and_then(|data| data.mirror.clone());
+
+    let mirror = match mirror {
+        Some(mirror) if mirror.should_mirror() => mirror,
+        _ => return Ok(next.call(req).await?.map_into_boxed_body()),
+    };
+
+    let body = buffered::read_request_body(&mut req).await?;
+
+    let method = req.method().to_string();
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.to_string())
+        .unwrap_or_default();
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let res = next.call(req).await?;
+    let primary_status = res.status();
+    let (res, primary_body) = buffered::read_response_body(res).await?;
+    // Streamed responses can't be compared, so they aren't mirrored.
+    let Some(primary_body) = primary_body else {
+        return Ok(res);
+    };
+
+    let mirrored = MirroredRequest {
+        method,
+        path_and_query,
+        content_type,
+        body,
+        primary_status,
+        primary_body,
+    };
+    tokio::spawn(async move { mirror.replay(mirrored).await });
+
+    Ok(res)
+}
+
+fn bodies_diverge(primary: &[u8], shadow: &[u8]) -> bool {
+    match (
+        serde_json::from_slice::<Value>(primary),
+        serde_json::from_slice::<Value>(shadow),
+    ) {
+        (Ok(mut primary), Ok(mut shadow)) => {
+            strip_volatile_fields(&mut primary);
+            strip_volatile_fields(&mut shadow);
+            primary != shadow
+        }
+        _ => primary != shadow,
+    }
+}
+
+fn strip_volatile_fields(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for field in VOLATILE_FIELDS {
+                map.remove(field);
+            }
+            map.values_mut().for_each(strip_volatile_fields);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_volatile_fields),
+        _ => {}
+    }
+}
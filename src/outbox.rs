@@ -0,0 +1,58 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use sqlx::Connection;
+
+use crate::{db, errors, events, health};
+
+const RELAY_BATCH_SIZE: i64 = 100;
+
+/// Starts the background task that publishes outbox events and marks them as
+/// sent. Delivery is at-least-once: an event is only marked after the broker
+/// accepted it, and a failed publish leaves it (and everything after it) for
+/// the next round.
+pub fn spawn_relay(
+    pool: sqlx::Pool<sqlx::Postgres>,
+    publisher: Arc<dyn events::EventPublisher>,
+    interval: Duration,
+    heartbeats: &health::Heartbeats,
+) {
+    let heartbeat = heartbeats.register("outbox", interval);
+    tokio::spawn(async move {
+        loop {
+            heartbeat.beat();
+            match relay_batch(&pool, publisher.as_ref()).await {
+                // A full batch means there is probably more waiting.
+                Ok(n) if n as i64 == RELAY_BATCH_SIZE => continue,
+                Ok(_) => {}
+                Err(err) => log::error!("outbox relay failed: {}", err),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+async fn relay_batch(
+    pool: &sqlx::Pool<sqlx::Postgres>,
+    publisher: &dyn events::EventPublisher,
+) -> Result<usize, errors::AppError> {
+    let mut conn = pool.acquire().await?;
+    let mut tx = conn.begin().await?;
+
+    let pending = db::lock_unsent_outbox_events_db(&mut tx, RELAY_BATCH_SIZE).await?;
+    let mut sent = Vec::with_capacity(pending.len());
+    for (id, event) in &pending {
+        if let Err(err) = publisher.publish(event).await {
+            log::warn!("publishing outbox event {} failed: {}", id, err);
+            break;
+        }
+        sent.push(*id);
+    }
+
+    if !sent.is_empty() {
+        db::mark_outbox_events_sent_db(&mut tx, &sent).await?;
+    }
+    tx.commit().await?;
+
+    Ok(sent.len())
+}
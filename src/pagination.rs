@@ -0,0 +1,121 @@
+use std::future::{ready, Ready};
+
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpRequest};
+use rinha_servico_rust::schema::Page;
+use serde::Deserialize;
+
+use crate::server::MyData;
+use crate::{config, error_catalog, errors};
+
+#[derive(Debug, Deserialize)]
+struct PaginationQuery {
+    #[serde(alias = "page")]
+    pagina: Option<i64>,
+    #[serde(alias = "limit")]
+    limite: Option<i64>,
+    cursor: Option<String>,
+}
+
+/// Where a page starts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Position {
+    /// 1-based page number.
+    Page(i64),
+    /// Key of the last item of the previous page, as handed out in
+    /// `next_cursor`. Unlike page numbers, cursors don't skip or repeat items
+    /// when the listing changes between requests.
+    After(i64),
+}
+
+/// `?pagina=&limite=` or `?cursor=&limite=`, with `limite` defaulting to and
+/// bounded by the configured values. Listings order their items by a unique
+/// key, which cursors carry.
+#[derive(Debug, Clone)]
+pub struct Pagination {
+    pub limit: i64,
+    pub position: Position,
+    requested: bool,
+}
+
+impl Pagination {
+    fn from_query(query: PaginationQuery, cfg: &config::PaginationConfig) -> Option<Pagination> {
+        let requested = query.pagina.is_some() || query.limite.is_some() || query.cursor.is_some();
+        let limit = query.limite.unwrap_or(cfg.default_limit);
+        if !(1..=cfg.max_limit).contains(&limit) {
+            return None;
+        }
+        let position = match (query.pagina, query.cursor) {
+            (Some(_), Some(_)) => return None,
+            (None, Some(cursor)) => Position::After(cursor.parse().ok()?),
+            (Some(page), None) if page < 1 => return None,
+            (page, None) => Position::Page(page.unwrap_or(1)),
+        };
+        Some(Pagination {
+            limit,
+            position,
+            requested,
+        })
+    }
+
+    /// Whether the request asked for pagination, for listings that are
+    /// returned whole otherwise.
+    pub fn is_requested(&self) -> bool {
+        self.requested
+    }
+
+    /// Rows to fetch: one past the page, telling whether another follows.
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit + 1
+    }
+
+    pub fn offset(&self) -> i64 {
+        match self.position {
+            Position::Page(page) => (page - 1).saturating_mul(self.limit),
+            Position::After(_) => 0,
+        }
+    }
+
+    pub fn after(&self) -> Option<i64> {
+        match self.position {
+            Position::Page(_) => None,
+            Position::After(key) => Some(key),
+        }
+    }
+
+    /// Builds the page out of rows fetched with `fetch_limit`, `key` giving
+    /// the cursor of a row.
+    pub fn page<T>(&self, mut rows: Vec<T>, total: i64, key: impl Fn(&T) -> i64) -> Page<T> {
+        let more = rows.len() as i64 > self.limit;
+        rows.truncate(self.limit as usize);
+        let next_page = match self.position {
+            Position::Page(page) if more => Some(page + 1),
+            _ => None,
+        };
+        let next_cursor = if more {
+            rows.last().map(|row| key(row).to_string())
+        } else {
+            None
+        };
+        Page {
+            items: rows,
+            total,
+            next_page,
+            next_cursor,
+        }
+    }
+}
+
+impl FromRequest for Pagination {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let pagination = req
+            .app_data::<web::Data<MyData>>()
+            .zip(web::Query::<PaginationQuery>::from_query(req.query_string()).ok())
+            .and_then(|(data, query)| Pagination::from_query(query.into_inner(), &data.pagination))
+            .ok_or_else(|| errors::AppError::ErrValidation(&error_catalog::INVALID_PAGINATION).into());
+        ready(pagination)
+    }
+}
@@ -0,0 +1,82 @@
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::HeaderValue;
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::web;
+use futures_util::FutureExt;
+
+use crate::server::MyData;
+use crate::{error_catalog, errors, request_id};
+
+thread_local! {
+    /// Message and backtrace of the last panic inside a request on this
+    /// thread, left by the hook for `catch` to log.
+    static LAST_PANIC: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Keeps panics inside requests from being printed by the default hook;
+/// `catch` logs them along with the request instead. Panics elsewhere still
+/// go to the default hook.
+pub fn install_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if request_id::current().is_some() {
+            let details = format!("{}\n{}", info, Backtrace::force_capture());
+            LAST_PANIC.with(|last| *last.borrow_mut() = Some(details));
+        } else {
+            default_hook(info);
+        }
+    }));
+}
+
+/// Answers a request whose handler panicked with the usual `500` error body
+/// instead of dropping the connection, and logs and counts the panic.
+/// Panics while streaming a body happen after the response started and
+/// aren't caught.
+///
+/// The request is gone once its handler unwinds, and holding on to a clone
+/// of it would break routing, so the response is returned as an error that
+/// carries it. Middlewares further out see an error and skip their work.
+pub async fn catch(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let method = req.method().clone();
+    let path = req.path().to_string();
+    let metrics = req
+        .app_data::<web::Data<MyData>>()
+        .map(|data| data.metrics.clone());
+
+    match AssertUnwindSafe(next.call(req)).catch_unwind().await {
+        Ok(res) => Ok(res?.map_into_boxed_body()),
+        Err(_) => {
+            let details = LAST_PANIC
+                .with(|last| last.borrow_mut().take())
+                .unwrap_or_else(|| "no details".to_string());
+            let request_id = request_id::current();
+            log::error!(
+                "{} {} panicked (request id {}): {}",
+                method,
+                path,
+                request_id.as_ref().map(|id| id.0.as_str()).unwrap_or_default(),
+                details
+            );
+            if let Some(metrics) = metrics {
+                metrics.record_panic();
+            }
+
+            let mut res =
+                errors::error_response(StatusCode::INTERNAL_SERVER_ERROR, &error_catalog::INTERNAL_ERROR);
+            if let Some(value) = request_id.and_then(|id| HeaderValue::from_str(&id.0).ok()) {
+                res.headers_mut().insert(request_id::REQUEST_ID_HEADER, value);
+            }
+            Err(InternalError::from_response("handler panicked", res).into())
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use async_trait::async_trait;
+
+use crate::domain::{CreatedTransaction, NewTransaction, Statement, Transaction};
+use crate::errors;
+
+/// Reads a customer's statement.
+#[async_trait]
+pub trait StatementPort: Send + Sync {
+    async fn statement(&self, customer_id: i64) -> Result<Statement, errors::AppError>;
+
+    /// Forgets what is cached of a customer's statement, or of every
+    /// customer's with `None`, after their rows were changed or removed other
+    /// than through a `TransactionPort`. Failures are logged.
+    async fn invalidate(&self, _customer_id: Option<i64>) {}
+}
+
+/// Stores a transaction and applies `balance_delta` to the customer's balance
+/// atomically, failing with `ErrNegativeTransactionBalance` when the new
+/// balance would cross the customer's limit.
+#[async_trait]
+pub trait TransactionPort: Send + Sync {
+    async fn create(
+        &self,
+        new_tx: NewTransaction,
+        balance_delta: i64,
+    ) -> Result<CreatedTransaction, errors::AppError>;
+}
+
+/// Notified of every transaction once it is stored.
+pub trait EventPort: Send + Sync {
+    fn transaction_created(&self, tx: &Transaction);
+}
@@ -0,0 +1,201 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDateTime};
+use rand::Rng;
+use sqlx::postgres::PgConnectOptions;
+use sqlx::{Connection, PgConnection};
+
+use crate::adapters::read_model::{Change, ReadModel};
+use crate::{db, failover, health, tenant};
+use crate::domain::{Customer, Transaction};
+
+/// Changes fetched from the slot per round trip. The slot only stops at
+/// transaction boundaries, so a batch may be somewhat larger.
+const MAX_CHANGES: i32 = 1000;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Keeps `model` current by consuming a temporary logical replication slot,
+/// decoded with the built-in `test_decoding` plugin. The slot is polled every
+/// `interval` while idle, which bounds the model's staleness. Requires
+/// `wal_level = logical` and a role allowed to create replication slots.
+/// With `failover`, reconnects go to the host in use at the time.
+pub fn spawn(
+    connect_options: PgConnectOptions,
+    failover: Option<Arc<failover::Failover>>,
+    schema: tenant::Schema,
+    model: Arc<ReadModel>,
+    interval: Duration,
+    heartbeats: &health::Heartbeats,
+) {
+    let heartbeat = heartbeats.register("replication", interval);
+    tokio::spawn(async move {
+        loop {
+            let connect_options = failover
+                .as_ref()
+                .map_or_else(|| connect_options.clone(), |failover| failover.current());
+            if let Err(err) = follow(&connect_options, &schema, &model, interval, &heartbeat).await {
+                log::error!("read model replication failed: {}", err);
+            }
+            model.set_ready(false);
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+async fn follow(
+    connect_options: &PgConnectOptions,
+    schema: &tenant::Schema,
+    model: &ReadModel,
+    interval: Duration,
+    heartbeat: &health::Heartbeat,
+) -> Result<(), sqlx::Error> {
+    let mut conn = PgConnection::connect_with(connect_options).await?;
+    tenant::set_schema(&mut conn, schema).await?;
+    // Temporary slots go away with the session, so a crashed instance doesn't
+    // leave one behind retaining WAL.
+    let slot = format!("rinha_read_model_{:08x}", rand::thread_rng().gen::<u32>());
+    sqlx::query("SELECT pg_create_logical_replication_slot($1, 'test_decoding', true)")
+        .bind(&slot)
+        .execute(&mut conn)
+        .await?;
+
+    // Everything committed after the slot was created is replayed on top of
+    // the snapshot; the overlap is deduplicated by the model.
+    model.load(db::get_all_statements_db(&mut conn).await?);
+
+    loop {
+        heartbeat.beat();
+        let rows: Vec<String> = sqlx::query_scalar(
+            "SELECT data FROM pg_logical_slot_get_changes($1, NULL, $2)",
+        )
+        .bind(&slot)
+        .bind(MAX_CHANGES)
+        .fetch_all(&mut conn)
+        .await?;
+        let caught_up = rows.is_empty();
+
+        let mut reload = false;
+        let mut changes = Vec::new();
+        for row in rows {
+            if row.starts_with("COMMIT") {
+                reload |= model.apply(std::mem::take(&mut changes));
+            } else if let Some(change) = decode(&row, schema) {
+                changes.push(change);
+            }
+        }
+
+        if reload {
+            model.set_ready(false);
+            model.load(db::get_all_statements_db(&mut conn).await?);
+        } else if caught_up {
+            model.set_ready(true);
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+/// Decodes a `test_decoding` row such as
+/// `table public.customers: UPDATE: id[integer]:1 limit[integer]:100000 ...`.
+/// Tables outside `schema` and the model are ignored.
+fn decode(row: &str, schema: &tenant::Schema) -> Option<Change> {
+    let rest = row.strip_prefix("table ")?.strip_prefix(&*schema.0)?.strip_prefix('.')?;
+    let (table, rest) = rest.split_once(": ")?;
+    let (action, columns) = rest.split_once(':')?;
+
+    match (table, action) {
+        ("customers" | "transactions", "DELETE" | "TRUNCATE") | ("transactions", "UPDATE") => {
+            Some(Change::Removed)
+        }
+        ("customers", "INSERT" | "UPDATE") => {
+            let columns = parse_columns(columns);
+            Some(Change::UpsertCustomer(Customer {
+                id: int(&columns, "id")?,
+                limit: int(&columns, "limit")?,
+                balance: int(&columns, "balance")?,
+                created_at: timestamp(&columns, "created_at")?,
+            }))
+        }
+        ("transactions", "INSERT") => {
+            let columns = parse_columns(columns);
+            Some(Change::InsertTransaction(Transaction {
+                id: int(&columns, "id"),
+                value: int(&columns, "value"),
+                tx_type: text(&columns, "type"),
+                description: text(&columns, "description"),
+                customer_id: int(&columns, "customer_id"),
+                created_at: timestamp(&columns, "created_at").map(|date| date.and_utc()),
+                uuid: text(&columns, "uuid").and_then(|uuid| uuid.parse().ok()),
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// Splits `name[type]:value` pairs. Quoted values may contain spaces and
+/// double their single quotes; `null` values are left out.
+fn parse_columns(input: &str) -> Vec<(String, String)> {
+    let mut columns = Vec::new();
+    let mut rest = input.trim_start();
+    while let Some(open) = rest.find('[') {
+        // Reserved words such as `limit` come double quoted.
+        let name = rest[..open].trim().trim_matches('"').to_string();
+        let Some(close) = rest[open..].find("]:") else { break };
+        rest = &rest[open + close + 2..];
+
+        let value;
+        if let Some(quoted) = rest.strip_prefix('\'') {
+            let mut out = String::new();
+            let mut chars = quoted.char_indices().peekable();
+            let mut end = quoted.len();
+            while let Some((i, c)) = chars.next() {
+                if c == '\'' {
+                    if chars.peek().map(|&(_, next)| next) == Some('\'') {
+                        chars.next();
+                    } else {
+                        end = i + 1;
+                        break;
+                    }
+                }
+                out.push(c);
+            }
+            value = Some(out);
+            rest = &quoted[end..];
+        } else {
+            let end = rest.find(' ').unwrap_or(rest.len());
+            value = (&rest[..end] != "null").then(|| rest[..end].to_string());
+            rest = &rest[end..];
+        }
+
+        if let Some(value) = value {
+            columns.push((name, value));
+        }
+        rest = rest.trim_start();
+    }
+    columns
+}
+
+fn text(columns: &[(String, String)], name: &str) -> Option<String> {
+    columns
+        .iter()
+        .find(|(column, _)| column == name)
+        .map(|(_, value)| value.clone())
+}
+
+fn int<T: FromStr>(columns: &[(String, String)], name: &str) -> Option<T> {
+    text(columns, name)?.parse().ok()
+}
+
+/// Accepts both `timestamp` and `timestamptz` output, the latter converted
+/// to UTC.
+fn timestamp(columns: &[(String, String)], name: &str) -> Option<NaiveDateTime> {
+    let value = text(columns, name)?;
+    NaiveDateTime::parse_from_str(&value, "%Y-%m-%d %H:%M:%S%.f")
+        .ok()
+        .or_else(|| {
+            DateTime::parse_from_str(&value, "%Y-%m-%d %H:%M:%S%.f%#z")
+                .ok()
+                .map(|date| date.naive_utc())
+        })
+}
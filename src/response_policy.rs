@@ -0,0 +1,33 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderValue, LOCATION};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+
+/// Attached by handlers to the response of a request that created a
+/// resource, with the path it can be fetched from. Handlers always answer
+/// 200 so the default behaviour matches the rinha spec; whether clients see
+/// a proper `201 Created` is decided once, here.
+#[derive(Debug, Clone)]
+pub struct Created(pub String);
+
+/// Turns successful responses marked with `Created` into `201 Created` with
+/// a `Location` header.
+pub async fn apply(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let mut res = next.call(req).await?;
+    if !res.status().is_success() {
+        return Ok(res);
+    }
+
+    let created = res.response_mut().extensions_mut().remove::<Created>();
+    if let Some(Created(location)) = created {
+        if let Ok(value) = HeaderValue::from_str(&location) {
+            *res.response_mut().status_mut() = StatusCode::CREATED;
+            res.headers_mut().insert(LOCATION, value);
+        }
+    }
+    Ok(res)
+}
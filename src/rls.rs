@@ -0,0 +1,60 @@
+use std::future::Future;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use futures_util::Stream;
+use sqlx::PgConnection;
+
+use crate::context;
+
+tokio::task_local! {
+    static CURRENT: i32;
+}
+
+/// Customer the request handled by the current task acts as, when row-level
+/// security is on.
+pub fn current() -> Option<i32> {
+    CURRENT.try_with(|id| *id).ok()
+}
+
+/// Runs `future` on behalf of `customer_id`.
+pub async fn scope<F: Future>(customer_id: i32, future: F) -> F::Output {
+    CURRENT.scope(customer_id, future).await
+}
+
+/// Makes the customer in a `/clientes/{id}` path the request's principal. The
+/// API has no other notion of identity; requests to other routes have none and
+/// the policies let them see every row.
+pub async fn bind_customer(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let customer_id = req
+        .path()
+        .strip_prefix("/clientes/")
+        .and_then(|rest| rest.split('/').next())
+        .and_then(|id| id.parse::<i32>().ok());
+
+    match customer_id {
+        Some(id) => CURRENT.scope(id, next.call(req)).await,
+        None => next.call(req).await,
+    }
+}
+
+/// Sets `app.current_customer_id`, which the row-level security policies
+/// filter on, for the current principal; clears it when there is none.
+/// Installed as a pool hook on checkout.
+pub async fn set_current_customer(conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+    let customer_id = current().map(|id| id.to_string()).unwrap_or_default();
+    sqlx::query("SELECT set_config('app.current_customer_id', $1, false)")
+        .bind(customer_id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Keeps the current principal for a response body stream.
+pub fn scoped<S: Stream>(stream: S) -> impl Stream<Item = S::Item> {
+    context::scoped_stream(&CURRENT, current(), stream)
+}
@@ -0,0 +1,147 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+use crate::domain::{CreatedTransaction, NewTransaction};
+use crate::ports::StatementPort;
+use crate::validation::TransactionValidator;
+use crate::{config, error_catalog, errors, tenant};
+
+const MINUTE: Duration = Duration::from_secs(60);
+const HOUR: Duration = Duration::from_secs(3600);
+
+type CustomerKey = (Option<tenant::Schema>, i32);
+
+/// Velocity limits on debits, registered as the `velocity` validator:
+///
+/// - at most `max_debits_per_minute` debits in the last minute;
+/// - at most `max_debited_per_hour` debited in the last hour;
+/// - no single debit above `max_debit_limit_ratio` times the customer's limit.
+///
+/// Counters are per instance and only count accepted debits, so concurrent
+/// debits may overshoot a limit by the number in flight.
+pub struct Velocity {
+    cfg: config::VelocityConfig,
+    statements: Arc<dyn StatementPort>,
+    started: Instant,
+    debits: Mutex<HashMap<CustomerKey, Debits>>,
+    limits: Mutex<HashMap<CustomerKey, i64>>,
+}
+
+impl Velocity {
+    pub fn new(cfg: config::VelocityConfig, statements: Arc<dyn StatementPort>) -> Velocity {
+        Velocity {
+            cfg,
+            statements,
+            started: Instant::now(),
+            debits: Mutex::new(HashMap::new()),
+            limits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The customer's limit, read once and then kept.
+    async fn limit(&self, key: &CustomerKey) -> Result<i64, errors::AppError> {
+        if let Some(limit) = self.limits.lock().unwrap().get(key) {
+            return Ok(*limit);
+        }
+        let limit = self.statements.statement(key.1 as i64).await?.customer.limit as i64;
+        self.limits.lock().unwrap().insert(key.clone(), limit);
+        Ok(limit)
+    }
+}
+
+#[async_trait]
+impl TransactionValidator for Velocity {
+    async fn validate(&self, tx: &NewTransaction) -> Result<(), errors::AppError> {
+        if tx.tx_type != "d" {
+            return Ok(());
+        }
+        let key = (tenant::current(), tx.customer_id);
+
+        if let Some(ratio) = self.cfg.max_debit_limit_ratio {
+            let limit = self.limit(&key).await?;
+            if tx.value as f64 > limit as f64 * ratio {
+                return Err(errors::AppError::ErrValidation(&error_catalog::DEBIT_TOO_LARGE));
+            }
+        }
+
+        let now = self.started.elapsed();
+        let mut debits = self.debits.lock().unwrap();
+        let Some(debits) = debits.get_mut(&key) else {
+            return Ok(());
+        };
+        debits.prune(now);
+
+        if let Some(max) = self.cfg.max_debits_per_minute {
+            if debits.count_within(now, MINUTE) >= max as usize {
+                return Err(errors::AppError::ErrValidation(&error_catalog::DEBIT_RATE_EXCEEDED));
+            }
+        }
+        if let Some(max) = self.cfg.max_debited_per_hour {
+            if debits.total() + tx.value as i64 > max {
+                return Err(errors::AppError::ErrValidation(&error_catalog::DEBIT_VOLUME_EXCEEDED));
+            }
+        }
+        Ok(())
+    }
+
+    fn accepted(&self, created: &CreatedTransaction) {
+        let tx = &created.transaction;
+        let (Some("d"), Some(customer_id), Some(value)) =
+            (tx.tx_type.as_deref(), tx.customer_id, tx.value)
+        else {
+            return;
+        };
+        let key = (tenant::current(), customer_id);
+        let now = self.started.elapsed();
+
+        self.limits.lock().unwrap().insert(key.clone(), created.limit);
+        let mut debits = self.debits.lock().unwrap();
+        let debits = debits.entry(key).or_default();
+        debits.prune(now);
+        debits.push(now, value as i64);
+    }
+}
+
+/// A customer's debits over the last hour, oldest first, as offsets from the
+/// engine's start.
+#[derive(Default)]
+struct Debits {
+    entries: VecDeque<(Duration, i64)>,
+    total: i64,
+}
+
+impl Debits {
+    fn push(&mut self, at: Duration, value: i64) {
+        self.entries.push_back((at, value));
+        self.total += value;
+    }
+
+    /// Drops debits older than an hour.
+    fn prune(&mut self, now: Duration) {
+        let Some(cutoff) = now.checked_sub(HOUR) else {
+            return;
+        };
+        while let Some(&(at, value)) = self.entries.front() {
+            if at > cutoff {
+                break;
+            }
+            self.entries.pop_front();
+            self.total -= value;
+        }
+    }
+
+    /// Debits in the `window` before `now`.
+    fn count_within(&self, now: Duration, window: Duration) -> usize {
+        match now.checked_sub(window) {
+            Some(since) => self.entries.iter().rev().take_while(|(at, _)| *at > since).count(),
+            None => self.entries.len(),
+        }
+    }
+
+    fn total(&self) -> i64 {
+        self.total
+    }
+}
@@ -0,0 +1,334 @@
+//! Request and response bodies of the public API, with the field names the
+//! rinha spec uses on the wire. Every Portuguese field name has an English
+//! alias, accepted on input and sent instead in the English dialect.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+/// Field names of a body on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Dialect {
+    /// The names of the rinha spec.
+    #[default]
+    Portuguese,
+    /// The English aliases.
+    English,
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "pt" | "pt-br" | "portuguese" => Ok(Dialect::Portuguese),
+            "en" | "english" => Ok(Dialect::English),
+            other => Err(format!("unknown dialect: {}", other)),
+        }
+    }
+}
+
+/// The `rename` and `alias` of every field below.
+const ENGLISH_FIELDS: &[(&str, &str)] = &[
+    ("saldo", "balance"),
+    ("ultimas_transacoes", "last_transactions"),
+    ("transacoes", "transactions"),
+    ("ultimo_id", "last_id"),
+    ("valor", "value"),
+    ("tipo", "type"),
+    ("descricao", "description"),
+    ("realizada_em", "created_at"),
+    ("limite", "limit"),
+    ("data_extrato", "statement_date"),
+    ("codigo", "code"),
+    ("erro", "message"),
+    ("clientes_verificados", "checked_customers"),
+    ("divergencias", "drifts"),
+    ("reparados", "repaired"),
+    ("cliente_id", "customer_id"),
+    ("saldo_calculado", "computed_balance"),
+    ("itens", "items"),
+    ("saldos", "balances"),
+    ("dia", "day"),
+    ("proxima_pagina", "next_page"),
+    ("proximo_cursor", "next_cursor"),
+    ("criado_em", "created_at"),
+    ("acao", "action"),
+    ("detalhes", "details"),
+];
+
+/// Renames the fields of a serialized body, at any depth, to their English
+/// aliases.
+pub fn to_english(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            let old = std::mem::take(fields);
+            for (name, mut field) in old {
+                to_english(&mut field);
+                let name = ENGLISH_FIELDS
+                    .iter()
+                    .find(|(pt, _)| *pt == name)
+                    .map_or(name, |(_, en)| en.to_string());
+                fields.insert(name, field);
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(to_english),
+        _ => {}
+    }
+}
+
+/// `GET /clientes/{id}/extrato`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetCustomerStatementResponse {
+    #[serde(rename = "saldo", alias = "balance")]
+    pub balance: Balance,
+    #[serde(rename = "ultimas_transacoes", alias = "last_transactions")]
+    pub last_transactions: Vec<StatementTransaction>,
+}
+
+/// `GET /clientes/{id}/transacoes?apos_id=`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionsSinceResponse {
+    #[serde(rename = "transacoes", alias = "transactions")]
+    pub transactions: Vec<StatementTransaction>,
+    #[serde(rename = "ultimo_id", alias = "last_id")]
+    pub last_id: i64,
+}
+
+/// Body of `POST /clientes/{id}/transacoes`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateCustomerTransactionRequest {
+    #[serde(rename = "valor", alias = "value")]
+    pub value: i32,
+    #[serde(rename = "tipo", alias = "type")]
+    pub tx_type: String,
+    #[serde(rename = "descricao", alias = "description")]
+    pub description: String,
+}
+
+/// `POST /clientes/{id}/transacoes`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateCustomerTransactionResponse {
+    pub id: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<Uuid>,
+    #[serde(rename = "realizada_em", alias = "created_at")]
+    pub date: Option<DateTime<Utc>>,
+    #[serde(rename = "limite", alias = "limit")]
+    pub limit: i64,
+    #[serde(rename = "saldo", alias = "balance")]
+    pub total: i64,
+}
+
+/// `GET /clientes/{id}/transacoes/{tx_id}`, where `tx_id` is the id or the
+/// uuid.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionResponse {
+    pub id: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<Uuid>,
+    #[serde(flatten)]
+    pub transaction: StatementTransaction,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Balance {
+    pub total: i32,
+    #[serde(rename = "limite", alias = "limit")]
+    pub limit: i32,
+    #[serde(rename = "data_extrato", alias = "statement_date")]
+    pub date: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatementTransaction {
+    #[serde(rename = "valor", alias = "value")]
+    pub value: Option<i32>,
+    #[serde(rename = "tipo", alias = "type")]
+    pub tx_type: Option<String>,
+    #[serde(rename = "descricao", alias = "description")]
+    pub description: Option<String>,
+    #[serde(rename = "realizada_em", alias = "created_at")]
+    pub date: Option<DateTime<Utc>>,
+}
+
+/// `GET /clientes/{id}/saldos-diarios`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DailyBalancesResponse {
+    #[serde(rename = "saldos", alias = "balances")]
+    pub balances: Vec<DailyBalance>,
+}
+
+/// A customer's balance at the end of a UTC day.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DailyBalance {
+    #[serde(rename = "dia", alias = "day")]
+    pub day: NaiveDate,
+    #[serde(rename = "saldo", alias = "balance")]
+    pub balance: i32,
+}
+
+/// One page of a paginated listing. `total` counts every item of the
+/// listing; `next_page` and `next_cursor` are unset on the last page.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Page<T> {
+    #[serde(rename = "itens", alias = "items")]
+    pub items: Vec<T>,
+    pub total: i64,
+    #[serde(rename = "proxima_pagina", alias = "next_page")]
+    pub next_page: Option<i64>,
+    #[serde(rename = "proximo_cursor", alias = "next_cursor")]
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+            next_page: self.next_page,
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+/// `GET /admin/consistency`: every customer's stored balance checked against
+/// the sum of its transactions.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConsistencyReport {
+    #[serde(rename = "clientes_verificados", alias = "checked_customers")]
+    pub checked: usize,
+    #[serde(rename = "divergencias", alias = "drifts")]
+    pub drifts: Vec<BalanceDrift>,
+}
+
+/// `POST /admin/consistency/repair`: the customers whose balance was set to
+/// the one computed from their transactions.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RepairReport {
+    #[serde(rename = "clientes_verificados", alias = "checked_customers")]
+    pub checked: usize,
+    #[serde(rename = "reparados", alias = "repaired")]
+    pub repaired: Vec<BalanceDrift>,
+}
+
+/// A customer whose stored balance doesn't match its transactions.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BalanceDrift {
+    #[serde(rename = "cliente_id", alias = "customer_id")]
+    pub customer_id: i32,
+    #[serde(rename = "saldo", alias = "balance")]
+    pub balance: i64,
+    #[serde(rename = "saldo_calculado", alias = "computed_balance")]
+    pub computed_balance: i64,
+}
+
+/// Body of every error response.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorEnvelope {
+    #[serde(rename = "codigo", alias = "code")]
+    pub code: String,
+    #[serde(rename = "erro", alias = "message")]
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn transaction_request_uses_spec_names() {
+        let request: CreateCustomerTransactionRequest =
+            serde_json::from_value(json!({"valor": 1000, "tipo": "c", "descricao": "descricao"})).unwrap();
+        assert_eq!(request.value, 1000);
+        assert_eq!(request.tx_type, "c");
+        assert_eq!(request.description, "descricao");
+
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({"valor": 1000, "tipo": "c", "descricao": "descricao"})
+        );
+    }
+
+    #[test]
+    fn transaction_request_accepts_english_aliases() {
+        let request: CreateCustomerTransactionRequest =
+            serde_json::from_value(json!({"value": 1, "type": "d", "description": "x"})).unwrap();
+        assert_eq!(request.value, 1);
+        assert_eq!(request.tx_type, "d");
+        assert_eq!(request.description, "x");
+    }
+
+    #[test]
+    fn statement_round_trips() {
+        let statement = GetCustomerStatementResponse {
+            balance: Balance {
+                total: -9098,
+                limit: 100000,
+                date: NaiveDate::from_ymd_opt(2024, 1, 17)
+                    .unwrap()
+                    .and_hms_micro_opt(2, 34, 41, 217753)
+                    .unwrap(),
+            },
+            last_transactions: vec![StatementTransaction {
+                value: Some(10),
+                tx_type: Some("c".to_string()),
+                description: Some("descricao".to_string()),
+                date: Some(Utc.with_ymd_and_hms(2024, 1, 17, 2, 34, 38).unwrap()),
+            }],
+        };
+
+        let value = serde_json::to_value(&statement).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "saldo": {
+                    "total": -9098,
+                    "limite": 100000,
+                    "data_extrato": "2024-01-17T02:34:41.217753"
+                },
+                "ultimas_transacoes": [{
+                    "valor": 10,
+                    "tipo": "c",
+                    "descricao": "descricao",
+                    "realizada_em": "2024-01-17T02:34:38Z"
+                }]
+            })
+        );
+
+        let parsed: GetCustomerStatementResponse = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), value);
+    }
+
+    #[test]
+    fn error_envelope_round_trips() {
+        let error = ErrorEnvelope {
+            code: "customer_not_found".to_string(),
+            message: "cliente não encontrado".to_string(),
+            request_id: None,
+        };
+
+        let value = serde_json::to_value(&error).unwrap();
+        assert_eq!(value, json!({"codigo": "customer_not_found", "erro": "cliente não encontrado"}));
+
+        let parsed: ErrorEnvelope = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.code, error.code);
+        assert_eq!(parsed.message, error.message);
+        assert_eq!(parsed.request_id, None);
+    }
+
+    #[test]
+    fn english_dialect_renames_nested_fields() {
+        let mut value = json!({"saldo": {"limite": 1}, "ultimas_transacoes": [{"tipo": "c"}]});
+        to_english(&mut value);
+        assert_eq!(value, json!({"balance": {"limit": 1}, "last_transactions": [{"type": "c"}]}));
+    }
+}
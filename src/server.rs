@@ -1,13 +1,57 @@
-use actix_web::error::{ErrorInternalServerError, ErrorUnprocessableEntity};
-use actix_web::{middleware, web, App, HttpRequest, HttpResponse, HttpServer};
-use env_logger;
-use serde::{Deserialize, Serialize};
-use sqlx::types::chrono::{Local, NaiveDateTime};
+use actix_web::error::{
+    ErrorInternalServerError, ErrorUnprocessableEntity, InternalError, JsonPayloadError,
+};
+use actix_web::http::header::{self, ContentType};
+use actix_web::http::StatusCode;
+use actix_web::{middleware, mime, web, App, HttpMessage, HttpRequest, HttpResponse, HttpServer};
+use async_stream::try_stream;
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use futures_util::{future, pin_mut, stream, Stream, StreamExt, TryStreamExt};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
 
-use crate::{db, errors};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rinha_servico_rust::json;
+use rinha_servico_rust::schema::{
+    Balance, CreateCustomerTransactionRequest, CreateCustomerTransactionResponse, DailyBalance,
+    DailyBalancesResponse, Dialect, GetCustomerStatementResponse, StatementTransaction,
+    TransactionResponse, TransactionsSinceResponse,
+};
+
+use crate::adapters::read_model::ReadModel;
+use crate::pagination::Pagination;
+use crate::{access_log, admin, body_log, chaos, config, db, deadline, dialect, disconnect, domain, error_catalog, errors, feed, health, i18n, latency, listener, maintenance, methods, metrics, mirror, panics, request_id, response_policy, rls, service, tenant, version, webhooks};
 
 pub struct MyData {
     pub pool: sqlx::Pool<sqlx::Postgres>,
+    pub pools: db::PoolRouter,
+    pub chaos: config::ChaosConfig,
+    pub mirror: Option<Arc<mirror::Mirror>>,
+    pub body_log: config::BodyLogConfig,
+    pub access_log_format: access_log::AccessLogFormat,
+    pub created_responses: bool,
+    pub api_dialect: Dialect,
+    pub feed: Arc<feed::Feed>,
+    pub transactions: service::TransactionService,
+    pub webhooks: config::WebhooksConfig,
+    pub metrics: Arc<metrics::Metrics>,
+    pub latency_enabled: bool,
+    pub latency: latency::LatencyTracker,
+    pub admin_token: Option<config::Secret>,
+    pub db_max_connections: u32,
+    pub warmup_lock: tokio::sync::Mutex<()>,
+    pub maintenance: maintenance::Maintenance,
+    pub tenants: config::TenantsConfig,
+    pub row_level_security: bool,
+    pub cancel_on_disconnect: bool,
+    pub request_timeout: Option<Duration>,
+    pub read_model: Option<Arc<ReadModel>>,
+    pub heartbeats: health::Heartbeats,
+    pub pagination: config::PaginationConfig,
 }
 
 pub async fn statement(
@@ -15,10 +59,15 @@ pub async fn statement(
     d: web::Data<MyData>,
     _: HttpRequest,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let statement_result = db::get_statement_db(d.pool.to_owned(), id.clone()).await?;
+    let read = d
+        .transactions
+        .statement(*id)
+        .await
+        .inspect_err(|err| d.metrics.record_app_error(err))?;
+    let statement_result = read.statement;
 
-    let customer = statement_result.0;
-    let transactions = statement_result.1;
+    let customer = statement_result.customer;
+    let transactions = statement_result.transactions;
 
     let txs = transactions
         .iter()
@@ -29,102 +78,443 @@ pub async fn statement(
         balance: Balance {
             total: customer.balance,
             limit: customer.limit,
-            date: Local::now().naive_utc(),
+            date: statement_result.taken_at,
         },
         last_transactions: txs,
     };
 
-    let res = serde_json::to_string(&statement).map_err(ErrorUnprocessableEntity)?;
-    Ok(HttpResponse::Ok().body(res))
+    let res = dialect::to_string(&statement).map_err(ErrorUnprocessableEntity)?;
+    let mut response = HttpResponse::Ok();
+    if let Some(stale_for) = read.stale_for {
+        // Served from memory while the database is down.
+        response
+            .insert_header(("X-Stale", "true"))
+            .insert_header((header::AGE, stale_for.as_secs()));
+    }
+    Ok(response.body(res))
 }
 
-async fn create_transaction(
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    #[serde(default)]
+    incluir_arquivadas: bool,
+}
+
+/// Full transaction history of a customer, newest first, with archived
+/// transactions when `?incluir_arquivadas=true`. Rows are streamed from the
+/// database straight into a chunked JSON array, so memory usage doesn't grow
+/// with the size of the history. With pagination parameters, answers a
+/// single page instead.
+async fn history(
     id: web::Path<i32>,
-    create_transaction_data: web::Json<CreateCustomerTransactionRequest>,
+    query: web::Query<HistoryQuery>,
+    pagination: Pagination,
     d: web::Data<MyData>,
     _: HttpRequest,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let request = create_transaction_data.into_inner();
+    let pool = d.pools.pool(db::RoutedQuery::History);
+    if !db::customer_exists_db(pool.clone(), *id).await? {
+        return Err(errors::AppError::ErrCustomerNotFound.into());
+    }
 
-    let tx_type = request.tx_type;
+    if pagination.is_requested() {
+        let (txs, total) = db::page_customer_transactions_db(
+            pool,
+            *id,
+            query.incluir_arquivadas,
+            pagination.fetch_limit(),
+            pagination.offset(),
+            pagination.after(),
+        )
+        .await?;
+        let page = pagination
+            .page(txs, total, |tx| tx.id.unwrap_or_default())
+            .map(|tx| StatementTransaction::from(&tx));
+        let res = dialect::to_string(&page).map_err(ErrorInternalServerError)?;
+        return Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res));
+    }
 
-    match tx_type.as_str() {
-        "d" | "c" => {}
-        _ => {
-            return Err(ErrorUnprocessableEntity("tipo de transação invalido"));
-        }
+    let rows = db::stream_customer_transactions_db(pool, *id, false, query.incluir_arquivadas);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .streaming(dialect::scoped(rls::scoped(tenant::scoped(json_array_stream(rows))))))
+}
+
+/// Longest range of days `GET /clientes/{id}/saldos-diarios` answers.
+const MAX_DAILY_BALANCE_DAYS: i64 = 366;
+
+#[derive(Debug, Deserialize)]
+struct DailyBalancesQuery {
+    #[serde(alias = "from")]
+    de: Option<NaiveDate>,
+    #[serde(alias = "until")]
+    ate: Option<NaiveDate>,
+}
+
+/// End-of-day balances of the customer between `de` and `ate`, both inclusive
+/// and defaulting to the 30 days up to yesterday. Days not closed yet, or
+/// before the customer existed, are left out.
+async fn daily_balances(
+    id: web::Path<i32>,
+    query: Result<web::Query<DailyBalancesQuery>, actix_web::Error>,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let invalid_range = || errors::AppError::ErrValidation(&error_catalog::INVALID_DATE_RANGE);
+    let query = query.map_err(|_| invalid_range())?;
+    let until = query
+        .ate
+        .unwrap_or_else(|| Utc::now().date_naive() - Days::new(1));
+    let from = query.de.unwrap_or(until - Days::new(29));
+    let days = (until - from).num_days() + 1;
+    if !(1..=MAX_DAILY_BALANCE_DAYS).contains(&days) {
+        return Err(invalid_range().into());
+    }
+
+    let pool = d.pools.pool(db::RoutedQuery::History);
+    if !db::customer_exists_db(pool.clone(), *id).await? {
+        return Err(errors::AppError::ErrCustomerNotFound.into());
     }
+    let balances = db::get_daily_balances_db(pool, *id, from, until).await?;
 
-    let desc_length = request.description.len();
+    let response = DailyBalancesResponse {
+        balances: balances
+            .into_iter()
+            .map(|(day, balance)| DailyBalance { day, balance })
+            .collect(),
+    };
+    let res = dialect::to_string(&response).map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
+}
+
+fn json_array_stream(
+    rows: impl Stream<Item = Result<domain::Transaction, errors::AppError>>,
+) -> impl Stream<Item = Result<web::Bytes, actix_web::Error>> {
+    let items = rows.enumerate().map(|(i, row)| {
+        let row = row?;
+        let item = dialect::to_string(&StatementTransaction::from(&row))
+            .map_err(ErrorInternalServerError)?;
+        let chunk = if i == 0 { item } else { format!(",{}", item) };
+        Ok(web::Bytes::from(chunk))
+    });
+
+    stream::once(future::ready(Ok(web::Bytes::from_static(b"["))))
+        .chain(items)
+        .chain(stream::once(future::ready(Ok(web::Bytes::from_static(b"]")))))
+}
+
+#[derive(Debug, Deserialize)]
+struct NdjsonQuery {
+    #[serde(default)]
+    seguir: bool,
+}
 
-    if desc_length == 0 || desc_length > 10 {
-        return Err(ErrorUnprocessableEntity("tamanho de descrição inválido"));
+/// Transaction history as newline-delimited JSON, oldest first. With
+/// `?seguir=true` the response stays open and new transactions created on this
+/// instance are appended as they are committed.
+async fn transactions_ndjson(
+    id: web::Path<i32>,
+    query: web::Query<NdjsonQuery>,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let customer_id = *id;
+    let pool = d.pools.pool(db::RoutedQuery::History);
+    if !db::customer_exists_db(pool.clone(), customer_id).await? {
+        return Err(errors::AppError::ErrCustomerNotFound.into());
     }
 
-    let (limit, total) = db::create_customer_transaction_db(
-        d.pool.to_owned(),
-        id.clone(),
-        request.value,
-        tx_type,
-        request.description,
-    )
-    .await?;
+    // Subscribe before reading the history so nothing committed in between is
+    // missed; what the history already had is filtered out below by creation
+    // time, which follows commit order where ids may not.
+    let mut live = query.seguir.then(|| d.feed.subscribe());
+    let rows = db::stream_customer_transactions_db(pool, customer_id, true, false);
 
-    let res = serde_json::to_string(&CreateCustomerTransactionResponse { limit, total })
-        .map_err(ErrorInternalServerError)?;
-    Ok(HttpResponse::Ok().body(res))
+    let lines = try_stream! {
+        let mut last = None;
+        pin_mut!(rows);
+        while let Some(tx) = rows.try_next().await? {
+            last = Some((tx.created_at, tx.id));
+            yield ndjson_line(&tx)?;
+        }
+
+        if let Some(live) = live.as_mut() {
+            loop {
+                match live.recv().await {
+                    Ok(tx) if tx.customer_id == Some(customer_id) && Some((tx.created_at, tx.id)) > last => {
+                        yield ndjson_line(&tx)?;
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        log::warn!("ndjson follower for customer {} skipped {} transactions", customer_id, skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming::<_, actix_web::Error>(dialect::scoped(rls::scoped(tenant::scoped(lines)))))
+}
+
+fn ndjson_line(tx: &domain::Transaction) -> Result<web::Bytes, actix_web::Error> {
+    let mut line = dialect::to_string(&StatementTransaction::from(tx)).map_err(ErrorInternalServerError)?;
+    line.push('\n');
+    Ok(web::Bytes::from(line))
 }
 
+const LONG_POLL_MAX_WAIT: Duration = Duration::from_secs(60);
+const LONG_POLL_MAX_TRANSACTIONS: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+struct TransactionsSinceQuery {
+    #[serde(default)]
+    apos_id: i64,
+    wait: Option<String>,
+}
+
+/// Long-polling read of the transactions created after the one with id
+/// `apos_id`, in the order they were committed. Answers
+/// right away when there are any, otherwise holds the request until this
+/// instance commits one for the customer or `wait` elapses.
+async fn transactions_since(
+    id: web::Path<i32>,
+    query: web::Query<TransactionsSinceQuery>,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let customer_id = *id;
+    let after_id = query.apos_id;
+    let wait = match &query.wait {
+        Some(wait) => parse_wait(wait)
+            .ok_or(errors::AppError::ErrValidation(&error_catalog::INVALID_WAIT))?
+            .min(LONG_POLL_MAX_WAIT),
+        None => Duration::ZERO,
+    };
+
+    let pool = d.pools.pool(db::RoutedQuery::Transactions);
+    if !db::customer_exists_db(pool.clone(), customer_id).await? {
+        return Err(errors::AppError::ErrCustomerNotFound.into());
+    }
+
+    // Subscribe before the first read so a commit racing with it still wakes
+    // us up.
+    let mut live = d.feed.subscribe();
+    let mut txs = match db::get_transactions_after_db(
+        pool,
+        customer_id,
+        after_id,
+        LONG_POLL_MAX_TRANSACTIONS,
+    )
+    .await?
+    {
+        Some(txs) => txs,
+        // A replica may not have replayed the cursor's transaction yet.
+        None => db::get_transactions_after_db(
+            d.pools.primary(),
+            customer_id,
+            after_id,
+            LONG_POLL_MAX_TRANSACTIONS,
+        )
+        .await?
+        .ok_or(errors::AppError::ErrValidation(&error_catalog::UNKNOWN_CURSOR))?,
+    };
+
+    if txs.is_empty() && !wait.is_zero() {
+        // Nothing newer than the cursor was committed before the read, so any
+        // transaction of the customer committed since is.
+        let arrived = tokio::time::timeout(wait, async {
+            loop {
+                match live.recv().await {
+                    Ok(tx) if tx.customer_id == Some(customer_id) => return true,
+                    // What was skipped may be what we're waiting for.
+                    Err(RecvError::Lagged(_)) => return true,
+                    Ok(_) => {}
+                    Err(RecvError::Closed) => return false,
+                }
+            }
+        })
+        .await
+        .unwrap_or(false);
 
-#[derive(Debug, Serialize, Deserialize)]
-struct GetCustomerStatementResponse {
-    #[serde(rename = "saldo")]
-    balance: Balance,
-    #[serde(rename = "ultimas_transacoes")]
-    last_transactions: Vec<StatementTransaction>,
+        // The wake-up comes from a commit on the primary, which a replica
+        // may not have replayed yet.
+        if arrived {
+            txs = db::get_transactions_after_db(
+                d.pools.primary(),
+                customer_id,
+                after_id,
+                LONG_POLL_MAX_TRANSACTIONS,
+            )
+            .await?
+            .unwrap_or_default();
+        }
+    }
+
+    let last_id = txs.last().and_then(|tx| tx.id).unwrap_or(after_id);
+    let res = dialect::to_string(&TransactionsSinceResponse {
+        transactions: txs.iter().map(StatementTransaction::from).collect(),
+        last_id,
+    })
+    .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct CreateCustomerTransactionRequest {
-    #[serde(rename = "valor")]
-    value: i32,
-    #[serde(rename = "tipo")]
-    tx_type: String,
-    #[serde(rename = "descricao")]
-    description: String,
+/// Parses wait durations such as `30s`, `500ms` or a bare number of seconds.
+fn parse_wait(wait: &str) -> Option<Duration> {
+    if let Some(ms) = wait.strip_suffix("ms") {
+        return ms.parse().ok().map(Duration::from_millis);
+    }
+    wait.strip_suffix('s')
+        .unwrap_or(wait)
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Largest transaction body accepted, the limit `web::Json` bodies get.
+const MAX_TRANSACTION_BODY_BYTES: usize = 32 * 1024;
+
+async fn create_transaction(
+    id: web::Path<i32>,
+    body: Result<web::Bytes, actix_web::Error>,
+    d: web::Data<MyData>,
+    req: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let request: CreateCustomerTransactionRequest = parse_body(&req, body)?;
+
+    let new_tx = domain::NewTransaction {
+        customer_id: *id,
+        value: request.value,
+        tx_type: request.tx_type,
+        description: request.description,
+    };
+
+    let created = d
+        .transactions
+        .create(new_tx, &d.metrics)
+        .await
+        .inspect_err(|err| d.metrics.record_app_error(err))?;
+    let (limit, total, created) = (created.limit, created.balance, created.transaction);
+
+    if let Some(tx_type) = &created.tx_type {
+        d.metrics.record_transaction(*id, tx_type);
+    }
+
+    let location = created
+        .id
+        .map(|tx_id| format!("/clientes/{}/transacoes/{}", id, tx_id));
+    let response = CreateCustomerTransactionResponse {
+        id: created.id,
+        uuid: created.uuid,
+        date: created.created_at,
+        limit,
+        total,
+    };
+
+    let res = dialect::to_string(&response).map_err(ErrorInternalServerError)?;
+    let mut res = HttpResponse::Ok().body(res);
+    if let Some(location) = location {
+        res.extensions_mut().insert(response_policy::Created(location));
+    }
+    Ok(res)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct CreateCustomerTransactionResponse {
-    #[serde(rename = "limite")]
-    limit: i64,
-    #[serde(rename = "saldo")]
-    total: i64,
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    #[serde(alias = "type")]
+    tipo: Option<String>,
+    #[serde(alias = "min_value")]
+    valor_min: Option<i32>,
+    #[serde(alias = "max_value")]
+    valor_max: Option<i32>,
+    #[serde(alias = "from")]
+    de: Option<DateTime<Utc>>,
+    #[serde(alias = "until")]
+    ate: Option<DateTime<Utc>>,
+    #[serde(alias = "description")]
+    descricao: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Balance {
-    total: i32,
-    #[serde(rename = "limite")]
-    limit: i32,
-    #[serde(rename = "data_extrato")]
-    date: NaiveDateTime,
+/// A page of the customer's transactions, newest first, filtered by type,
+/// value range (inclusive), date range (from inclusive, until exclusive, as
+/// RFC 3339) and a case-insensitive substring of the description.
+async fn search_transactions(
+    id: web::Path<i32>,
+    query: Result<web::Query<SearchQuery>, actix_web::Error>,
+    pagination: Pagination,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let invalid_search = || errors::AppError::ErrValidation(&error_catalog::INVALID_SEARCH);
+    let query = query.map_err(|_| invalid_search())?.into_inner();
+    let invalid = query.tipo.as_deref().is_some_and(|tx_type| tx_type != "c" && tx_type != "d")
+        || matches!((query.valor_min, query.valor_max), (Some(min), Some(max)) if min > max)
+        || matches!((query.de, query.ate), (Some(from), Some(until)) if from > until);
+    if invalid {
+        return Err(invalid_search().into());
+    }
+    let filter = db::TransactionFilter {
+        tx_type: query.tipo,
+        min_value: query.valor_min,
+        max_value: query.valor_max,
+        from: query.de,
+        until: query.ate,
+        description: query.descricao.filter(|description| !description.is_empty()),
+    };
+
+    let pool = d.pools.pool(db::RoutedQuery::Search);
+    if !db::customer_exists_db(pool.clone(), *id).await? {
+        return Err(errors::AppError::ErrCustomerNotFound.into());
+    }
+    let (txs, total) = db::search_customer_transactions_db(
+        pool,
+        *id,
+        &filter,
+        pagination.fetch_limit(),
+        pagination.offset(),
+        pagination.after(),
+    )
+    .await?
+    .ok_or(errors::AppError::ErrValidation(&error_catalog::UNKNOWN_CURSOR))?;
+
+    let page = pagination
+        .page(txs, total, |tx| tx.id.unwrap_or_default())
+        .map(|tx| StatementTransaction::from(&tx));
+    let res = dialect::to_string(&page).map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct StatementTransaction {
-    #[serde(rename = "valor")]
-    value: Option<i32>,
-    #[serde(rename = "tipo")]
-    tx_type: Option<String>,
-    #[serde(rename = "descricao")]
-    description: Option<String>,
-    #[serde(rename = "realizada_em")]
-    date: Option<NaiveDateTime>,
+async fn transaction(
+    path: web::Path<(i32, String)>,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (customer_id, transaction_id) = path.into_inner();
+    let transaction = if let Ok(id) = transaction_id.parse::<i64>() {
+        db::TransactionRef::Id(id)
+    } else if let Ok(uuid) = transaction_id.parse::<Uuid>() {
+        db::TransactionRef::Uuid(uuid)
+    } else {
+        return Err(errors::AppError::ErrTransactionNotFound.into());
+    };
+
+    let tx = db::get_transaction_db(d.pools.pool(db::RoutedQuery::Transactions), customer_id, transaction).await?;
+
+    let res = dialect::to_string(&TransactionResponse {
+        id: tx.id,
+        uuid: tx.uuid,
+        transaction: StatementTransaction::from(&tx),
+    })
+    .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().body(res))
 }
 
-impl From<&db::Transaction> for StatementTransaction {
-    fn from(db_tx: &db::Transaction) -> Self {
+
+impl From<&domain::Transaction> for StatementTransaction {
+    fn from(db_tx: &domain::Transaction) -> Self {
         StatementTransaction {
             value: db_tx.value,
             tx_type: db_tx.tx_type.clone(),
@@ -134,23 +524,165 @@ impl From<&db::Transaction> for StatementTransaction {
     }
 }
 
-pub async fn run_server(data: web::Data<MyData>, port: u16) -> Result<(), errors::CustomError> {
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("debug"));
+/// Bodies that are valid JSON but don't fit the request type (a fractional
+/// `valor`, a null `descricao`, a missing field) are validation failures and
+/// get 422 like the handler-level checks, as the rinha spec expects. Anything
+/// that isn't JSON at all keeps actix's status.
+fn json_error(err: JsonPayloadError, req: &HttpRequest) -> actix_web::Error {
+    let data_error = matches!(&err, JsonPayloadError::Deserialize(err) if err.is_data());
+    if !data_error {
+        let status = actix_web::ResponseError::status_code(&err);
+        let res = errors::error_response(status, &error_catalog::INVALID_BODY);
+        return InternalError::from_response(err, res).into();
+    }
+
+    if let Some(d) = req.app_data::<web::Data<MyData>>() {
+        d.metrics.record_validation_error();
+    }
+    errors::AppError::ErrValidation(&error_catalog::INVALID_BODY).into()
+}
+
+/// Decodes a JSON body with the serializer the build selected, answering
+/// errors the way `json_error` does for `web::Json` extractors.
+fn parse_body<T: DeserializeOwned>(
+    req: &HttpRequest,
+    body: Result<web::Bytes, actix_web::Error>,
+) -> Result<T, actix_web::Error> {
+    // Bodies over the resource's `PayloadConfig` limit end up here.
+    let body = body.map_err(|err| {
+        let status = err.as_response_error().status_code();
+        let res = errors::error_response(status, &error_catalog::INVALID_BODY);
+        actix_web::Error::from(InternalError::from_response(err, res))
+    })?;
+
+    let is_json = req.mime_type().ok().flatten().is_some_and(|mime| {
+        mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON)
+    });
+    if !is_json {
+        return Err(json_error(JsonPayloadError::ContentType, req));
+    }
+
+    let mut body = body.to_vec();
+    json::from_slice(&mut body).map_err(|err| {
+        if !err.is_data() {
+            let res = errors::error_response(StatusCode::BAD_REQUEST, &error_catalog::INVALID_BODY);
+            return InternalError::from_response(err, res).into();
+        }
+        if let Some(d) = req.app_data::<web::Data<MyData>>() {
+            d.metrics.record_validation_error();
+        }
+        errors::AppError::ErrValidation(&error_catalog::INVALID_BODY).into()
+    })
+}
+
+async fn not_found() -> HttpResponse {
+    errors::error_response(StatusCode::NOT_FOUND, &error_catalog::ROUTE_NOT_FOUND)
+}
+
+/// Every route the service serves, registered by `run_server` under its
+/// middleware.
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg
+        .service(web::resource("/clientes/{id}/extrato").route(web::get().to(statement)))
+        .configure(admin::configure)
+        .service(web::resource("/metrics").route(web::get().to(metrics::metrics)))
+        .service(web::resource("/health").route(web::get().to(health::health)))
+        .service(web::resource("/version").route(web::get().to(version::version)))
+        .configure(webhooks::configure)
+        .service(web::resource("/clientes/{id}/historico").route(web::get().to(history)))
+        .service(
+            web::resource("/clientes/{id}/saldos-diarios")
+                .route(web::get().to(daily_balances)),
+        )
+        .service(
+            web::resource("/clientes/{id}/transacoes.ndjson")
+                .route(web::get().to(transactions_ndjson)),
+        )
+        .service(
+            web::resource("/clientes/{id}/transacoes")
+                .app_data(web::PayloadConfig::new(MAX_TRANSACTION_BODY_BYTES))
+                .route(web::get().to(transactions_since))
+                .route(web::post().to(create_transaction)),
+        )
+        .service(
+            web::resource("/clientes/{id}/transacoes/busca")
+                .route(web::get().to(search_transactions)),
+        )
+        .service(
+            web::resource("/clientes/{id}/transacoes/{tx_id}")
+                .route(web::get().to(transaction)),
+        )
+        .default_service(web::to(not_found));
+}
 
-    let _ = HttpServer::new(
+pub async fn run_server(
+    data: web::Data<MyData>,
+    port: u16,
+    listen: &config::ListenConfig,
+) -> Result<(), errors::CustomError> {
+    panics::install_hook();
+
+    let chaos_enabled = data.chaos.enabled;
+    let mirror_enabled = data.mirror.is_some();
+    let body_log_enabled = data.body_log.enabled;
+    let created_responses = data.created_responses;
+    let latency_enabled = data.latency_enabled;
+    let row_level_security = data.row_level_security;
+    let cancel_on_disconnect = data.cancel_on_disconnect;
+
+    HttpServer::new(
         move || {
             App::new()
-                .service(web::resource("/clientes/{id}/extrato").route(web::get().to(statement)))
-                .service(
-                    web::resource("/clientes/{id}/transacoes")
-                        .route(web::post().to(create_transaction)),
-                )
-                // enable logger
-                .wrap(middleware::Logger::default())
+                .configure(routes)
+                // Registered here so HEAD and OPTIONS work on every route.
+                .wrap(middleware::from_fn(methods::handle))
+                .wrap(middleware::Condition::new(
+                    created_responses,
+                    middleware::from_fn(response_policy::apply),
+                ))
+                .wrap(middleware::Condition::new(
+                    chaos_enabled,
+                    middleware::from_fn(chaos::inject_faults),
+                ))
+                .wrap(middleware::Condition::new(
+                    mirror_enabled,
+                    middleware::from_fn(mirror::mirror_traffic),
+                ))
+                .wrap(middleware::Condition::new(
+                    body_log_enabled,
+                    middleware::from_fn(body_log::log_bodies),
+                ))
+                .wrap(middleware::Condition::new(
+                    latency_enabled,
+                    middleware::from_fn(latency::track),
+                ))
+                .wrap(middleware::Condition::new(
+                    row_level_security,
+                    middleware::from_fn(rls::bind_customer),
+                ))
+                .wrap(middleware::from_fn(tenant::resolve))
+                .wrap(middleware::from_fn(deadline::assign))
+                .wrap(middleware::from_fn(maintenance::guard))
+                .wrap(middleware::Condition::new(
+                    cancel_on_disconnect,
+                    middleware::from_fn(disconnect::cancel),
+                ))
+                .wrap(middleware::from_fn(panics::catch))
+                .wrap(middleware::from_fn(dialect::negotiate))
+                .wrap(middleware::from_fn(access_log::log_access))
+                .wrap(middleware::from_fn(i18n::negotiate))
+                .wrap(middleware::from_fn(request_id::assign))
                 .app_data(data.clone())
+                .app_data(web::JsonConfig::default().error_handler(json_error))
         }, // add shared state
     )
-    .bind(("0.0.0.0", port))?
+    .on_connect(move |conn, data| {
+        if cancel_on_disconnect {
+            disconnect::on_connect(conn, data);
+        }
+    })
+    .tcp_nodelay(listen.tcp_nodelay)
+    .listen(listener::bind(port, listen)?)?
     .run()
     .await?;
     Ok(())
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::domain::{self, CreatedTransaction, NewTransaction, Statement};
+use crate::ports::{EventPort, StatementPort, TransactionPort};
+use crate::{dedup, degraded, error_catalog, errors, metrics, validation};
+
+/// Business rules of the API. Handlers in server.rs deal with HTTP and the
+/// adapters behind the ports deal with storage; everything in between lives
+/// here.
+pub struct TransactionService {
+    statements: Arc<dyn StatementPort>,
+    transactions: Arc<dyn TransactionPort>,
+    events: Arc<dyn EventPort>,
+    validators: validation::Pipeline,
+    degraded: Option<degraded::DegradedMode>,
+    dedup: Option<dedup::Dedup>,
+}
+
+/// A statement and, when it was served from memory because the database is
+/// down, how long ago it was read.
+pub struct StatementRead {
+    pub statement: Statement,
+    pub stale_for: Option<Duration>,
+}
+
+impl TransactionService {
+    pub fn new(
+        statements: Arc<dyn StatementPort>,
+        transactions: Arc<dyn TransactionPort>,
+        events: Arc<dyn EventPort>,
+        validators: validation::Pipeline,
+        degraded: Option<degraded::DegradedMode>,
+        dedup: Option<dedup::Dedup>,
+    ) -> TransactionService {
+        TransactionService {
+            statements,
+            transactions,
+            events,
+            validators,
+            degraded,
+            dedup,
+        }
+    }
+
+    /// Forgets what is cached of a customer's statement and transactions, or
+    /// of every customer's with `None`, after their rows changed outside
+    /// `create`: in the statement port, the degraded mode's statements and
+    /// the results kept for repeated requests.
+    pub async fn invalidate(&self, customer_id: Option<i64>) {
+        self.statements.invalidate(customer_id).await;
+        if let Some(degraded) = &self.degraded {
+            degraded.forget(customer_id);
+        }
+        if let Some(dedup) = &self.dedup {
+            dedup.forget(customer_id);
+        }
+    }
+
+    /// Whether degraded mode is serving from memory right now, or `None` when
+    /// it is disabled.
+    pub fn degraded(&self) -> Option<bool> {
+        self.degraded.as_ref().map(|degraded| !degraded.allows_requests())
+    }
+
+    /// Validates and applies a transaction. Transactions that would take the
+    /// balance past the customer's limit are rejected without touching it,
+    /// and repeats of a transaction just applied get its result back.
+    pub async fn create(
+        &self,
+        new_tx: NewTransaction,
+        metrics: &metrics::Metrics,
+    ) -> Result<CreatedTransaction, errors::AppError> {
+        let Some(delta) = domain::balance_delta(&new_tx) else {
+            metrics.record_validation_error();
+            return Err(errors::AppError::ErrValidation(&error_catalog::INVALID_TRANSACTION_TYPE));
+        };
+        self.validators
+            .validate(&new_tx)
+            .await
+            .inspect_err(|_| metrics.record_validation_error())?;
+
+        if let Some(created) = self.dedup.as_ref().and_then(|dedup| dedup.recent(&new_tx)) {
+            metrics.record_duplicate_transaction();
+            return Ok(created);
+        }
+        let dedup_key = self.dedup.as_ref().map(|_| new_tx.clone());
+
+        let created = match &self.degraded {
+            Some(degraded) if !degraded.allows_requests() => {
+                return Err(errors::AppError::ErrDatabaseUnavailable)
+            }
+            Some(degraded) => {
+                let result = self.transactions.create(new_tx, delta).await;
+                degraded.record(&result);
+                result?
+            }
+            None => self.transactions.create(new_tx, delta).await?,
+        };
+        self.validators.accepted(&created);
+        self.events.transaction_created(&created.transaction);
+        if let (Some(dedup), Some(key)) = (&self.dedup, dedup_key) {
+            dedup.remember(key, &created);
+        }
+
+        Ok(created)
+    }
+
+    /// The customer's balance and ten latest transactions, all from a single
+    /// snapshot. In degraded mode, when the database is down, the last
+    /// statement read for the customer is returned instead.
+    pub async fn statement(&self, customer_id: i64) -> Result<StatementRead, errors::AppError> {
+        let Some(degraded) = &self.degraded else {
+            let statement = self.statements.statement(customer_id).await?;
+            return Ok(StatementRead {
+                statement,
+                stale_for: None,
+            });
+        };
+
+        let result = if degraded.allows_requests() {
+            let result = self.statements.statement(customer_id).await;
+            degraded.record(&result);
+            result
+        } else {
+            Err(errors::AppError::ErrDatabaseUnavailable)
+        };
+
+        match result {
+            Ok(statement) => {
+                degraded.remember(customer_id, &statement);
+                Ok(StatementRead {
+                    statement,
+                    stale_for: None,
+                })
+            }
+            Err(err) if !degraded.allows_requests() => match degraded.last_known(customer_id) {
+                Some((statement, age)) => Ok(StatementRead {
+                    statement,
+                    stale_for: Some(age),
+                }),
+                None => Err(err),
+            },
+            Err(err) => Err(err),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use rinha_servico_rust::client::{ClientError, RinhaClient};
+use rinha_servico_rust::schema::CreateCustomerTransactionRequest;
+
+use crate::errors;
+
+/// Customer id no deployment is expected to have.
+const UNKNOWN_CUSTOMER_ID: i32 = 999_999_999;
+
+/// Runs the smoke sequence against the customer `id` of a deployment. The
+/// customer's balance is the same at the end; two transactions are added.
+pub async fn run(client: &RinhaClient, id: i32) -> Result<(), errors::CustomError> {
+    let mut smoke = Smoke { failures: 0 };
+
+    let before = client.extrato(id).await.map_err(fatal)?;
+    let balance = before.balance.total as i64;
+    let limit = before.balance.limit as i64;
+    smoke.pass(&format!("extrato inicial: saldo {}, limite {}", balance, limit));
+
+    match client.criar_transacao(id, &request(1, "d", "smoke-d")).await {
+        Ok(res) => smoke.check("debito", res.total == balance - 1 && res.limit == limit),
+        Err(err) => smoke.fail("debito", &err),
+    }
+
+    match client.criar_transacao(id, &request(1, "c", "smoke-c")).await {
+        Ok(res) => smoke.check("credito", res.total == balance && res.limit == limit),
+        Err(err) => smoke.fail("credito", &err),
+    }
+
+    let over_limit = (balance + limit + 1).clamp(1, i32::MAX as i64) as i32;
+    let res = client.criar_transacao(id, &request(over_limit, "d", "smoke-x")).await;
+    smoke.expect_status("debito acima do limite", res, 422);
+
+    let res = client
+        .criar_transacao(UNKNOWN_CUSTOMER_ID, &request(1, "c", "smoke-c"))
+        .await;
+    smoke.expect_status("transacao de cliente inexistente", res, 404);
+
+    let res = client.extrato(UNKNOWN_CUSTOMER_ID).await;
+    smoke.expect_status("extrato de cliente inexistente", res, 404);
+
+    match client.extrato(id).await {
+        Ok(after) => {
+            let latest: Vec<_> = after
+                .last_transactions
+                .iter()
+                .take(2)
+                .map(|tx| (tx.tx_type.as_deref(), tx.description.as_deref()))
+                .collect();
+            let ordered = latest == [(Some("c"), Some("smoke-c")), (Some("d"), Some("smoke-d"))];
+            smoke.check("extrato final: saldo", after.balance.total as i64 == balance);
+            smoke.check("extrato final: ordem das transacoes", ordered);
+        }
+        Err(err) => smoke.fail("extrato final", &err),
+    }
+
+    if smoke.failures > 0 {
+        return Err(errors::CustomError::StringError(format!(
+            "smoke test failed: {} check(s)",
+            smoke.failures
+        )));
+    }
+    println!("smoke test passed");
+    Ok(())
+}
+
+struct Smoke {
+    failures: usize,
+}
+
+impl Smoke {
+    fn pass(&self, step: &str) {
+        println!("ok    {}", step);
+    }
+
+    fn check(&mut self, step: &str, passed: bool) {
+        if passed {
+            self.pass(step);
+        } else {
+            self.failures += 1;
+            println!("FAIL  {}", step);
+        }
+    }
+
+    fn fail(&mut self, step: &str, err: &ClientError) {
+        self.failures += 1;
+        println!("FAIL  {}: {}", step, err);
+    }
+
+    fn expect_status<T>(&mut self, step: &str, res: Result<T, ClientError>, status: u16) {
+        match res {
+            Err(err) if err.status() == Some(status) => self.pass(step),
+            Err(err) => self.fail(step, &err),
+            Ok(_) => {
+                self.failures += 1;
+                println!("FAIL  {}: expected status {}, got success", step, status);
+            }
+        }
+    }
+}
+
+fn request(value: i32, tx_type: &str, description: &str) -> CreateCustomerTransactionRequest {
+    CreateCustomerTransactionRequest {
+        value,
+        tx_type: tx_type.to_string(),
+        description: description.to_string(),
+    }
+}
+
+fn fatal(err: ClientError) -> errors::CustomError {
+    errors::CustomError::StandardError(Box::new(err))
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+
+use crate::config;
+use crate::metrics::{Metrics, Sample};
+
+/// Keeps datagrams under the usual MTU, so they aren't fragmented.
+const MAX_PACKET_SIZE: usize = 1432;
+
+/// Starts the background task pushing the counters of `metrics` to a StatsD
+/// agent over UDP, as increments since the previous push. Labels become
+/// DogStatsD tags when `dogstatsd` is set and name segments otherwise.
+pub fn spawn(metrics: Arc<Metrics>, cfg: config::StatsdConfig) {
+    let Some(host) = cfg.host.clone() else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        let socket = match connect(&host, cfg.port).await {
+            Ok(socket) => socket,
+            Err(err) => {
+                log::error!("statsd exporter couldn't reach {}:{}: {}", host, cfg.port, err);
+                return;
+            }
+        };
+
+        let mut sent: HashMap<String, u64> = HashMap::new();
+        let interval = Duration::from_millis(cfg.flush_interval_ms.max(1));
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let mut lines = Vec::new();
+            for sample in metrics.samples() {
+                let (name, tags) = name_and_tags(&cfg, &sample);
+                let previous = sent.insert(format!("{}|{}", name, tags), sample.value).unwrap_or(0);
+                let delta = sample.value.saturating_sub(previous);
+                if delta == 0 {
+                    continue;
+                }
+                lines.push(if tags.is_empty() {
+                    format!("{}:{}|c", name, delta)
+                } else {
+                    format!("{}:{}|c|{}", name, delta, tags)
+                });
+            }
+
+            for packet in packets(&lines) {
+                if let Err(err) = socket.send(packet.as_bytes()).await {
+                    log::warn!("sending statsd metrics failed: {}", err);
+                }
+            }
+        }
+    });
+}
+
+async fn connect(host: &str, port: u16) -> std::io::Result<UdpSocket> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect((host, port)).await?;
+    Ok(socket)
+}
+
+/// The metric name of a sample and its DogStatsD tags, if any.
+fn name_and_tags(cfg: &config::StatsdConfig, sample: &Sample) -> (String, String) {
+    let mut name = format!("{}.{}", cfg.prefix, sample.name);
+    let mut tags = String::new();
+    for (label, value) in &sample.labels {
+        if cfg.dogstatsd {
+            tags.push(if tags.is_empty() { '#' } else { ',' });
+            tags.push_str(&format!("{}:{}", label, value));
+        } else {
+            name.push_str(&format!(".{}_{}", label, value));
+        }
+    }
+    (name, tags)
+}
+
+/// Joins lines into newline-separated packets of at most `MAX_PACKET_SIZE`
+/// bytes.
+fn packets(lines: &[String]) -> Vec<String> {
+    let mut packets = Vec::new();
+    let mut packet = String::new();
+    for line in lines {
+        if !packet.is_empty() && packet.len() + 1 + line.len() > MAX_PACKET_SIZE {
+            packets.push(std::mem::take(&mut packet));
+        }
+        if !packet.is_empty() {
+            packet.push('\n');
+        }
+        packet.push_str(line);
+    }
+    if !packet.is_empty() {
+        packets.push(packet);
+    }
+    packets
+}
@@ -0,0 +1,121 @@
+use std::future::Future;
+use std::sync::Arc;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HOST};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::web;
+use futures_util::Stream;
+use sqlx::PgConnection;
+
+use crate::{config, context, error_catalog, errors, server};
+
+pub const TENANT_HEADER: HeaderName = HeaderName::from_static("x-tenant");
+
+/// Schema requests without a tenant, and background work, run against unless
+/// `DB_SCHEMA` names another.
+pub const DEFAULT_SCHEMA: &str = "public";
+
+tokio::task_local! {
+    static CURRENT: Schema;
+}
+
+/// Postgres schema holding a tenant's dataset.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Schema(pub Arc<str>);
+
+/// Schema of the tenant whose request is handled by the current task; `None`
+/// outside a request or for requests without a tenant.
+pub fn current() -> Option<Schema> {
+    CURRENT.try_with(Schema::clone).ok()
+}
+
+/// Runs `future` on behalf of the tenant stored in `schema`.
+pub async fn scope<F: Future>(schema: Schema, future: F) -> F::Output {
+    CURRENT.scope(schema, future).await
+}
+
+/// Maps the `X-Tenant` header, or the first label of the host name when
+/// `TENANT_FROM_SUBDOMAIN` is set, to the tenant's schema for the rest of the
+/// request. Requests naming an unknown tenant get a `404`; requests naming
+/// none use the default schema.
+pub async fn resolve(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let tenants = match req.app_data::<web::Data<server::MyData>>() {
+        Some(data) if !data.tenants.registry.is_empty() => data.tenants.clone(),
+        _ => return Ok(next.call(req).await?.map_into_boxed_body()),
+    };
+
+    let name = req
+        .headers()
+        .get(&TENANT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| {
+            tenants
+                .from_subdomain
+                .then(|| subdomain(&req))
+                .flatten()
+                .filter(|label| tenants.registry.contains_key(label))
+        });
+
+    let Some(name) = name else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    match tenants.registry.get(&name) {
+        Some(schema) => {
+            let schema = Schema(Arc::from(schema.as_str()));
+            Ok(CURRENT.scope(schema, next.call(req)).await?.map_into_boxed_body())
+        }
+        None => {
+            let res = errors::error_response(StatusCode::NOT_FOUND, &error_catalog::UNKNOWN_TENANT);
+            let (req, _) = req.into_parts();
+            Ok(ServiceResponse::new(req, res))
+        }
+    }
+}
+
+fn subdomain(req: &ServiceRequest) -> Option<String> {
+    let host = req.headers().get(HOST)?.to_str().ok()?;
+    let host = host.split(':').next()?;
+    let (label, rest) = host.split_once('.')?;
+    (!rest.is_empty()).then(|| label.to_lowercase())
+}
+
+/// Points a pooled connection at the current tenant's schema, or at `default`
+/// outside any tenant. Installed as a pool hook on checkout, so every query
+/// made on behalf of a request sees only that tenant's tables.
+pub async fn set_search_path(conn: &mut PgConnection, default: &Schema) -> Result<(), sqlx::Error> {
+    let schema = current();
+    set_schema(conn, schema.as_ref().unwrap_or(default)).await
+}
+
+/// Points `conn` at `schema` for the rest of its session.
+pub async fn set_schema(conn: &mut PgConnection, schema: &Schema) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT set_config('search_path', $1, false)")
+        .bind(&*schema.0)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Keeps the current tenant for a response body stream, which is polled after
+/// the handler returns and outside the request's task-local scope.
+pub fn scoped<S: Stream>(stream: S) -> impl Stream<Item = S::Item> {
+    context::scoped_stream(&CURRENT, current(), stream)
+}
+
+/// Schemas of every registered tenant, for setting them up at startup.
+pub fn schemas(cfg: &config::TenantsConfig) -> Vec<Schema> {
+    let mut schemas: Vec<&String> = cfg.registry.values().collect();
+    schemas.sort();
+    schemas.dedup();
+    schemas
+        .into_iter()
+        .map(|schema| Schema(Arc::from(schema.as_str())))
+        .collect()
+}
@@ -0,0 +1,123 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::ports::StatementPort;
+use crate::{config, domain, error_catalog, errors, rules};
+
+/// A business rule a new transaction must satisfy before it is written.
+/// Validators run in the order configured in `VALIDATION_CHAIN` and the first
+/// failure rejects the transaction.
+#[async_trait]
+pub trait TransactionValidator: Send + Sync {
+    async fn validate(&self, tx: &domain::NewTransaction) -> Result<(), errors::AppError>;
+
+    /// Called once a transaction that passed validation is stored, for
+    /// validators that keep track of what was accepted.
+    fn accepted(&self, _created: &domain::CreatedTransaction) {}
+}
+
+pub struct Pipeline {
+    validators: Vec<Box<dyn TransactionValidator>>,
+}
+
+impl Pipeline {
+    pub fn from_config(
+        cfg: &config::ValidationConfig,
+        statements: Arc<dyn StatementPort>,
+    ) -> Result<Pipeline, errors::CustomError> {
+        let validators = cfg
+            .chain
+            .iter()
+            .map(|name| build(name, cfg, &statements))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Pipeline { validators })
+    }
+
+    pub async fn validate(&self, tx: &domain::NewTransaction) -> Result<(), errors::AppError> {
+        for validator in &self.validators {
+            validator.validate(tx).await?;
+        }
+        Ok(())
+    }
+
+    pub fn accepted(&self, created: &domain::CreatedTransaction) {
+        for validator in &self.validators {
+            validator.accepted(created);
+        }
+    }
+}
+
+fn build(
+    name: &str,
+    cfg: &config::ValidationConfig,
+    statements: &Arc<dyn StatementPort>,
+) -> Result<Box<dyn TransactionValidator>, errors::CustomError> {
+    let validator: Box<dyn TransactionValidator> = match name {
+        "value" => Box::new(ValueRange {
+            min: cfg.min_value,
+            max: cfg.max_value,
+        }),
+        "type" => Box::new(AllowedTypes {
+            types: cfg.tx_types.clone(),
+        }),
+        "description" => Box::new(DescriptionLength {
+            max: cfg.description_max_len,
+        }),
+        "velocity" => Box::new(rules::Velocity::new(cfg.velocity.clone(), statements.clone())),
+        other => {
+            return Err(errors::CustomError::StringError(format!(
+                "unknown validator in VALIDATION_CHAIN: {}",
+                other
+            )))
+        }
+    };
+    Ok(validator)
+}
+
+struct ValueRange {
+    min: i32,
+    max: i32,
+}
+
+#[async_trait]
+impl TransactionValidator for ValueRange {
+    async fn validate(&self, tx: &domain::NewTransaction) -> Result<(), errors::AppError> {
+        if tx.value < self.min || tx.value > self.max {
+            return Err(errors::AppError::ErrValidation(&error_catalog::INVALID_AMOUNT));
+        }
+        Ok(())
+    }
+}
+
+struct AllowedTypes {
+    types: Vec<String>,
+}
+
+#[async_trait]
+impl TransactionValidator for AllowedTypes {
+    async fn validate(&self, tx: &domain::NewTransaction) -> Result<(), errors::AppError> {
+        if !self.types.contains(&tx.tx_type) {
+            return Err(errors::AppError::ErrValidation(
+                &error_catalog::INVALID_TRANSACTION_TYPE,
+            ));
+        }
+        Ok(())
+    }
+}
+
+struct DescriptionLength {
+    max: usize,
+}
+
+#[async_trait]
+impl TransactionValidator for DescriptionLength {
+    async fn validate(&self, tx: &domain::NewTransaction) -> Result<(), errors::AppError> {
+        let len = tx.description.chars().count();
+        if len == 0 || len > self.max {
+            return Err(errors::AppError::ErrValidation(&error_catalog::INVALID_DESCRIPTION));
+        }
+        Ok(())
+    }
+}
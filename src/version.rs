@@ -0,0 +1,39 @@
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::ContentType;
+use actix_web::{HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Build details captured by `build.rs`.
+#[derive(Debug, Serialize)]
+struct VersionResponse {
+    #[serde(rename = "versao")]
+    version: &'static str,
+    git_sha: &'static str,
+    #[serde(rename = "compilado_em")]
+    built_at: Option<DateTime<Utc>>,
+    features: Vec<&'static str>,
+    rustc: &'static str,
+}
+
+/// `GET /version`: which build this instance is running.
+pub async fn version(_: HttpRequest) -> Result<HttpResponse, actix_web::Error> {
+    let built_at = env!("BUILD_TIMESTAMP")
+        .parse()
+        .ok()
+        .and_then(|secs| DateTime::from_timestamp(secs, 0));
+    let features = env!("BUILD_FEATURES")
+        .split(',')
+        .filter(|feature| !feature.is_empty())
+        .collect();
+
+    let res = serde_json::to_string(&VersionResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("BUILD_GIT_SHA"),
+        built_at,
+        features,
+        rustc: env!("BUILD_RUSTC_VERSION"),
+    })
+    .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
+}
@@ -0,0 +1,90 @@
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use futures_util::future;
+use rinha_servico_rust::json;
+use rinha_servico_rust::schema::{
+    Balance, CreateCustomerTransactionRequest, CreateCustomerTransactionResponse,
+    GetCustomerStatementResponse, StatementTransaction,
+};
+
+use crate::{db, errors};
+
+/// Payload of the transaction requests run through the JSON code paths at
+/// boot.
+const SAMPLE_REQUEST: &str = r#"{"valor": 1000, "tipo": "d", "descricao": "warmup"}"#;
+
+/// What a warm-up pass touched.
+#[derive(Debug)]
+pub struct WarmupReport {
+    pub customers: usize,
+    pub connections: usize,
+    pub elapsed: Duration,
+}
+
+/// Opens every connection the pool allows and runs the hot-path queries on
+/// each one, then reads every customer's statement once so their rows and
+/// index pages are cached by Postgres.
+pub async fn warm_up(
+    pool: &sqlx::Pool<sqlx::Postgres>,
+    max_connections: u32,
+) -> Result<WarmupReport, errors::AppError> {
+    let started = Instant::now();
+    let customer_ids = db::list_customer_ids_db(pool).await?;
+    let probe_customer = customer_ids.first().copied().unwrap_or(1);
+
+    // Holding all connections at once forces the pool to open new ones
+    // instead of reusing the same idle connection.
+    let mut conns = future::try_join_all((0..max_connections).map(|_| pool.acquire())).await?;
+    for conn in conns.iter_mut() {
+        db::warm_connection_db(conn, probe_customer).await?;
+    }
+    let connections = conns.len();
+    drop(conns);
+
+    for id in &customer_ids {
+        db::get_statement_db(pool.clone(), *id as i64).await?;
+    }
+
+    Ok(WarmupReport {
+        customers: customer_ids.len(),
+        connections,
+        elapsed: started.elapsed(),
+    })
+}
+
+/// Runs the request and response bodies of the hot endpoints through the
+/// serializer once, with a full statement, so the first real requests don't pay for
+/// cold code and allocator pages.
+pub fn warm_serialization() -> Result<(), json::Error> {
+    let request: CreateCustomerTransactionRequest =
+        json::from_slice(&mut SAMPLE_REQUEST.as_bytes().to_vec())?;
+    let now = Utc::now();
+
+    json::to_string(&CreateCustomerTransactionResponse {
+        id: Some(1),
+        uuid: Some(uuid::Uuid::nil()),
+        date: Some(now),
+        limit: 100000,
+        total: -(request.value as i64),
+    })?;
+
+    let transactions = (0..10)
+        .map(|_| StatementTransaction {
+            value: Some(request.value),
+            tx_type: Some(request.tx_type.clone()),
+            description: Some(request.description.clone()),
+            date: Some(now),
+        })
+        .collect();
+    json::to_string(&GetCustomerStatementResponse {
+        balance: Balance {
+            total: 0,
+            limit: 100000,
+            date: now.naive_utc(),
+        },
+        last_transactions: transactions,
+    })?;
+
+    Ok(())
+}
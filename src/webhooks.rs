@@ -0,0 +1,341 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::ContentType;
+use actix_web::middleware;
+use actix_web::{web, HttpRequest, HttpResponse};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::types::chrono::NaiveDateTime;
+
+use crate::jobs::{JobError, JobHandler};
+use crate::server::MyData;
+use crate::{admin, config, db, error_catalog, errors};
+
+pub const DELIVERY_JOB_KIND: &str = "webhook_delivery";
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+/// Registers the per-customer subscription management endpoints. They hand
+/// out signing secrets and make this service call arbitrary URLs, so they
+/// sit behind the same token as `/admin`.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/clientes/{id}/webhooks")
+            .wrap(middleware::from_fn(admin::require_token))
+            .service(
+                web::resource("")
+                    .route(web::get().to(list_webhooks))
+                    .route(web::post().to(create_webhook)),
+            )
+            .service(
+                web::resource("/{webhook_id}")
+                    .route(web::patch().to(update_webhook))
+                    .route(web::delete().to(delete_webhook)),
+            )
+            .service(web::resource("/{webhook_id}/segredo").route(web::post().to(rotate_secret))),
+    );
+}
+
+fn generate_secret() -> String {
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    hex::encode(secret)
+}
+
+/// The host of `url` and the addresses it resolves to, when it's an http(s)
+/// URL whose host only resolves to public addresses, so subscriptions can't
+/// point deliveries at the loopback, the private network or cloud metadata
+/// endpoints.
+async fn public_target(url: &str) -> Option<(String, Vec<SocketAddr>)> {
+    let url = reqwest::Url::parse(url).ok()?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+    let host = url.host_str()?;
+    let port = url.port_or_known_default()?;
+    let name = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<_> = tokio::net::lookup_host((name, port)).await.ok()?.collect();
+    if addrs.is_empty() || !addrs.iter().all(|addr| is_public(addr.ip())) {
+        return None;
+    }
+    Some((host.to_string(), addrs))
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local, fc00::/7, and link-local, fe80::/10.
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [first, second, ..] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || first == 0
+        // Carrier-grade NAT, 100.64.0.0/10.
+        || (first == 100 && second & 0xc0 == 64))
+}
+
+/// Value of the signature header: `t=<unix seconds>,v1=<hex HMAC-SHA256>`,
+/// where the MAC covers `<unix seconds>.<body>`. Binding the timestamp lets
+/// receivers reject replayed deliveries.
+pub fn sign(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    // HMAC accepts keys of any length, so this can't fail.
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac key");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("t={},v1={}", timestamp, hex::encode(mac.finalize().into_bytes()))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateWebhookRequest {
+    url: String,
+    /// Transaction types to notify about; all of them when absent.
+    #[serde(default)]
+    tipos: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateWebhookRequest {
+    ativo: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookResponse {
+    id: i64,
+    #[serde(rename = "cliente_id")]
+    customer_id: i32,
+    url: String,
+    #[serde(rename = "ativo")]
+    active: bool,
+    #[serde(rename = "tipos")]
+    tx_types: Option<Vec<String>>,
+    /// Only returned when the secret is created or rotated.
+    #[serde(rename = "segredo", skip_serializing_if = "Option::is_none")]
+    secret: Option<String>,
+    #[serde(rename = "criado_em")]
+    created_at: NaiveDateTime,
+}
+
+impl WebhookResponse {
+    fn with_secret(webhook: &db::Webhook) -> Self {
+        WebhookResponse {
+            secret: Some(webhook.secret.clone()),
+            ..WebhookResponse::from(webhook)
+        }
+    }
+}
+
+impl From<&db::Webhook> for WebhookResponse {
+    fn from(webhook: &db::Webhook) -> Self {
+        WebhookResponse {
+            id: webhook.id,
+            customer_id: webhook.customer_id,
+            url: webhook.url.clone(),
+            active: webhook.active,
+            tx_types: webhook.tx_types.clone(),
+            secret: None,
+            created_at: webhook.created_at,
+        }
+    }
+}
+
+async fn create_webhook(
+    id: web::Path<i32>,
+    create_webhook_data: web::Json<CreateWebhookRequest>,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let request = create_webhook_data.into_inner();
+
+    if public_target(&request.url).await.is_none() {
+        return Err(errors::AppError::ErrValidation(&error_catalog::INVALID_WEBHOOK_URL).into());
+    }
+    if let Some(tipos) = &request.tipos {
+        if tipos.is_empty() || tipos.iter().any(|t| t != "c" && t != "d") {
+            return Err(errors::AppError::ErrValidation(&error_catalog::INVALID_WEBHOOK_TYPES).into());
+        }
+    }
+
+    if !db::customer_exists_db(d.pool.to_owned(), *id).await? {
+        return Err(errors::AppError::ErrCustomerNotFound.into());
+    }
+
+    let webhook = db::create_webhook_db(
+        d.pool.to_owned(),
+        *id,
+        request.url,
+        request.tipos,
+        generate_secret(),
+    )
+    .await?;
+
+    let res = serde_json::to_string(&WebhookResponse::with_secret(&webhook))
+        .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Created().content_type(ContentType::json()).body(res))
+}
+
+async fn list_webhooks(
+    id: web::Path<i32>,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !db::customer_exists_db(d.pool.to_owned(), *id).await? {
+        return Err(errors::AppError::ErrCustomerNotFound.into());
+    }
+
+    let webhooks = db::list_webhooks_db(d.pool.to_owned(), *id).await?;
+
+    let webhooks: Vec<WebhookResponse> = webhooks.iter().map(WebhookResponse::from).collect();
+    let res = serde_json::to_string(&webhooks).map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
+}
+
+async fn update_webhook(
+    path: web::Path<(i32, i64)>,
+    update_webhook_data: web::Json<UpdateWebhookRequest>,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (customer_id, webhook_id) = path.into_inner();
+    let webhook = db::set_webhook_active_db(
+        d.pool.to_owned(),
+        customer_id,
+        webhook_id,
+        update_webhook_data.ativo,
+    )
+    .await?;
+
+    let res = serde_json::to_string(&WebhookResponse::from(&webhook))
+        .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
+}
+
+/// Replaces the signing secret. Deliveries still waiting for a retry are
+/// signed with the new secret.
+async fn rotate_secret(
+    path: web::Path<(i32, i64)>,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (customer_id, webhook_id) = path.into_inner();
+    let webhook =
+        db::rotate_webhook_secret_db(d.pool.to_owned(), customer_id, webhook_id, generate_secret())
+            .await?;
+
+    let res = serde_json::to_string(&WebhookResponse::with_secret(&webhook))
+        .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::json()).body(res))
+}
+
+async fn delete_webhook(
+    path: web::Path<(i32, i64)>,
+    d: web::Data<MyData>,
+    _: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (customer_id, webhook_id) = path.into_inner();
+    db::delete_webhook_db(d.pool.to_owned(), customer_id, webhook_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Payload of a delivery job, built when the transaction is committed.
+#[derive(Debug, Deserialize)]
+struct Delivery {
+    webhook_id: i64,
+    url: String,
+    evento: serde_json::Value,
+}
+
+/// Posts events to subscriber URLs. Any non-2xx answer counts as a failed
+/// attempt; once a delivery runs out of attempts it's copied to
+/// `webhook_dead_letters`, from where it can be requeued.
+pub struct DeliveryHandler {
+    pool: sqlx::Pool<sqlx::Postgres>,
+    timeout: Duration,
+}
+
+impl DeliveryHandler {
+    pub fn new(pool: sqlx::Pool<sqlx::Postgres>, cfg: &config::WebhooksConfig) -> DeliveryHandler {
+        DeliveryHandler {
+            pool,
+            timeout: Duration::from_millis(cfg.timeout_ms),
+        }
+    }
+}
+
+#[async_trait]
+impl JobHandler for DeliveryHandler {
+    async fn run(&self, payload: &serde_json::Value) -> Result<(), JobError> {
+        let delivery = Delivery::deserialize(payload)?;
+
+        let secret = match db::get_webhook_secret_db(&self.pool, delivery.webhook_id).await? {
+            Some(secret) => secret,
+            None => {
+                log::info!("webhook {} was deleted, dropping delivery", delivery.webhook_id);
+                return Ok(());
+            }
+        };
+
+        // Checked again here as the name may resolve elsewhere by now. The
+        // client connects to the addresses checked, so the name can't be
+        // rebound before the request, and follows no redirects, which could
+        // lead anywhere.
+        let Some((host, addrs)) = public_target(&delivery.url).await else {
+            return Err(format!("{} doesn't resolve to a public address", delivery.url).into());
+        };
+        let client = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .resolve_to_addrs(&host, &addrs)
+            .build()?;
+
+        let body = serde_json::to_vec(&delivery.evento)?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        let res = client
+            .post(&delivery.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, sign(&secret, timestamp, &body))
+            .body(body)
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            return Err(format!("{} answered {}", delivery.url, res.status()).into());
+        }
+        Ok(())
+    }
+
+    async fn on_exhausted(&self, job: &db::Job, error: &str) -> Result<(), JobError> {
+        let delivery = Delivery::deserialize(&job.payload.0)?;
+        db::insert_webhook_dead_letter_db(&self.pool, delivery.webhook_id, job, error).await?;
+        log::warn!(
+            "webhook {} delivery moved to dead letters after {} attempts",
+            delivery.webhook_id,
+            job.attempts
+        );
+        Ok(())
+    }
+}